@@ -1,8 +1,49 @@
 use eframe::egui::{
     self, FontData, FontDefinitions, Response, Ui, containers::menu::SubMenuButton,
 };
-use rustendulator_core::{Nes, RunMode};
+use rustendulator_core::{
+    Button, CallKind, CheatKind, ColorPalette, Command, CompareTo, Comparison, EmulationThread,
+    EventKind, FilterChain, GdbStub, Nes, NtscFilter, NtscSettings, RamSearch, Region, RgbaImage,
+    RunAheadMode, RunMode, SCREEN_HEIGHT, SCREEN_WIDTH, ScaleFilter, Speed, StackMismatch,
+    SymbolTable, ValueSize, VideoFormat, Watch, disassemble_instruction,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const REWIND_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+// Battery saves are also written on eject and exit, this only limits loss on a crash
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+const SAVES_DIRECTORY: &str = "saves";
+const SCREENSHOTS_DIRECTORY: &str = "screenshots";
+const RECORDINGS_DIRECTORY: &str = "recordings";
+const GIF_REPLAY_SECONDS: u32 = 10;
+// Speed while the fast-forward key is held
+const FAST_FORWARD_SPEED: Speed = Speed::Percent(400);
+const SPEEDS: [Speed; 6] = [
+    Speed::Percent(25),
+    Speed::Percent(50),
+    Speed::NORMAL,
+    Speed::Percent(200),
+    Speed::Percent(400),
+    Speed::Uncapped,
+];
+// Local only, connect with "target remote :6502"
+const GDB_ADDRESS: &str = "127.0.0.1:6502";
+
+// Keyboard layout for controller 1
+const CONTROLLER_KEYS: [(egui::Key, Button); 8] = [
+    (egui::Key::X, Button::A),
+    (egui::Key::Z, Button::B),
+    (egui::Key::Space, Button::Select),
+    (egui::Key::Enter, Button::Start),
+    (egui::Key::ArrowUp, Button::Up),
+    (egui::Key::ArrowDown, Button::Down),
+    (egui::Key::ArrowLeft, Button::Left),
+    (egui::Key::ArrowRight, Button::Right),
+];
 
 fn pixel_font_family() -> egui::FontFamily {
     egui::FontFamily::Name("pixel".into())
@@ -49,27 +90,644 @@ fn main() -> Result<(), eframe::Error> {
         },
         Box::new(|cc| {
             load_fonts(&cc.egui_ctx);
-            Ok(Box::new(Rustendulator::default()))
+            let mut app = Rustendulator::new(&cc.egui_ctx);
+            if let Some(path) = std::env::args_os().nth(1) {
+                app.open_rom(PathBuf::from(path));
+            }
+            Ok(Box::new(app))
         }),
     )
 }
 
+#[derive(Copy, Clone, PartialEq)]
+enum MemorySpace {
+    Cpu,
+    Ppu,
+    Oam,
+}
+
+impl MemorySpace {
+    fn size(self) -> usize {
+        match self {
+            MemorySpace::Cpu => 0x10000,
+            MemorySpace::Ppu => 0x4000,
+            MemorySpace::Oam => 0x100,
+        }
+    }
+
+    fn peek(self, nes: &Nes, address: usize) -> u8 {
+        match self {
+            MemorySpace::Cpu => nes.peek(address as u16),
+            MemorySpace::Ppu => nes.peek_ppu(address as u16),
+            MemorySpace::Oam => nes.peek_oam(address as u8),
+        }
+    }
+
+    fn poke(self, nes: &mut Nes, address: usize, value: u8) {
+        match self {
+            MemorySpace::Cpu => nes.poke(address as u16, value),
+            MemorySpace::Ppu => nes.poke_ppu(address as u16, value),
+            MemorySpace::Oam => nes.poke_oam(address as u8, value),
+        }
+    }
+}
+
+const HEX_EDITOR_COLUMNS: usize = 16;
+// Updates a changed byte stays highlighted for
+const HEX_EDITOR_HIGHLIGHT: u8 = 60;
+
+struct HexEditor {
+    space: MemorySpace,
+    // Values at the previous update and how long each byte stays highlighted
+    snapshot: Vec<u8>,
+    highlight: Vec<u8>,
+    selected: Option<usize>,
+    // First hex digit typed into the selected byte
+    pending_nibble: Option<u8>,
+    goto_address: String,
+    scroll_to: Option<usize>,
+}
+
+impl HexEditor {
+    fn new() -> Self {
+        Self {
+            space: MemorySpace::Cpu,
+            snapshot: Vec::new(),
+            highlight: Vec::new(),
+            selected: None,
+            pending_nibble: None,
+            goto_address: String::new(),
+            scroll_to: None,
+        }
+    }
+
+    fn set_space(&mut self, space: MemorySpace) {
+        self.space = space;
+        self.snapshot.clear();
+        self.highlight.clear();
+        self.selected = None;
+        self.pending_nibble = None;
+    }
+
+    fn refresh(&mut self, nes: &Nes) {
+        let size = self.space.size();
+        let first_refresh = self.snapshot.len() != size;
+        self.snapshot.resize(size, 0);
+        self.highlight.resize(size, 0);
+
+        for address in 0..size {
+            let value = self.space.peek(nes, address);
+            if !first_refresh && value != self.snapshot[address] {
+                self.highlight[address] = HEX_EDITOR_HIGHLIGHT;
+            } else {
+                self.highlight[address] = self.highlight[address].saturating_sub(1);
+            }
+            self.snapshot[address] = value;
+        }
+    }
+}
+
+const DISASSEMBLY_LINES: usize = 40;
+
+struct DisassemblyView {
+    // Symbols from .dbg/.nl/.mlb files and the user's own, shown combined with the user's on top
+    imported_symbols: SymbolTable,
+    user_symbols: SymbolTable,
+    symbols: SymbolTable,
+    follow_pc: bool,
+    start: u16,
+    goto_address: String,
+    selected: Option<u16>,
+    edit_label: String,
+    edit_comment: String,
+    import_path: String,
+}
+
+impl DisassemblyView {
+    fn new() -> Self {
+        Self {
+            imported_symbols: SymbolTable::new(),
+            user_symbols: SymbolTable::new(),
+            symbols: SymbolTable::new(),
+            follow_pc: true,
+            start: 0,
+            goto_address: String::new(),
+            selected: None,
+            edit_label: String::new(),
+            edit_comment: String::new(),
+            import_path: String::new(),
+        }
+    }
+
+    fn rebuild_symbols(&mut self) {
+        self.symbols = self.imported_symbols.clone();
+        self.symbols.overlay(&self.user_symbols);
+    }
+
+    fn clear_symbols(&mut self) {
+        self.imported_symbols.clear();
+        self.user_symbols.clear();
+        self.symbols.clear();
+        self.selected = None;
+    }
+
+    // Picks the format from the extension. FCEUX bank files are named game.nes.0.nl, the
+    // RAM file game.nes.ram.nl.
+    fn import_file(&mut self, path: &Path) -> Result<usize, &'static str> {
+        let text = fs::read_to_string(path).map_err(|_| "Could not read symbol file")?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+        let count = match extension.to_ascii_lowercase().as_str() {
+            "dbg" => self.imported_symbols.import_dbg(&text)?,
+            "mlb" => self.imported_symbols.import_mlb(&text),
+            "nl" => {
+                let bank = path
+                    .file_stem()
+                    .and_then(|stem| Path::new(stem).extension())
+                    .and_then(|bank| bank.to_str())
+                    .and_then(|bank| usize::from_str_radix(bank, 16).ok());
+                self.imported_symbols.import_nl(&text, bank)
+            }
+            _ => return Err("Symbol files are .dbg, .nl or .mlb"),
+        };
+        self.rebuild_symbols();
+        Ok(count)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum SaveLocation {
+    NextToRom,
+    SavesDirectory,
+}
+
 struct Rustendulator {
-    nes: Nes,
+    emulator: EmulationThread,
     show_left_panel: bool,
     show_right_panel: bool,
+    rom_path: Option<PathBuf>,
+    save_location: SaveLocation,
+    // Save RAM as last written to disk, so unchanged saves are not rewritten
+    saved_ram: Vec<u8>,
+    last_autosave: Instant,
+    // Frames run but not drawn between two drawn ones
+    frame_skip: u32,
+    // Number of the last published frame drawn
+    rendered_frame: u64,
+    status: String,
+    show_cheats: bool,
+    cheat_code: String,
+    cheat_name: String,
+    cheat_error: String,
+    show_ram_search: bool,
+    ram_search: RamSearch,
+    search_comparison: Comparison,
+    search_against_value: bool,
+    search_value: String,
+    search_hex: bool,
+    show_ram_watch: bool,
+    watches: Vec<Watch>,
+    show_hex_editor: bool,
+    hex_editor: HexEditor,
+    show_disassembly: bool,
+    disassembly: DisassemblyView,
+    show_code_data_logger: bool,
+    show_profiler: bool,
+    show_call_stack: bool,
+    show_event_viewer: bool,
+    gdb: Option<GdbStub>,
+    screen: Option<egui::TextureHandle>,
+    ntsc_filter: NtscFilter,
+    ntsc_enabled: bool,
+    show_ntsc_settings: bool,
+    filter_chain: FilterChain,
+    // Screenshots as shown on screen rather than the raw 256x240 frame
+    screenshot_filtered: bool,
+    // Keeps the last GIF_REPLAY_SECONDS ready to save as a GIF
+    gif_replay: bool,
+    gif_clip: bool,
 }
 
-impl Default for Rustendulator {
-    fn default() -> Self {
+impl Rustendulator {
+    fn new(ctx: &egui::Context) -> Self {
+        let mut nes = Nes::new();
+        nes.enable_rewind(REWIND_MEMORY_BUDGET);
+        // Frames come in at the emulator's pace, each one repaints
+        let repaint_ctx = ctx.clone();
+        let emulator = EmulationThread::spawn(nes, move || repaint_ctx.request_repaint());
+
         Self {
-            nes: Nes::new(),
+            emulator,
             show_left_panel: true,
             show_right_panel: true,
+            rom_path: None,
+            save_location: SaveLocation::NextToRom,
+            saved_ram: Vec::new(),
+            last_autosave: Instant::now(),
+            frame_skip: 0,
+            rendered_frame: 0,
+            status: String::new(),
+            show_cheats: false,
+            cheat_code: String::new(),
+            cheat_name: String::new(),
+            cheat_error: String::new(),
+            show_ram_search: false,
+            ram_search: RamSearch::new(ValueSize::Byte, false, false),
+            search_comparison: Comparison::NotEqual,
+            search_against_value: false,
+            search_value: String::new(),
+            search_hex: true,
+            show_ram_watch: false,
+            watches: Vec::new(),
+            show_hex_editor: false,
+            hex_editor: HexEditor::new(),
+            show_disassembly: false,
+            disassembly: DisassemblyView::new(),
+            show_code_data_logger: false,
+            show_profiler: false,
+            show_call_stack: false,
+            show_event_viewer: false,
+            gdb: None,
+            screen: None,
+            ntsc_filter: NtscFilter::default(),
+            ntsc_enabled: false,
+            show_ntsc_settings: false,
+            filter_chain: FilterChain::new(),
+            screenshot_filtered: false,
+            gif_replay: false,
+            gif_clip: false,
+        }
+    }
+}
+
+// Cartridge and battery save handling
+impl Rustendulator {
+    fn open_rom(&mut self, path: PathBuf) {
+        self.eject_rom();
+
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(_) => {
+                self.status = "Could not read ROM file".to_owned();
+                return;
+            }
+        };
+        self.emulator.send(Command::LoadRom(data));
+        if let Some(error) = self.emulator.take_error() {
+            self.status = error.to_owned();
+            return;
+        }
+
+        self.rom_path = Some(path);
+        self.status.clear();
+        self.read_save_ram();
+        self.read_cheats();
+        self.read_symbols();
+        self.emulator.send(Command::PowerOn);
+        self.emulator.send(Command::SetRunMode(RunMode::Running));
+    }
+
+    fn eject_rom(&mut self) {
+        if self.emulator.lock().is_recording() {
+            self.toggle_recording();
+        }
+        if self.emulator.lock().has_cartridge() {
+            self.write_save_ram();
+            self.emulator.lock().eject_cartridge();
+        }
+        self.rom_path = None;
+        self.saved_ram.clear();
+        self.disassembly.clear_symbols();
+    }
+
+    // Per-ROM files (.sav, .cht, .mlb, .cdl) go next to the ROM or into the saves directory
+    fn rom_file_path(&self, extension: &str) -> Option<PathBuf> {
+        let rom_path = self.rom_path.as_ref()?;
+        match self.save_location {
+            SaveLocation::NextToRom => Some(rom_path.with_extension(extension)),
+            SaveLocation::SavesDirectory => {
+                let name = rom_path.file_stem()?.to_string_lossy();
+                Some(Path::new(SAVES_DIRECTORY).join(format!("{}.{}", name, extension)))
+            }
+        }
+    }
+
+    // Palettes are not per-ROM, a dropped .pal applies until another replaces it
+    fn load_palette(&mut self, path: &Path) {
+        let result = fs::read(path)
+            .map_err(|_| "Could not read palette file")
+            .and_then(|data| ColorPalette::from_pal(&data));
+        self.status = match result {
+            Ok(palette) => {
+                self.emulator.lock().set_palette(palette);
+                "Palette loaded".to_owned()
+            }
+            Err(error) => error.to_owned(),
+        };
+    }
+
+    fn read_save_ram(&mut self) {
+        if !self.emulator.lock().has_save_ram() {
+            return;
+        }
+        let Some(path) = self.rom_file_path("sav") else {
+            return;
+        };
+        // No file yet is normal for a game that has never saved
+        let Ok(data) = fs::read(&path) else {
+            return;
+        };
+
+        match self.emulator.lock().load_save_ram(&data) {
+            Ok(()) => self.saved_ram = data,
+            Err(error) => self.status = error.to_owned(),
+        }
+    }
+
+    fn write_save_ram(&mut self) {
+        let Some(path) = self.rom_file_path("sav") else {
+            return;
+        };
+        let nes = self.emulator.lock();
+        let Some(data) = nes.save_ram() else {
+            return;
+        };
+        if data == self.saved_ram.as_slice() {
+            return;
+        }
+
+        if let Some(directory) = path.parent() {
+            let _ = fs::create_dir_all(directory);
+        }
+        match fs::write(&path, data) {
+            Ok(()) => {
+                self.saved_ram.clear();
+                self.saved_ram.extend_from_slice(data);
+            }
+            Err(_) => self.status = "Could not write save file".to_owned(),
+        }
+    }
+
+    fn read_cheats(&mut self) {
+        let Some(text) = self
+            .rom_file_path("cht")
+            .and_then(|path| fs::read_to_string(path).ok())
+        else {
+            return;
+        };
+
+        if let Err(error) = self.emulator.lock().import_cheats(&text) {
+            self.status = error.to_owned();
+        }
+    }
+
+    fn write_cheats(&mut self) {
+        let Some(path) = self.rom_file_path("cht") else {
+            return;
+        };
+
+        let result = if self.emulator.lock().cheats().is_empty() {
+            match fs::remove_file(&path) {
+                Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+                _ => Ok(()),
+            }
+        } else {
+            if let Some(directory) = path.parent() {
+                let _ = fs::create_dir_all(directory);
+            }
+            fs::write(&path, self.emulator.lock().export_cheats())
+        };
+        if result.is_err() {
+            self.status = "Could not write cheat file".to_owned();
+        }
+    }
+
+    // Assembler and FCEUX symbol files are picked up from next to the ROM, then the user's
+    // labels from the .mlb written by the disassembly window
+    fn read_symbols(&mut self) {
+        let Some(rom_path) = self.rom_path.clone() else {
+            return;
+        };
+
+        let mut candidates = vec![rom_path.with_extension("dbg")];
+        let mut nl_path = rom_path.clone().into_os_string();
+        nl_path.push(".ram.nl");
+        candidates.push(nl_path.into());
+        for bank in 0..0x100 {
+            let mut nl_path = rom_path.clone().into_os_string();
+            nl_path.push(format!(".{:X}.nl", bank));
+            candidates.push(nl_path.into());
+        }
+
+        for path in candidates.iter().filter(|path| path.exists()) {
+            if let Err(error) = self.disassembly.import_file(path) {
+                self.status = error.to_owned();
+            }
+        }
+
+        if let Some(text) = self
+            .rom_file_path("mlb")
+            .and_then(|path| fs::read_to_string(path).ok())
+        {
+            self.disassembly.user_symbols.import_mlb(&text);
+            self.disassembly.rebuild_symbols();
+        }
+    }
+
+    fn read_code_data_log(&mut self) {
+        let Some(data) = self
+            .rom_file_path("cdl")
+            .and_then(|path| fs::read(path).ok())
+        else {
+            self.status = "No code/data log for this ROM".to_owned();
+            return;
+        };
+
+        if let Err(error) = self.emulator.lock().import_code_data_log(&data) {
+            self.status = error.to_owned();
+        }
+    }
+
+    fn write_code_data_log(&mut self) {
+        let nes = self.emulator.lock();
+        let (Some(path), Some(log)) = (self.rom_file_path("cdl"), nes.code_data_log()) else {
+            return;
+        };
+
+        if let Some(directory) = path.parent() {
+            let _ = fs::create_dir_all(directory);
+        }
+        if fs::write(&path, log.export()).is_err() {
+            self.status = "Could not write code/data log".to_owned();
+        }
+    }
+
+    fn write_profile(&mut self, extension: &str) {
+        let Some(path) = self.rom_file_path(extension) else {
+            self.status = "No ROM loaded".to_owned();
+            return;
+        };
+
+        let symbols = Some(&self.disassembly.symbols);
+        let text = if extension.ends_with("csv") {
+            self.emulator.lock().export_profile_csv(symbols)
+        } else {
+            self.emulator.lock().export_profile_text(symbols)
+        };
+        if let Some(directory) = path.parent() {
+            let _ = fs::create_dir_all(directory);
+        }
+        self.status = match fs::write(&path, text) {
+            Ok(()) => format!("Profile written to {}", path.display()),
+            Err(_) => "Could not write profile".to_owned(),
+        };
+    }
+
+    // Named after the ROM and frame, numbered when a frame is taken more than once
+    fn write_screenshot(&mut self) {
+        let Some(rom_name) = self.rom_path.as_ref().and_then(|path| path.file_stem()) else {
+            self.status = "No ROM loaded".to_owned();
+            return;
+        };
+        let rom_name = rom_name.to_string_lossy().into_owned();
+
+        let mut screenshot = self.emulator.lock().screenshot();
+        screenshot.set_rom_name(&rom_name);
+        if self.screenshot_filtered {
+            screenshot.set_image(self.filtered_frame(self.is_running()));
+        }
+        let data = match screenshot.to_png() {
+            Ok(data) => data,
+            Err(error) => {
+                self.status = error.to_owned();
+                return;
+            }
+        };
+
+        let base = format!("{}-{}", rom_name, screenshot.frame());
+        let path = unused_path(SCREENSHOTS_DIRECTORY, &base, "png");
+        self.status = match fs::write(&path, data) {
+            Ok(()) => format!("Screenshot written to {}", path.display()),
+            Err(_) => "Could not write screenshot".to_owned(),
+        };
+    }
+
+    // Unfiltered ZMBV video with a WAV alongside, named like screenshots after the start frame
+    fn toggle_recording(&mut self) {
+        let mut nes = self.emulator.lock();
+        if nes.is_recording() {
+            self.status = match nes.stop_recording() {
+                Ok(frames) => format!("Recorded {} frames", frames),
+                Err(error) => error.to_owned(),
+            };
+            return;
+        }
+
+        let Some(rom_name) = self.rom_path.as_ref().and_then(|path| path.file_stem()) else {
+            self.status = "No ROM loaded".to_owned();
+            return;
+        };
+        let base = format!("{}-{}", rom_name.to_string_lossy(), nes.frame_count());
+        let video_path = unused_path(RECORDINGS_DIRECTORY, &base, "avi");
+        let audio_path = video_path.with_extension("wav");
+        self.status = match nes.start_recording(&video_path, VideoFormat::Avi, Some(&audio_path)) {
+            Ok(()) => format!("Recording to {}", video_path.display()),
+            Err(error) => error.to_owned(),
+        };
+    }
+
+    // A clip runs until toggled off and is saved then, in place of the replay buffer
+    fn toggle_gif_clip(&mut self) {
+        if self.gif_clip {
+            self.write_gif();
+            self.gif_clip = false;
+            self.restart_gif_replay();
+        } else {
+            self.emulator.lock().start_gif_capture(None);
+            self.gif_clip = true;
+            self.status = "Recording GIF clip".to_owned();
+        }
+    }
+
+    fn restart_gif_replay(&mut self) {
+        if self.gif_replay {
+            self.emulator
+                .lock()
+                .start_gif_capture(Some(GIF_REPLAY_SECONDS));
+        } else {
+            self.emulator.lock().stop_gif_capture();
+        }
+    }
+
+    fn write_gif(&mut self) {
+        let Some(rom_name) = self.rom_path.as_ref().and_then(|path| path.file_stem()) else {
+            self.status = "No ROM loaded".to_owned();
+            return;
+        };
+        let (frame, gif) = {
+            let nes = self.emulator.lock();
+            (nes.frame_count(), nes.gif())
+        };
+        let base = format!("{}-{}", rom_name.to_string_lossy(), frame);
+        let path = unused_path(RECORDINGS_DIRECTORY, &base, "gif");
+        self.status = match gif {
+            Ok(data) => match fs::write(&path, data) {
+                Ok(()) => format!("GIF written to {}", path.display()),
+                Err(_) => "Could not write GIF".to_owned(),
+            },
+            Err(error) => error.to_owned(),
+        };
+    }
+
+    fn write_symbols(&mut self) {
+        let Some(path) = self.rom_file_path("mlb") else {
+            return;
+        };
+
+        let result = if self.disassembly.user_symbols.is_empty() {
+            match fs::remove_file(&path) {
+                Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+                _ => Ok(()),
+            }
+        } else {
+            if let Some(directory) = path.parent() {
+                let _ = fs::create_dir_all(directory);
+            }
+            fs::write(&path, self.disassembly.user_symbols.export_mlb())
+        };
+        if result.is_err() {
+            self.status = "Could not write label file".to_owned();
         }
     }
 }
 
+// Emulation speed
+impl Rustendulator {
+    // Uncapped until toggled again, back to normal speed then
+    fn toggle_turbo(&mut self) {
+        let mut nes = self.emulator.lock();
+        let speed = if nes.speed() == Speed::Uncapped {
+            Speed::NORMAL
+        } else {
+            Speed::Uncapped
+        };
+        nes.set_speed(speed);
+    }
+}
+
+// `<base>.<extension>` in the directory, created if needed, numbered when already taken
+fn unused_path(directory: &str, base: &str, extension: &str) -> PathBuf {
+    let directory = Path::new(directory);
+    let _ = fs::create_dir_all(directory);
+    let mut path = directory.join(format!("{}.{}", base, extension));
+    let mut count = 1;
+    while path.exists() {
+        count += 1;
+        path = directory.join(format!("{}-{}.{}", base, count, extension));
+    }
+    path
+}
+
 // Visual Components
 impl Rustendulator {
     fn power_led(&self, ui: &mut Ui, on: bool) {
@@ -102,6 +760,981 @@ impl Rustendulator {
     }
 }
 
+// Two's complement digits in hex so signed values keep their width
+fn format_value(value: i32, size: ValueSize, hex: bool) -> String {
+    match (hex, size) {
+        (false, _) => value.to_string(),
+        (true, ValueSize::Byte) => format!("{:02X}", value as u8),
+        (true, ValueSize::Word) => format!("{:04X}", value as u16),
+    }
+}
+
+fn parse_value(text: &str, hex: bool) -> Option<i32> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if hex {
+        i32::from_str_radix(digits.trim_start_matches('$'), 16).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+const COMPARISONS: [(Comparison, &str); 6] = [
+    (Comparison::Equal, "="),
+    (Comparison::NotEqual, "!="),
+    (Comparison::Less, "<"),
+    (Comparison::Greater, ">"),
+    (Comparison::LessOrEqual, "<="),
+    (Comparison::GreaterOrEqual, ">="),
+];
+
+// Tool Windows
+impl Rustendulator {
+    fn cheats_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_cheats;
+        let mut changed = false;
+
+        egui::Window::new("Cheats")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Code");
+                    ui.add(egui::TextEdit::singleline(&mut self.cheat_code).desired_width(100.0));
+                    ui.label("Name");
+                    ui.add(egui::TextEdit::singleline(&mut self.cheat_name).desired_width(160.0));
+                    if ui.button("Add").clicked() {
+                        match self
+                            .emulator
+                            .lock()
+                            .add_cheat(&self.cheat_code, &self.cheat_name)
+                        {
+                            Ok(_) => {
+                                self.cheat_code.clear();
+                                self.cheat_name.clear();
+                                self.cheat_error.clear();
+                                changed = true;
+                            }
+                            Err(error) => self.cheat_error = error.to_owned(),
+                        }
+                    }
+                });
+                ui.label("Game Genie, AAAAVV RAM freeze, or AAAA:VV[:CC] patch");
+                if !self.cheat_error.is_empty() {
+                    ui.colored_label(egui::Color32::from_rgb(220, 40, 40), &self.cheat_error);
+                }
+                ui.separator();
+
+                // Changes are collected first, the list is borrowed from the core while drawn
+                let mut toggled = None;
+                let mut renamed = None;
+                let mut removed = None;
+
+                egui::Grid::new("cheat_list").striped(true).show(ui, |ui| {
+                    for cheat in self.emulator.lock().cheats() {
+                        let mut enabled = cheat.is_enabled();
+                        if ui.checkbox(&mut enabled, "").changed() {
+                            toggled = Some((cheat.id(), enabled));
+                        }
+
+                        ui.monospace(cheat.code());
+
+                        let mut name = cheat.name().to_owned();
+                        if ui.text_edit_singleline(&mut name).changed() {
+                            renamed = Some((cheat.id(), name));
+                        }
+
+                        let target = match cheat.compare() {
+                            Some(compare) => format!(
+                                "${:04X} = {:02X} if {:02X}",
+                                cheat.address(),
+                                cheat.value(),
+                                compare
+                            ),
+                            None => format!("${:04X} = {:02X}", cheat.address(), cheat.value()),
+                        };
+                        ui.label(match cheat.kind() {
+                            CheatKind::GameGenie => "Game Genie",
+                            CheatKind::RamFreeze => "Freeze",
+                            CheatKind::Patch => "Patch",
+                        });
+                        ui.monospace(target);
+
+                        if ui.button("Remove").clicked() {
+                            removed = Some(cheat.id());
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                if let Some((id, enabled)) = toggled {
+                    self.emulator.lock().set_cheat_enabled(id, enabled);
+                    changed = true;
+                }
+                if let Some((id, name)) = renamed {
+                    self.emulator.lock().rename_cheat(id, &name);
+                    changed = true;
+                }
+                if let Some(id) = removed {
+                    self.emulator.lock().remove_cheat(id);
+                    changed = true;
+                }
+            });
+
+        self.show_cheats = open;
+        if changed {
+            self.write_cheats();
+        }
+    }
+
+    fn ram_search_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_ram_search;
+
+        egui::Window::new("RAM Search")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let nes = self.emulator.lock();
+                let mut size = self.ram_search.size();
+                let mut signed = self.ram_search.is_signed();
+                let mut include_prg_ram = self.ram_search.includes_prg_ram();
+
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut size, ValueSize::Byte, "8-bit");
+                    ui.radio_value(&mut size, ValueSize::Word, "16-bit");
+                    ui.checkbox(&mut signed, "Signed");
+                    ui.checkbox(&mut self.search_hex, "Hex");
+                    ui.checkbox(&mut include_prg_ram, "PRG-RAM");
+                });
+                if size != self.ram_search.size() || signed != self.ram_search.is_signed() {
+                    self.ram_search.set_format(&nes, size, signed);
+                }
+                if include_prg_ram != self.ram_search.includes_prg_ram() {
+                    self.ram_search.set_include_prg_ram(&nes, include_prg_ram);
+                }
+
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("search_comparison")
+                        .width(48.0)
+                        .selected_text(
+                            COMPARISONS
+                                .iter()
+                                .find(|(c, _)| *c == self.search_comparison)
+                                .map_or("", |(_, label)| *label),
+                        )
+                        .show_ui(ui, |ui| {
+                            for (comparison, label) in COMPARISONS {
+                                ui.selectable_value(&mut self.search_comparison, comparison, label);
+                            }
+                        });
+                    ui.radio_value(&mut self.search_against_value, false, "Previous");
+                    ui.radio_value(&mut self.search_against_value, true, "Value");
+                    ui.add_enabled(
+                        self.search_against_value,
+                        egui::TextEdit::singleline(&mut self.search_value).desired_width(64.0),
+                    );
+                });
+
+                ui.horizontal(|ui| {
+                    if ui.button("Search").clicked() {
+                        let compare_to = if self.search_against_value {
+                            parse_value(&self.search_value, self.search_hex).map(CompareTo::Value)
+                        } else {
+                            Some(CompareTo::Previous)
+                        };
+                        if let Some(compare_to) = compare_to {
+                            self.ram_search
+                                .filter(&nes, self.search_comparison, compare_to);
+                        }
+                    }
+                    if ui.button("Reset").clicked() {
+                        self.ram_search.reset(&nes);
+                    }
+                    if ui.button("Snapshot").clicked() {
+                        self.ram_search.take_snapshot(&nes);
+                    }
+                    ui.label(format!("{} candidates", self.ram_search.candidates().len()));
+                });
+                ui.separator();
+
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                let candidates = self.ram_search.candidates().len();
+                let mut pinned = None;
+
+                egui::ScrollArea::vertical().show_rows(ui, row_height, candidates, |ui, rows| {
+                    egui::Grid::new("search_candidates")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for index in rows {
+                                let address = self.ram_search.candidates()[index];
+                                let current = self.ram_search.current_value(&nes, address);
+                                let previous =
+                                    self.ram_search.previous_value(index).unwrap_or(current);
+
+                                ui.monospace(format!("${:04X}", address));
+                                ui.monospace(format_value(current, size, self.search_hex));
+                                ui.monospace(format_value(previous, size, self.search_hex));
+                                if ui.small_button("Watch").clicked() {
+                                    pinned = Some(address);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+
+                if let Some(address) = pinned {
+                    self.watches.push(Watch {
+                        address,
+                        size,
+                        signed,
+                        label: String::new(),
+                    });
+                    self.show_ram_watch = true;
+                }
+            });
+
+        self.show_ram_search = open;
+    }
+
+    fn ram_watch_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_ram_watch;
+
+        egui::Window::new("RAM Watch")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let nes = self.emulator.lock();
+                let mut removed = None;
+
+                egui::Grid::new("ram_watches").striped(true).show(ui, |ui| {
+                    for (index, watch) in self.watches.iter_mut().enumerate() {
+                        ui.monospace(format!("${:04X}", watch.address));
+                        ui.add(egui::TextEdit::singleline(&mut watch.label).desired_width(120.0));
+
+                        let value = watch.value(&nes);
+                        ui.monospace(format_value(value, watch.size, true));
+                        ui.monospace(value.to_string());
+
+                        if ui.small_button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                if let Some(index) = removed {
+                    self.watches.remove(index);
+                }
+                if self.watches.is_empty() {
+                    ui.label("Pin addresses from RAM Search");
+                }
+            });
+
+        self.show_ram_watch = open;
+    }
+
+    fn hex_editor_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_hex_editor;
+        if !open {
+            return;
+        }
+
+        let mut nes = self.emulator.lock();
+        self.hex_editor.refresh(&nes);
+
+        // Typed hex digits edit the selected byte, high nibble first
+        if let Some(address) = self.hex_editor.selected
+            && !ctx.wants_keyboard_input()
+        {
+            let typed: Vec<u8> = ctx.input(|i| {
+                i.events
+                    .iter()
+                    .filter_map(|event| match event {
+                        egui::Event::Text(text) => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .flat_map(str::chars)
+                    .filter_map(|c| c.to_digit(16))
+                    .map(|digit| digit as u8)
+                    .collect()
+            });
+            let editor = &mut self.hex_editor;
+            let mut address = address;
+            for digit in typed {
+                match editor.pending_nibble.take() {
+                    None => editor.pending_nibble = Some(digit),
+                    Some(high) => {
+                        editor.space.poke(&mut nes, address, (high << 4) | digit);
+                        address = (address + 1) % editor.space.size();
+                    }
+                }
+            }
+            editor.selected = Some(address);
+
+            if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                editor.selected = None;
+                editor.pending_nibble = None;
+            }
+        }
+
+        egui::Window::new("Hex Editor")
+            .open(&mut open)
+            .resizable(true)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                let editor = &mut self.hex_editor;
+
+                ui.horizontal(|ui| {
+                    let mut space = editor.space;
+                    ui.radio_value(&mut space, MemorySpace::Cpu, "CPU");
+                    ui.radio_value(&mut space, MemorySpace::Ppu, "PPU");
+                    ui.radio_value(&mut space, MemorySpace::Oam, "OAM");
+                    if space != editor.space {
+                        editor.set_space(space);
+                        editor.refresh(&nes);
+                    }
+
+                    ui.separator();
+                    ui.label("Go to");
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut editor.goto_address).desired_width(48.0),
+                    );
+                    let submitted =
+                        response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Go").clicked() || submitted {
+                        let text = editor.goto_address.trim().trim_start_matches('$');
+                        if let Ok(address) = usize::from_str_radix(text, 16)
+                            && address < editor.space.size()
+                        {
+                            editor.selected = Some(address);
+                            editor.pending_nibble = None;
+                            editor.scroll_to = Some(address / HEX_EDITOR_COLUMNS);
+                        }
+                    }
+                });
+                ui.label("Click a byte and type hex digits to edit, Esc to deselect");
+                ui.separator();
+
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                let rows = editor.space.size() / HEX_EDITOR_COLUMNS;
+                let mut scroll_area = egui::ScrollArea::vertical().auto_shrink(false);
+                if let Some(row) = editor.scroll_to.take() {
+                    let spacing = ui.spacing().item_spacing.y;
+                    scroll_area =
+                        scroll_area.vertical_scroll_offset(row as f32 * (row_height + spacing));
+                }
+
+                scroll_area.show_rows(ui, row_height, rows, |ui, visible_rows| {
+                    for row in visible_rows {
+                        ui.horizontal(|ui| {
+                            ui.spacing_mut().item_spacing.x = 6.0;
+                            let start = row * HEX_EDITOR_COLUMNS;
+                            ui.monospace(format!("{:04X}", start));
+                            ui.add_space(6.0);
+
+                            for address in start..start + HEX_EDITOR_COLUMNS {
+                                let mut text = egui::RichText::new(format!(
+                                    "{:02X}",
+                                    editor.snapshot[address]
+                                ))
+                                .monospace();
+                                if editor.selected == Some(address) {
+                                    text =
+                                        text.background_color(egui::Color32::from_rgb(60, 60, 140));
+                                } else if editor.highlight[address] > 0 {
+                                    text = text.color(egui::Color32::from_rgb(220, 40, 40));
+                                }
+
+                                let byte =
+                                    ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                                if byte.clicked() {
+                                    editor.selected = Some(address);
+                                    editor.pending_nibble = None;
+                                }
+                            }
+                        });
+                    }
+                });
+            });
+
+        self.show_hex_editor = open;
+        // Keep refreshing so highlights fade while paused
+        ctx.request_repaint_after(Duration::from_millis(50));
+    }
+
+    fn code_data_logger_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_code_data_logger;
+
+        egui::Window::new("Code/Data Logger")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if self.emulator.lock().is_code_data_logging() {
+                        if ui.button("Pause").clicked() {
+                            self.emulator.lock().pause_code_data_log();
+                        }
+                    } else if ui.button("Start").clicked()
+                        && let Err(error) = self.emulator.lock().start_code_data_log()
+                    {
+                        self.status = error.to_owned();
+                    }
+                    if ui.button("Reset").clicked() {
+                        self.emulator.lock().reset_code_data_log();
+                    }
+                    ui.label(if self.emulator.lock().is_code_data_logging() {
+                        "Logging"
+                    } else {
+                        "Paused"
+                    });
+                });
+                ui.separator();
+
+                match self.emulator.lock().code_data_log() {
+                    Some(log) => {
+                        let percent = |count: usize, total: usize| {
+                            if total == 0 {
+                                0.0
+                            } else {
+                                count as f32 * 100.0 / total as f32
+                            }
+                        };
+                        let prg_size = log.prg().len();
+                        ui.label(format!(
+                            "PRG logged: {} of {} bytes ({:.1}%)",
+                            log.logged_prg_bytes(),
+                            prg_size,
+                            percent(log.logged_prg_bytes(), prg_size)
+                        ));
+                        ui.label(format!("Code: {} bytes", log.code_bytes()));
+                        ui.label(format!("Data: {} bytes", log.data_bytes()));
+                        if !log.chr().is_empty() {
+                            ui.label(format!(
+                                "CHR logged: {} of {} bytes",
+                                log.logged_chr_bytes(),
+                                log.chr().len()
+                            ));
+                        }
+                    }
+                    None => {
+                        ui.label("No log yet");
+                    }
+                }
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Save .cdl").clicked() {
+                        self.write_code_data_log();
+                    }
+                    if ui.button("Load .cdl").clicked() {
+                        self.read_code_data_log();
+                    }
+                });
+            });
+
+        self.show_code_data_logger = open;
+    }
+
+    fn profiler_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_profiler;
+        if !open {
+            return;
+        }
+
+        egui::Window::new("Profiler")
+            .open(&mut open)
+            .resizable(true)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if self.emulator.lock().is_profiling() {
+                        if ui.button("Stop").clicked() {
+                            self.emulator.lock().stop_profiler();
+                        }
+                    } else if ui.button("Start").clicked() {
+                        self.emulator.lock().start_profiler();
+                    }
+                    if ui.button("Reset").clicked() {
+                        self.emulator.lock().reset_profiler();
+                    }
+                    ui.separator();
+                    for (label, extension) in [
+                        ("Export text", "profile.txt"),
+                        ("Export CSV", "profile.csv"),
+                    ] {
+                        if ui.button(label).clicked() {
+                            self.write_profile(extension);
+                        }
+                    }
+                });
+                ui.separator();
+
+                let symbols = Some(&self.disassembly.symbols);
+                let nes = self.emulator.lock();
+                let profile = nes.profile();
+                let total = profile[0].inclusive_cycles().max(1);
+                let mut entries: Vec<_> = profile.iter().collect();
+                entries.sort_by_key(|entry| std::cmp::Reverse(entry.exclusive_cycles()));
+
+                egui::ScrollArea::vertical()
+                    .auto_shrink(false)
+                    .show(ui, |ui| {
+                        egui::Grid::new("profile").striped(true).show(ui, |ui| {
+                            for heading in [
+                                "Routine",
+                                "Calls",
+                                "Inclusive",
+                                "Exclusive",
+                                "Excl %",
+                                "Max/frame",
+                            ] {
+                                ui.strong(heading);
+                            }
+                            ui.end_row();
+
+                            for entry in entries {
+                                ui.monospace(entry.name(symbols));
+                                ui.monospace(entry.calls().to_string());
+                                ui.monospace(entry.inclusive_cycles().to_string());
+                                ui.monospace(entry.exclusive_cycles().to_string());
+                                ui.monospace(format!(
+                                    "{:.2}%",
+                                    entry.exclusive_cycles() as f64 * 100.0 / total as f64
+                                ));
+                                ui.monospace(entry.max_cycles_per_frame().to_string());
+                                ui.end_row();
+                            }
+                        });
+                    });
+            });
+
+        self.show_profiler = open;
+    }
+
+    fn call_stack_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_call_stack;
+        if !open {
+            return;
+        }
+        let mut run_to_return = None;
+
+        egui::Window::new("Call Stack")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let nes = self.emulator.lock();
+                let frames = nes.call_stack();
+                if frames.is_empty() {
+                    ui.label("No calls on the stack");
+                    return;
+                }
+
+                let name = |address: u16| {
+                    self.disassembly
+                        .symbols
+                        .lookup(&nes, address)
+                        .filter(|symbol| !symbol.label.is_empty())
+                        .map_or_else(|| format!("${:04X}", address), |s| s.label.clone())
+                };
+
+                egui::Grid::new("call_stack").striped(true).show(ui, |ui| {
+                    for heading in ["#", "Kind", "Routine", "Called from", "Returns to", "", ""] {
+                        ui.strong(heading);
+                    }
+                    ui.end_row();
+
+                    // Innermost call first
+                    for (index, frame) in frames.iter().enumerate().rev() {
+                        ui.monospace(index.to_string());
+                        ui.label(match frame.kind() {
+                            CallKind::Subroutine => "JSR",
+                            CallKind::Break => "BRK",
+                            CallKind::Irq => "IRQ",
+                            CallKind::Nmi => "NMI",
+                        });
+                        ui.monospace(name(frame.target()));
+                        ui.monospace(name(frame.call_site()));
+                        ui.monospace(format!("${:04X}", frame.return_address()));
+
+                        match frame.mismatch() {
+                            Some(mismatch) => {
+                                ui.colored_label(
+                                    egui::Color32::from_rgb(220, 180, 60),
+                                    match mismatch {
+                                        StackMismatch::ReturnWithoutCall => "RTS/RTI used as jump",
+                                        StackMismatch::Unwound => "Calls above were dropped",
+                                        StackMismatch::ReturnAddressChanged => {
+                                            "Return address was changed"
+                                        }
+                                        StackMismatch::WrongReturnKind => "RTS/RTI mismatch",
+                                    },
+                                );
+                            }
+                            None => {
+                                ui.label("");
+                            }
+                        }
+
+                        if ui.button("Run to return").clicked() {
+                            run_to_return = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+        if let Some(index) = run_to_return {
+            self.emulator.lock().run_to_return(index);
+        }
+        self.show_call_stack = open;
+    }
+
+    fn toggle_gdb_server(&mut self) {
+        if self.gdb.take().is_some() {
+            self.status = "GDB server stopped".to_owned();
+            return;
+        }
+        match GdbStub::listen(GDB_ADDRESS) {
+            Ok(gdb) => {
+                self.gdb = Some(gdb);
+                self.status = format!("GDB server listening on {}", GDB_ADDRESS);
+            }
+            Err(error) => self.status = error.to_owned(),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        let nes = self.emulator.lock();
+        nes.get_run_mode() == RunMode::Running && nes.is_powered_on()
+    }
+
+    // The last frame as shown: through the NTSC filter or the palette, then the filter chain.
+    // While running that is the thread's latest frame. Otherwise it is the emulator's own,
+    // since stepping or loading a state changes it without a frame being published.
+    fn filtered_frame(&mut self, running: bool) -> RgbaImage {
+        let nes = self.emulator.lock();
+        let frame = match (running, self.ntsc_enabled) {
+            (true, true) => {
+                let frame = self.emulator.frame();
+                self.ntsc_filter.apply(&frame.pixels, frame.phase)
+            }
+            (true, false) => self.emulator.frame().image(nes.palette()),
+            (false, true) => self
+                .ntsc_filter
+                .apply(nes.frame_buffer(), nes.frame_phase()),
+            (false, false) => nes.frame_image(),
+        };
+        drop(nes);
+        self.filter_chain.apply(frame)
+    }
+
+    fn ntsc_settings_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_ntsc_settings;
+        if !open {
+            return;
+        }
+
+        let mut settings = self.ntsc_filter.settings();
+        egui::Window::new("NTSC Settings")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.ntsc_enabled, "Enabled");
+                let sliders = [
+                    ("Hue", &mut settings.hue),
+                    ("Saturation", &mut settings.saturation),
+                    ("Sharpness", &mut settings.sharpness),
+                    ("Fringing", &mut settings.fringing),
+                    ("Bleed", &mut settings.bleed),
+                ];
+                for (name, value) in sliders {
+                    ui.add(egui::Slider::new(value, -1.0..=1.0).text(name));
+                }
+                if ui.button("Defaults").clicked() {
+                    settings = NtscSettings::default();
+                }
+            });
+
+        if settings != self.ntsc_filter.settings() {
+            self.ntsc_filter.set_settings(settings);
+        }
+        self.show_ntsc_settings = open;
+    }
+
+    fn event_viewer_window(&mut self, ctx: &egui::Context) {
+        // Events are only recorded while the viewer is open
+        let mut nes = self.emulator.lock();
+        if nes.is_event_logging() != self.show_event_viewer {
+            nes.set_event_logging(self.show_event_viewer);
+        }
+        let mut open = self.show_event_viewer;
+        if !open {
+            return;
+        }
+
+        const SCALE: f32 = 2.0;
+        const DOTS: u16 = 341;
+        let kinds = [
+            (
+                EventKind::PpuRegisterWrite,
+                "PPU register",
+                egui::Color32::from_rgb(80, 160, 255),
+            ),
+            (
+                EventKind::OamDma,
+                "OAM DMA",
+                egui::Color32::from_rgb(255, 150, 40),
+            ),
+            (
+                EventKind::ControllerStrobe,
+                "Controller",
+                egui::Color32::from_rgb(230, 230, 80),
+            ),
+            (
+                EventKind::MapperRegisterWrite,
+                "Mapper",
+                egui::Color32::from_rgb(200, 90, 255),
+            ),
+            (EventKind::Irq, "IRQ", egui::Color32::from_rgb(255, 70, 70)),
+            (EventKind::Nmi, "NMI", egui::Color32::from_rgb(80, 220, 100)),
+            (EventKind::Sprite0Hit, "Sprite 0 hit", egui::Color32::WHITE),
+        ];
+        let color = |kind: EventKind| {
+            kinds
+                .iter()
+                .find(|(k, _, _)| *k == kind)
+                .map_or(egui::Color32::GRAY, |(_, _, color)| *color)
+        };
+
+        let scanlines = nes.region().scanlines_per_frame();
+
+        egui::Window::new("Event Viewer")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let events = nes.frame_events();
+
+                ui.horizontal_wrapped(|ui| {
+                    for (kind, name, kind_color) in kinds {
+                        let count = events.iter().filter(|event| event.kind == kind).count();
+                        ui.colored_label(kind_color, "■");
+                        ui.label(format!("{} ({})", name, count));
+                    }
+                });
+
+                let size = egui::vec2(DOTS as f32 * SCALE, scanlines as f32 * SCALE);
+                let (rect, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+                let painter = ui.painter_at(rect);
+                let position = |scanline: u16, dot: u16| {
+                    rect.min + egui::vec2(dot as f32 * SCALE, scanline as f32 * SCALE)
+                };
+
+                painter.rect_filled(rect, 0.0, egui::Color32::from_gray(24));
+                // Horizontal blanking, then vertical blanking through the pre-render line
+                painter.rect_filled(
+                    egui::Rect::from_min_max(position(0, 256), position(240, DOTS)),
+                    0.0,
+                    egui::Color32::from_gray(44),
+                );
+                painter.rect_filled(
+                    egui::Rect::from_min_max(position(240, 0), position(scanlines, DOTS)),
+                    0.0,
+                    egui::Color32::from_gray(56),
+                );
+
+                for event in events {
+                    let min = position(event.scanline, event.dot);
+                    painter.rect_filled(
+                        egui::Rect::from_min_size(min, egui::vec2(SCALE, SCALE)).expand(1.0),
+                        0.0,
+                        color(event.kind),
+                    );
+                }
+
+                let Some(pointer) = response.hover_pos() else {
+                    return;
+                };
+                let dot = ((pointer.x - rect.min.x) / SCALE) as i32;
+                let scanline = ((pointer.y - rect.min.y) / SCALE) as i32;
+                let nearby: Vec<_> = events
+                    .iter()
+                    .filter(|event| {
+                        (event.dot as i32 - dot).abs() <= 2
+                            && (event.scanline as i32 - scanline).abs() <= 2
+                    })
+                    .collect();
+
+                response.on_hover_ui_at_pointer(|ui| {
+                    ui.monospace(format!("Scanline {}, dot {}", scanline, dot));
+                    for event in nearby {
+                        let text = match event.kind {
+                            EventKind::Irq | EventKind::Nmi | EventKind::Sprite0Hit => format!(
+                                "{}: scanline {}, dot {} -> ${:04X} (PC ${:04X})",
+                                kinds
+                                    .iter()
+                                    .find(|(k, _, _)| *k == event.kind)
+                                    .map_or("", |(_, name, _)| name),
+                                event.scanline,
+                                event.dot,
+                                event.address,
+                                event.program_counter
+                            ),
+                            _ => format!(
+                                "Scanline {}, dot {}: ${:04X} = ${:02X} (PC ${:04X})",
+                                event.scanline,
+                                event.dot,
+                                event.address,
+                                event.value,
+                                event.program_counter
+                            ),
+                        };
+                        ui.colored_label(color(event.kind), egui::RichText::new(text).monospace());
+                    }
+                });
+            });
+
+        self.show_event_viewer = open;
+    }
+
+    fn disassembly_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_disassembly;
+        if !open {
+            return;
+        }
+        let mut labels_changed = false;
+
+        let nes = self.emulator.lock();
+        let program_counter = nes.cpu_registers().program_counter();
+        if self.disassembly.follow_pc {
+            self.disassembly.start = program_counter;
+        }
+
+        egui::Window::new("Disassembly")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let view = &mut self.disassembly;
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut view.follow_pc, "Follow PC");
+                    ui.separator();
+                    ui.label("Go to");
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut view.goto_address).desired_width(48.0),
+                    );
+                    let submitted =
+                        response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Go").clicked() || submitted {
+                        let text = view.goto_address.trim().trim_start_matches('$');
+                        if let Ok(address) = u16::from_str_radix(text, 16) {
+                            view.follow_pc = false;
+                            view.start = address;
+                        }
+                    }
+                });
+                ui.separator();
+
+                let mut address = view.start;
+                for _ in 0..DISASSEMBLY_LINES {
+                    let instruction = disassemble_instruction(&nes, Some(&view.symbols), address);
+                    let symbol = view.symbols.lookup(&nes, address);
+
+                    if let Some(symbol) = symbol.filter(|s| !s.label.is_empty()) {
+                        ui.colored_label(
+                            egui::Color32::from_rgb(220, 180, 60),
+                            format!("{}:", symbol.label),
+                        );
+                    }
+
+                    let bytes = instruction.bytes[..instruction.length as usize]
+                        .iter()
+                        .map(|byte| format!("{:02X}", byte))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let mut line = format!(
+                        "{} {:04X}  {:<8}  {}",
+                        if address == program_counter { '>' } else { ' ' },
+                        address,
+                        bytes,
+                        instruction.text()
+                    );
+                    if let Some(symbol) = symbol.filter(|s| !s.comment.is_empty()) {
+                        line.push_str(&format!("  ; {}", symbol.comment.replace('\n', " ")));
+                    }
+
+                    let mut text = egui::RichText::new(line).monospace();
+                    // With a code/data log, bytes it never saw are dimmed
+                    if nes.code_data_log().is_some() && instruction.code_data_flags == 0 {
+                        text = text.color(egui::Color32::from_gray(110));
+                    }
+                    if view.selected == Some(address) {
+                        text = text.background_color(egui::Color32::from_rgb(60, 60, 140));
+                    } else if address == program_counter {
+                        text = text.background_color(egui::Color32::from_rgb(100, 30, 30));
+                    }
+                    if ui
+                        .add(egui::Label::new(text).sense(egui::Sense::click()))
+                        .clicked()
+                    {
+                        view.selected = Some(address);
+                        let symbol = symbol.cloned().unwrap_or_default();
+                        view.edit_label = symbol.label;
+                        view.edit_comment = symbol.comment;
+                    }
+
+                    address = address.wrapping_add(instruction.length as u16);
+                }
+
+                ui.separator();
+                if let Some(selected) = view.selected {
+                    ui.horizontal(|ui| {
+                        ui.monospace(format!("${:04X}", selected));
+                        ui.label("Label");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut view.edit_label).desired_width(120.0),
+                        );
+                        ui.label("Comment");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut view.edit_comment).desired_width(200.0),
+                        );
+                        if ui.button("Set").clicked() {
+                            let location = SymbolTable::location(&nes, selected);
+                            view.user_symbols.set_label(location, &view.edit_label);
+                            view.user_symbols.set_comment(location, &view.edit_comment);
+                            view.rebuild_symbols();
+                            labels_changed = true;
+                        }
+                    });
+                } else {
+                    ui.label("Click a line to label or comment it");
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Import");
+                    ui.add(egui::TextEdit::singleline(&mut view.import_path).desired_width(240.0));
+                    if ui.button("Load").clicked() {
+                        let path = PathBuf::from(view.import_path.trim());
+                        self.status = match view.import_file(&path) {
+                            Ok(count) => format!("Imported {} symbols", count),
+                            Err(error) => error.to_owned(),
+                        };
+                    }
+                });
+                ui.label(format!("{} symbols", view.symbols.len()));
+            });
+        drop(nes);
+
+        self.show_disassembly = open;
+        if labels_changed {
+            self.write_symbols();
+        }
+    }
+}
+
 // Main GUI Code
 impl eframe::App for Rustendulator {
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
@@ -111,6 +1744,10 @@ impl eframe::App for Rustendulator {
             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
         }
 
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::F12)) {
+            self.write_screenshot();
+        }
+
         if ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::U)) {
             self.show_left_panel = !self.show_left_panel;
         }
@@ -119,6 +1756,52 @@ impl eframe::App for Rustendulator {
             self.show_right_panel = !self.show_right_panel;
         }
 
+        let dropped_file = ctx.input(|i| i.raw.dropped_files.iter().find_map(|f| f.path.clone()));
+        if let Some(path) = dropped_file {
+            if path.extension().is_some_and(|extension| extension == "pal") {
+                self.load_palette(&path);
+            } else {
+                self.open_rom(path);
+            }
+        }
+
+        // Emulation
+
+        let buttons = ctx.input(|i| {
+            CONTROLLER_KEYS
+                .iter()
+                .filter(|(key, _)| i.key_down(*key))
+                .fold(0, |buttons, (_, button)| buttons | *button as u8)
+        });
+        self.emulator.send(Command::SetButtons(0, buttons));
+
+        // GDB commands run between frames, so a continue takes effect right away
+        if let Some(gdb) = self.gdb.as_mut() {
+            gdb.poll(&mut self.emulator.lock());
+            ctx.request_repaint_after(Duration::from_millis(10));
+        }
+
+        // Holding Backspace plays backwards, holding Tab fast-forwards
+        let rewinding = ctx.input(|i| i.key_down(egui::Key::Backspace));
+        let fast_forward = ctx.input(|i| i.key_down(egui::Key::Tab));
+        self.emulator.send(Command::Rewind(rewinding));
+        self.emulator.send(Command::OverrideSpeed(
+            fast_forward.then_some(FAST_FORWARD_SPEED),
+        ));
+
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Backtick)) {
+            self.toggle_turbo();
+        }
+
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Backslash)) {
+            self.emulator.send(Command::AdvanceFrame);
+        }
+
+        if self.last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+            self.write_save_ram();
+            self.last_autosave = Instant::now();
+        }
+
         // Menu
 
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
@@ -128,9 +1811,118 @@ impl eframe::App for Rustendulator {
                         .add(egui::Button::new("Open ROM...").shortcut_text("Ctrl+O"))
                         .clicked()
                     {
-                        // Load ROM
+                        // No file dialog yet, ROMs are opened from the command line or dropped
+                        self.status = "Drop a ROM file onto the window to open it".to_owned();
+                    }
+
+                    if ui.button("Default Palette").clicked() {
+                        self.emulator.lock().set_palette(ColorPalette::ntsc());
+                        self.status = "Drop a .pal file onto the window to load it".to_owned();
+                    }
+
+                    if ui
+                        .add_enabled(
+                            self.emulator.lock().has_cartridge(),
+                            egui::Button::new("Eject"),
+                        )
+                        .clicked()
+                    {
+                        self.eject_rom();
+                    }
+
+                    ui.separator();
+
+                    if ui
+                        .add_enabled(
+                            self.emulator.lock().has_cartridge(),
+                            egui::Button::new("Screenshot").shortcut_text("F12"),
+                        )
+                        .clicked()
+                    {
+                        self.write_screenshot();
+                    }
+
+                    if ui
+                        .add(
+                            egui::Button::new("Filtered Screenshots")
+                                .selected(self.screenshot_filtered),
+                        )
+                        .clicked()
+                    {
+                        self.screenshot_filtered = !self.screenshot_filtered;
+                    }
+
+                    let (has_cartridge, recording) = {
+                        let nes = self.emulator.lock();
+                        (nes.has_cartridge(), nes.is_recording())
+                    };
+                    if ui
+                        .add_enabled(
+                            has_cartridge,
+                            egui::Button::new("Record Video").selected(recording),
+                        )
+                        .clicked()
+                    {
+                        self.toggle_recording();
+                    }
+
+                    if ui
+                        .add_enabled(
+                            has_cartridge || self.gif_clip,
+                            egui::Button::new("Record GIF Clip").selected(self.gif_clip),
+                        )
+                        .clicked()
+                    {
+                        self.toggle_gif_clip();
+                    }
+
+                    if ui
+                        .add(egui::Button::new("GIF Replay Buffer").selected(self.gif_replay))
+                        .clicked()
+                    {
+                        self.gif_replay = !self.gif_replay;
+                        if !self.gif_clip {
+                            self.restart_gif_replay();
+                        }
+                    }
+
+                    if ui
+                        .add_enabled(
+                            self.gif_replay && !self.gif_clip && has_cartridge,
+                            egui::Button::new(format!(
+                                "Save Last {} Seconds as GIF",
+                                GIF_REPLAY_SECONDS
+                            )),
+                        )
+                        .clicked()
+                    {
+                        self.write_gif();
+                    }
+
+                    ui.separator();
+
+                    if ui
+                        .add(
+                            egui::Button::new("Saves Next to ROM")
+                                .selected(self.save_location == SaveLocation::NextToRom),
+                        )
+                        .clicked()
+                    {
+                        self.save_location = SaveLocation::NextToRom;
+                    }
+
+                    if ui
+                        .add(
+                            egui::Button::new("Saves in saves/ Folder")
+                                .selected(self.save_location == SaveLocation::SavesDirectory),
+                        )
+                        .clicked()
+                    {
+                        self.save_location = SaveLocation::SavesDirectory;
                     }
 
+                    ui.separator();
+
                     if ui
                         .add(egui::Button::new("Quit").shortcut_text("Ctrl+Q"))
                         .clicked()
@@ -176,7 +1968,7 @@ impl eframe::App for Rustendulator {
                         egui::Button::new("Run Mode").right_text(SubMenuButton::RIGHT_ARROW),
                     )
                     .ui(ui, |ui| {
-                        let current = self.nes.get_run_mode();
+                        let current = self.emulator.lock().get_run_mode();
 
                         if ui
                             .add(
@@ -186,7 +1978,7 @@ impl eframe::App for Rustendulator {
                             )
                             .clicked()
                         {
-                            self.nes.set_run_mode(RunMode::Paused);
+                            self.emulator.send(Command::SetRunMode(RunMode::Paused));
                         };
 
                         if ui
@@ -197,7 +1989,7 @@ impl eframe::App for Rustendulator {
                             )
                             .clicked()
                         {
-                            self.nes.set_run_mode(RunMode::Running);
+                            self.emulator.send(Command::SetRunMode(RunMode::Running));
                         };
 
                         if ui
@@ -208,7 +2000,7 @@ impl eframe::App for Rustendulator {
                             )
                             .clicked()
                         {
-                            self.nes.set_run_mode(RunMode::StepCycle);
+                            self.emulator.send(Command::SetRunMode(RunMode::StepCycle));
                         };
 
                         if ui
@@ -219,7 +2011,8 @@ impl eframe::App for Rustendulator {
                             )
                             .clicked()
                         {
-                            self.nes.set_run_mode(RunMode::StepInstruction);
+                            self.emulator
+                                .send(Command::SetRunMode(RunMode::StepInstruction));
                         };
 
                         if ui
@@ -230,13 +2023,297 @@ impl eframe::App for Rustendulator {
                             )
                             .clicked()
                         {
-                            self.nes.set_run_mode(RunMode::StepFrame);
+                            self.emulator.send(Command::SetRunMode(RunMode::StepFrame));
                         };
-                    })
-                })
+                    });
+
+                    SubMenuButton::from_button(
+                        egui::Button::new("Speed").right_text(SubMenuButton::RIGHT_ARROW),
+                    )
+                    .ui(ui, |ui| {
+                        let current = self.emulator.lock().speed();
+                        for speed in SPEEDS {
+                            let button = egui::Button::new(speed.name()).selected(current == speed);
+                            let button = match speed {
+                                Speed::Uncapped => button.shortcut_text("`"),
+                                Speed::Percent(_) => button,
+                            };
+                            if ui.add(button).clicked() {
+                                self.emulator.lock().set_speed(speed);
+                            }
+                        }
+                        ui.separator();
+                        ui.label(format!("Hold Tab for {}", FAST_FORWARD_SPEED.name()));
+                    });
+
+                    SubMenuButton::from_button(
+                        egui::Button::new("Frame Skip").right_text(SubMenuButton::RIGHT_ARROW),
+                    )
+                    .ui(ui, |ui| {
+                        for skip in 0..=3 {
+                            let label = if skip == 0 {
+                                "Off".to_owned()
+                            } else {
+                                format!("Draw 1 in {}", skip + 1)
+                            };
+                            if ui
+                                .add(egui::Button::new(label).selected(self.frame_skip == skip))
+                                .clicked()
+                            {
+                                self.frame_skip = skip;
+                            }
+                        }
+                    });
+
+                    if ui
+                        .add_enabled(
+                            self.emulator.lock().is_powered_on(),
+                            egui::Button::new("Frame Advance").shortcut_text("\\"),
+                        )
+                        .clicked()
+                    {
+                        self.emulator.send(Command::AdvanceFrame);
+                    }
+
+                    SubMenuButton::from_button(
+                        egui::Button::new("Run-Ahead").right_text(SubMenuButton::RIGHT_ARROW),
+                    )
+                    .ui(ui, |ui| {
+                        let (frames, mode) = {
+                            let nes = self.emulator.lock();
+                            (nes.run_ahead_frames(), nes.run_ahead_mode())
+                        };
+                        for option in 0..=3 {
+                            let label = match option {
+                                0 => "Off".to_owned(),
+                                1 => "1 Frame".to_owned(),
+                                _ => format!("{} Frames", option),
+                            };
+                            if ui
+                                .add(egui::Button::new(label).selected(frames == option))
+                                .clicked()
+                            {
+                                self.emulator.lock().set_run_ahead(option, mode);
+                            }
+                        }
+                        ui.separator();
+                        let second_instance = mode == RunAheadMode::SecondInstance;
+                        if ui
+                            .add(egui::Button::new("Second Instance").selected(second_instance))
+                            .clicked()
+                        {
+                            let mode = if second_instance {
+                                RunAheadMode::SingleInstance
+                            } else {
+                                RunAheadMode::SecondInstance
+                            };
+                            self.emulator.lock().set_run_ahead(frames, mode);
+                        }
+                    });
+
+                    // Replays from rewind states
+                    let can_step_back = {
+                        let nes = self.emulator.lock();
+                        nes.is_rewind_enabled() && nes.is_powered_on()
+                    };
+                    SubMenuButton::from_button(
+                        egui::Button::new("Region").right_text(SubMenuButton::RIGHT_ARROW),
+                    )
+                    .ui(ui, |ui| {
+                        let current = self.emulator.lock().region_setting();
+                        let auto = format!("Auto ({})", self.emulator.lock().region().name());
+                        if ui
+                            .add(egui::Button::new(auto).selected(current.is_none()))
+                            .clicked()
+                        {
+                            self.emulator.lock().set_region(None);
+                        }
+                        for region in [Region::Ntsc, Region::Pal, Region::Dendy] {
+                            if ui
+                                .add(
+                                    egui::Button::new(region.name())
+                                        .selected(current == Some(region)),
+                                )
+                                .clicked()
+                            {
+                                self.emulator.lock().set_region(Some(region));
+                            }
+                        }
+                    });
+
+                    SubMenuButton::from_button(
+                        egui::Button::new("Step Back").right_text(SubMenuButton::RIGHT_ARROW),
+                    )
+                    .ui(ui, |ui| {
+                        let mut result = None;
+                        if ui
+                            .add_enabled(can_step_back, egui::Button::new("Instruction"))
+                            .clicked()
+                        {
+                            result = Some(self.emulator.lock().step_back_instruction());
+                        }
+                        if ui
+                            .add_enabled(can_step_back, egui::Button::new("Frame"))
+                            .clicked()
+                        {
+                            result = Some(self.emulator.lock().step_back_frame());
+                        }
+                        if ui
+                            .add_enabled(can_step_back, egui::Button::new("To Previous Breakpoint"))
+                            .clicked()
+                        {
+                            result = Some(self.emulator.lock().run_back_to_breakpoint());
+                        }
+                        if let Some(Err(error)) = result {
+                            self.status = error.to_owned();
+                        }
+                    });
+                });
+
+                ui.menu_button("Video", |ui| {
+                    if ui
+                        .add(egui::Button::new("NTSC Filter").selected(self.ntsc_enabled))
+                        .clicked()
+                    {
+                        self.ntsc_enabled = !self.ntsc_enabled;
+                    }
+
+                    if ui
+                        .add(
+                            egui::Button::new("NTSC Settings...").selected(self.show_ntsc_settings),
+                        )
+                        .clicked()
+                    {
+                        self.show_ntsc_settings = !self.show_ntsc_settings;
+                    }
+
+                    ui.separator();
+
+                    // Filters run top to bottom, clicking one in the chain removes it
+                    let mut removed = None;
+                    for (index, filter) in self.filter_chain.filters().iter().enumerate() {
+                        if ui
+                            .button(format!("{}. {}", index + 1, filter.name()))
+                            .on_hover_text("Remove")
+                            .clicked()
+                        {
+                            removed = Some(index);
+                        }
+                    }
+                    if let Some(index) = removed {
+                        self.filter_chain.remove(index);
+                    }
+
+                    SubMenuButton::from_button(
+                        egui::Button::new("Add Filter").right_text(SubMenuButton::RIGHT_ARROW),
+                    )
+                    .ui(ui, |ui| {
+                        for filter in ScaleFilter::ALL {
+                            if ui.button(filter.name()).clicked() {
+                                self.filter_chain.push(filter);
+                            }
+                        }
+                    });
+
+                    if ui
+                        .add_enabled(
+                            !self.filter_chain.is_empty(),
+                            egui::Button::new("Clear Filters"),
+                        )
+                        .clicked()
+                    {
+                        self.filter_chain.clear();
+                    }
+                });
+
+                ui.menu_button("Tools", |ui| {
+                    if ui
+                        .add(egui::Button::new("Cheats...").selected(self.show_cheats))
+                        .clicked()
+                    {
+                        self.show_cheats = !self.show_cheats;
+                    }
+
+                    if ui
+                        .add(egui::Button::new("RAM Search...").selected(self.show_ram_search))
+                        .clicked()
+                    {
+                        self.show_ram_search = !self.show_ram_search;
+                    }
+
+                    if ui
+                        .add(egui::Button::new("RAM Watch...").selected(self.show_ram_watch))
+                        .clicked()
+                    {
+                        self.show_ram_watch = !self.show_ram_watch;
+                    }
+
+                    if ui
+                        .add(egui::Button::new("Hex Editor...").selected(self.show_hex_editor))
+                        .clicked()
+                    {
+                        self.show_hex_editor = !self.show_hex_editor;
+                    }
+
+                    if ui
+                        .add(egui::Button::new("Disassembly...").selected(self.show_disassembly))
+                        .clicked()
+                    {
+                        self.show_disassembly = !self.show_disassembly;
+                    }
+
+                    if ui
+                        .add(
+                            egui::Button::new("Code/Data Logger...")
+                                .selected(self.show_code_data_logger),
+                        )
+                        .clicked()
+                    {
+                        self.show_code_data_logger = !self.show_code_data_logger;
+                    }
+
+                    if ui
+                        .add(egui::Button::new("Profiler...").selected(self.show_profiler))
+                        .clicked()
+                    {
+                        self.show_profiler = !self.show_profiler;
+                    }
+
+                    if ui
+                        .add(egui::Button::new("Call Stack...").selected(self.show_call_stack))
+                        .clicked()
+                    {
+                        self.show_call_stack = !self.show_call_stack;
+                    }
+
+                    if ui
+                        .add(egui::Button::new("Event Viewer...").selected(self.show_event_viewer))
+                        .clicked()
+                    {
+                        self.show_event_viewer = !self.show_event_viewer;
+                    }
+
+                    if ui
+                        .add(egui::Button::new("GDB Server").selected(self.gdb.is_some()))
+                        .clicked()
+                    {
+                        self.toggle_gdb_server();
+                    }
+                });
             });
         });
 
+        self.cheats_window(ctx);
+        self.ram_search_window(ctx);
+        self.ram_watch_window(ctx);
+        self.hex_editor_window(ctx);
+        self.disassembly_window(ctx);
+        self.code_data_logger_window(ctx);
+        self.profiler_window(ctx);
+        self.call_stack_window(ctx);
+        self.event_viewer_window(ctx);
+        self.ntsc_settings_window(ctx);
+
         egui::SidePanel::left("left_panel")
             .resizable(false)
             .min_width(300.0)
@@ -252,7 +2329,7 @@ impl eframe::App for Rustendulator {
                             egui::Vec2 { x: 0.0, y: 50.0 },
                             egui::Layout::left_to_right(egui::Align::Center),
                             |ui| {
-                                let on = self.nes.is_powered_on();
+                                let on = self.emulator.lock().is_powered_on();
 
                                 self.power_led(ui, on);
 
@@ -260,9 +2337,9 @@ impl eframe::App for Rustendulator {
 
                                 if power.clicked() {
                                     if on {
-                                        self.nes.power_off();
+                                        self.emulator.send(Command::PowerOff);
                                     } else {
-                                        self.nes.power_on();
+                                        self.emulator.send(Command::PowerOn);
                                     }
                                 }
 
@@ -270,7 +2347,7 @@ impl eframe::App for Rustendulator {
 
                                 if reset.clicked() && on {
                                     {
-                                        self.nes.reset();
+                                        self.emulator.send(Command::Reset);
                                     }
                                 }
                             },
@@ -286,32 +2363,29 @@ impl eframe::App for Rustendulator {
                                 let run = self.gui_button(ui, "RUN |\nPAUSE");
 
                                 if run.clicked() {
-                                    if self.nes.get_run_mode() != RunMode::Paused {
-                                        self.nes.set_run_mode(RunMode::Paused);
+                                    if self.emulator.lock().get_run_mode() != RunMode::Paused {
+                                        self.emulator.send(Command::SetRunMode(RunMode::Paused));
                                     } else {
-                                        self.nes.set_run_mode(RunMode::Running);
+                                        self.emulator.send(Command::SetRunMode(RunMode::Running));
                                     }
                                 }
 
                                 let mode = self.gui_button(ui, "CYCLE\nMODE");
 
                                 if mode.clicked() {
-                                    match self.nes.get_run_mode() {
-                                        RunMode::StepCycle => {
-                                            self.nes.set_run_mode(RunMode::StepFrame)
-                                        }
-                                        RunMode::StepFrame => {
-                                            self.nes.set_run_mode(RunMode::StepInstruction)
-                                        }
-                                        _ => self.nes.set_run_mode(RunMode::StepCycle),
-                                    }
+                                    let next = match self.emulator.lock().get_run_mode() {
+                                        RunMode::StepCycle => RunMode::StepFrame,
+                                        RunMode::StepFrame => RunMode::StepInstruction,
+                                        _ => RunMode::StepCycle,
+                                    };
+                                    self.emulator.send(Command::SetRunMode(next));
                                 }
                             },
                         );
                     });
                     ui.label(format!(
                         "Run mode: {}",
-                        match self.nes.get_run_mode() {
+                        match self.emulator.lock().get_run_mode() {
                             RunMode::Paused => "Paused",
                             RunMode::Running => "Running",
                             RunMode::StepCycle => "Step Cycle",
@@ -319,6 +2393,14 @@ impl eframe::App for Rustendulator {
                             RunMode::StepInstruction => "Step Instr",
                         }
                     ));
+                    ui.label(format!("Speed: {}", self.emulator.lock().speed().name()));
+                    ui.label(format!(
+                        "Rewind: {} frames",
+                        self.emulator.lock().rewind_frames_available()
+                    ));
+                    if !self.status.is_empty() {
+                        ui.label(&self.status);
+                    }
                     ui.separator();
                     ui.label("CPU Info")
                 });
@@ -327,9 +2409,51 @@ impl eframe::App for Rustendulator {
             ui.style_mut().override_font_id = Some(egui::FontId::new(12.0, pixel_font_family()));
             ui.heading("PPU Debug");
         });
+        // While running, only every (frame_skip + 1)th published frame is filtered and uploaded
+        let running = self.is_running();
+        let new_frame = self.emulator.take_frame().map(|frame| frame.number);
+        let frame_due =
+            new_frame.is_some_and(|number| number > self.rendered_frame + self.frame_skip as u64);
+        if self.screen.is_none() || !running || frame_due {
+            let frame = self.filtered_frame(running);
+            let image = egui::ColorImage::from_rgba_unmultiplied(
+                [frame.width(), frame.height()],
+                &frame.to_rgba(),
+            );
+            match self.screen.as_mut() {
+                Some(screen) => screen.set(image, egui::TextureOptions::NEAREST),
+                None => {
+                    self.screen =
+                        Some(ctx.load_texture("screen", image, egui::TextureOptions::NEAREST))
+                }
+            }
+            self.rendered_frame = self.emulator.frame().number;
+        }
+        let Some(screen) = self.screen.as_ref() else {
+            return;
+        };
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.style_mut().override_font_id = Some(egui::FontId::new(12.0, pixel_font_family()));
             ui.heading("NES Display");
+            // Largest whole multiple of the NES resolution that fits, the wide NTSC output and
+            // filtered frames are fit back into the same space
+            let available = ui.available_size();
+            let scale = (available.x / SCREEN_WIDTH as f32)
+                .min(available.y / SCREEN_HEIGHT as f32)
+                .floor()
+                .max(1.0);
+            ui.centered_and_justified(|ui| {
+                ui.image((
+                    screen.id(),
+                    egui::vec2(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32) * scale,
+                ));
+            });
         });
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        let _ = self.emulator.lock().stop_recording();
+        self.write_save_ram();
+    }
 }
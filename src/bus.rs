@@ -1,6 +1,14 @@
-use crate::cartridge::Cartridge;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::cheats::ReadPatches;
+use crate::code_data_log::CodeDataLog;
+use crate::controller::Controller;
+use crate::debugger::{AccessLog, EventKind, EventLog};
 use crate::memory::Ram;
 use crate::ppu::Ppu;
+use crate::region::Region;
+use crate::state::{StateReader, StateWriter};
+
+const STATE_VERSION: u16 = 1;
 
 pub(crate) struct Bus {
     ram: Ram,
@@ -19,13 +27,27 @@ pub(crate) struct Bus {
     irq_mapper: bool,
 
     cartridge: Option<Cartridge>,
+
+    controllers: [Controller; 2],
+
+    // Watchpoint hits for the debugger
+    access_log: AccessLog,
+    // Register writes and interrupts by PPU position, for the event viewer
+    event_log: EventLog,
+
+    // Cheat substitutions applied to every CPU read
+    read_patches: ReadPatches,
+
+    // Kept while paused so logging can resume, dropped with the cartridge
+    code_data_log: Option<CodeDataLog>,
+    code_data_logging: bool,
 }
 
 impl Bus {
     pub(crate) fn new() -> Self {
         Self {
             ram: Ram::new(),
-            ppu: Ppu::new(),
+            ppu: Ppu::new(Region::Ntsc),
             last_read: 0,
             nmi_line: false,
             nmi_edge_detected: false,
@@ -33,6 +55,12 @@ impl Bus {
             irq_apu_dmc: false,
             irq_mapper: false,
             cartridge: None,
+            controllers: [Controller::new(), Controller::new()],
+            access_log: AccessLog::new(),
+            event_log: EventLog::new(),
+            read_patches: ReadPatches::new(),
+            code_data_log: None,
+            code_data_logging: false,
         }
     }
 
@@ -48,8 +76,23 @@ impl Bus {
         }
     }
 
+    // Everything but the cartridge and debugger state back to power-up values
+    pub(crate) fn power_on(&mut self) {
+        self.ram = Ram::new();
+        self.ppu = Ppu::new(self.ppu.region());
+        self.last_read = 0;
+        self.nmi_line = false;
+        self.nmi_edge_detected = false;
+        self.irq_apu_frame = false;
+        self.irq_apu_dmc = false;
+        self.irq_mapper = false;
+        self.controllers = [Controller::new(), Controller::new()];
+    }
+
     pub(crate) fn unload_cartridge(&mut self) {
         self.cartridge = None;
+        self.code_data_log = None;
+        self.code_data_logging = false;
     }
 
     fn set_nmi(&mut self, level: bool) {
@@ -81,32 +124,268 @@ impl Bus {
         self.irq_apu_frame || self.irq_apu_dmc || self.irq_mapper
     }
 
-    pub(crate) fn ppu_stub_tick(&self) {
-        // TODO: Remove when PPU implemented
+    pub(crate) fn ppu_tick(&mut self) {
+        self.ppu.tick();
+        let nmi = self.ppu.nmi_output();
+        self.set_nmi(nmi);
+    }
+
+    pub(crate) fn set_region(&mut self, region: Region) {
+        self.ppu.set_region(region);
+    }
+
+    // What the ROM header asks for, None when it doesn't say
+    pub(crate) fn cartridge_region(&self) -> Option<Region> {
+        self.cartridge
+            .as_ref()
+            .and_then(|cartridge| cartridge.region())
+    }
+
+    // Once per CPU cycle, for mapper IRQ counters
+    pub(crate) fn cartridge_tick(&mut self) {
+        if let Some(cartridge) = self.cartridge.as_mut() {
+            cartridge.cpu_tick();
+            let irq = cartridge.irq_pending();
+            self.set_irq_mapper(irq);
+        }
+    }
+
+    pub(crate) fn cartridge_crc32(&self) -> Option<u32> {
+        self.cartridge.as_ref().map(Cartridge::crc32)
+    }
+
+    pub(crate) fn cartridge_md5(&self) -> Option<[u8; 16]> {
+        self.cartridge.as_ref().map(Cartridge::md5)
+    }
+
+    pub(crate) fn has_cartridge(&self) -> bool {
+        self.cartridge.is_some()
+    }
+
+    pub(crate) fn save_ram(&self) -> Option<&[u8]> {
+        self.cartridge.as_ref()?.save_ram()
+    }
+
+    pub(crate) fn load_save_ram(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        match self.cartridge.as_mut() {
+            Some(cartridge) => cartridge.load_save_ram(data),
+            None => Err("No cartridge loaded"),
+        }
+    }
+
+    pub(crate) fn controller_buttons(&self, port: usize) -> u8 {
+        self.controllers
+            .get(port)
+            .map_or(0, |controller| controller.buttons())
+    }
+
+    pub(crate) fn set_controller_buttons(&mut self, port: usize, buttons: u8) {
+        if let Some(controller) = self.controllers.get_mut(port) {
+            controller.set_buttons(buttons);
+        }
+    }
+
+    pub(crate) fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub(crate) fn access_log_mut(&mut self) -> &mut AccessLog {
+        &mut self.access_log
+    }
+
+    pub(crate) fn event_log(&self) -> &EventLog {
+        &self.event_log
+    }
+
+    pub(crate) fn event_log_mut(&mut self) -> &mut EventLog {
+        &mut self.event_log
+    }
+
+    // PPU and controller registers, OAM DMA and mapper registers
+    pub(crate) fn record_write_event(&mut self, address: u16, value: u8) {
+        if !self.event_log.is_enabled() {
+            return;
+        }
+
+        let kind = match address {
+            0x2000..=0x3FFF => EventKind::PpuRegisterWrite,
+            0x4014 => EventKind::OamDma,
+            0x4016 => EventKind::ControllerStrobe,
+            0x4020..=0xFFFF
+                if self
+                    .cartridge
+                    .as_ref()
+                    .is_some_and(|cartridge| cartridge.is_mapper_register(address)) =>
+            {
+                EventKind::MapperRegisterWrite
+            }
+            _ => return,
+        };
+        let (scanline, dot) = (self.ppu.scanline(), self.ppu.dot());
+        self.event_log.record(kind, scanline, dot, address, value);
+    }
+
+    pub(crate) fn read_patches_mut(&mut self) -> &mut ReadPatches {
+        &mut self.read_patches
+    }
+
+    pub(crate) fn code_data_log(&self) -> Option<&CodeDataLog> {
+        self.code_data_log.as_ref()
+    }
+
+    // Creates the log sized for the cartridge on first use
+    pub(crate) fn code_data_log_mut(&mut self) -> Result<&mut CodeDataLog, &'static str> {
+        let cartridge = self.cartridge.as_ref().ok_or("No cartridge loaded")?;
+        Ok(self.code_data_log.get_or_insert_with(|| {
+            CodeDataLog::new(cartridge.prg_rom_size(), cartridge.chr_rom_size())
+        }))
+    }
+
+    pub(crate) fn set_code_data_logging(&mut self, logging: bool) {
+        self.code_data_logging = logging;
+    }
+
+    pub(crate) fn is_code_data_logging(&self) -> bool {
+        self.code_data_logging
+    }
+
+    // Called by the CPU after each read, with how the byte was used
+    #[inline]
+    pub(crate) fn log_code_data(&mut self, address: u16, flags: u8) {
+        if !self.code_data_logging || address < 0x4020 {
+            return;
+        }
+        if let (Some(log), Some(cartridge)) = (self.code_data_log.as_mut(), self.cartridge.as_ref())
+            && let Some(offset) = cartridge.prg_rom_offset(address)
+        {
+            log.log_prg(offset, address, flags);
+        }
+    }
+
+    // RAM freeze cheats, kept out of the access log so they don't trip watchpoints
+    pub(crate) fn write_frozen(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram.write(address, value),
+            // Poked so boards with registers or EEPROM there instead of RAM are left alone
+            0x6000..=0x7FFF => {
+                if let Some(cartridge) = self.cartridge.as_mut() {
+                    cartridge.cpu_poke(address, value);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Side effect free read for debugging tools. Registers return what a read would, without
+    // clearing flags, advancing addresses or shifting controllers.
+    pub(crate) fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.ram.read(address), // RAM
+            0x2000..=0x3FFF => self.ppu.peek_register(address, self.last_read), // PPU Registers
+            0x4016 => self.controllers[0].peek() | (self.last_read & 0xE0), // Controller 1
+            0x4017 => self.controllers[1].peek() | (self.last_read & 0xE0), // Controller 2
+            0x4020..=0xFFFF => match self.cartridge.as_ref() {
+                Some(cartridge) => cartridge.cpu_read(address),
+                None => self.last_read,
+            }, // Cartridge
+            _ => self.last_read,                       // APU and open bus
+        }
+    }
+
+    // Debugger write to RAM or cartridge memory, registers are left alone
+    pub(crate) fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram.write(address, value),
+            0x4020..=0xFFFF => {
+                if let Some(cartridge) = self.cartridge.as_mut() {
+                    cartridge.cpu_poke(address, value);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub(crate) fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.cartridge.as_ref()?.prg_rom_offset(address)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.cartridge
+            .as_ref()
+            .map_or(Mirroring::Horizontal, |cartridge| cartridge.mirroring())
     }
 
-    pub(crate) fn cpu_read(&self, address: u16) -> u8 {
+    // PPU address space ($0000-$3FFF) as the PPU would see it, for debugging tools
+    pub(crate) fn ppu_peek(&self, address: u16) -> u8 {
+        let address = address & 0x3FFF;
         match address {
+            0x0000..=0x1FFF => self
+                .cartridge
+                .as_ref()
+                .map_or(0, |cartridge| cartridge.ppu_read(address)), // CHR
+            0x2000..=0x3EFF => self.ppu.peek_vram(self.mirroring().vram_index(address)), // Nametables
+            _ => self.ppu.peek_palette(address),                                         // Palette
+        }
+    }
+
+    pub(crate) fn ppu_poke(&mut self, address: u16, value: u8) {
+        let address = address & 0x3FFF;
+        match address {
+            0x0000..=0x1FFF => {
+                if let Some(cartridge) = self.cartridge.as_mut() {
+                    cartridge.ppu_write(address, value);
+                }
+            }
+            0x2000..=0x3EFF => {
+                let index = self.mirroring().vram_index(address);
+                self.ppu.poke_vram(index, value);
+            }
+            _ => self.ppu.poke_palette(address, value),
+        }
+    }
+
+    pub(crate) fn peek_oam(&self, address: u8) -> u8 {
+        self.ppu.peek_oam(address)
+    }
+
+    pub(crate) fn poke_oam(&mut self, address: u8, value: u8) {
+        self.ppu.poke_oam(address, value);
+    }
+
+    pub(crate) fn cpu_read(&mut self, address: u16) -> u8 {
+        let value = match address {
             0x0000..=0x1FFF => self.ram.read(address), // RAM
             0x2000..=0x3FFF => todo!(),                // PPU Registers
             0x4000..=0x4013 | 0x4015 => todo!(),       // APU
-            0x4016 => todo!(),                         // Controller 1
-            0x4017 => todo!(),                         // Controller 2
+            // Controllers only drive the low bits, the rest is open bus
+            0x4016 => self.controllers[0].read() | (self.last_read & 0xE0), // Controller 1
+            0x4017 => self.controllers[1].read() | (self.last_read & 0xE0), // Controller 2
             0x4020..=0xFFFF => match self.cartridge.as_ref() {
                 Some(cartridge) => cartridge.cpu_read(address),
                 None => self.last_read,
             }, // Cartridge
-            _ => self.last_read,                       // Open Bus
-        }
+            _ => self.last_read,                                            // Open Bus
+        };
+        let value = self.read_patches.apply(address, value);
+
+        self.access_log.record_read(address, value);
+        value
     }
 
     pub(crate) fn cpu_write(&mut self, address: u16, value: u8) {
+        self.access_log.record_write(address, value);
+        self.record_write_event(address, value);
+
         match address {
             0x0000..=0x1FFF => self.ram.write(address, value), // RAM
             0x2000..=0x3FFF => todo!(),                        // PPU Registers
             0x4000..=0x4013 | 0x4015 | 0x4017 => todo!(),      // APU
             0x4014 => todo!(),                                 // OAM DMA
-            0x4016 => todo!(),                                 // Controller Strobe
+            0x4016 => {
+                for controller in &mut self.controllers {
+                    controller.write_strobe(value);
+                }
+            } // Controller Strobe
             0x4020..=0xFFFF => match self.cartridge.as_mut() {
                 Some(cartridge) => cartridge.cpu_write(address, value),
                 None => (),
@@ -119,4 +398,60 @@ impl Bus {
         let oam_address = self.ppu.get_oam_address();
         self.ppu.oam.dma_write(oam_address, &buffer);
     }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.chunk(b"BUS ", STATE_VERSION, |writer| {
+            writer.write_u8(self.last_read);
+            writer.write_bool(self.nmi_line);
+            writer.write_bool(self.nmi_edge_detected);
+            writer.write_bool(self.irq_apu_frame);
+            writer.write_bool(self.irq_apu_dmc);
+            writer.write_bool(self.irq_mapper);
+        });
+        writer.chunk(b"RAM ", STATE_VERSION, |writer| self.ram.save_state(writer));
+        writer.chunk(b"CTRL", STATE_VERSION, |writer| {
+            for controller in &self.controllers {
+                controller.save_state(writer);
+            }
+        });
+        self.ppu.save_state(writer);
+        if let Some(cartridge) = self.cartridge.as_ref() {
+            cartridge.save_state(writer);
+        }
+    }
+
+    pub(crate) fn check_state(&self, state: &StateReader) -> Result<(), &'static str> {
+        match self.cartridge.as_ref() {
+            Some(cartridge) => cartridge.check_state(state),
+            None if state.chunk(b"CART").is_some() => Err("Save state needs a cartridge"),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &StateReader) -> Result<(), &'static str> {
+        if let Some(mut chunk) = state.chunk(b"BUS ") {
+            chunk.expect_version(STATE_VERSION)?;
+            self.last_read = chunk.read_u8()?;
+            self.nmi_line = chunk.read_bool()?;
+            self.nmi_edge_detected = chunk.read_bool()?;
+            self.irq_apu_frame = chunk.read_bool()?;
+            self.irq_apu_dmc = chunk.read_bool()?;
+            self.irq_mapper = chunk.read_bool()?;
+        }
+        if let Some(mut chunk) = state.chunk(b"RAM ") {
+            chunk.expect_version(STATE_VERSION)?;
+            self.ram.load_state(&mut chunk)?;
+        }
+        if let Some(mut chunk) = state.chunk(b"CTRL") {
+            chunk.expect_version(STATE_VERSION)?;
+            for controller in &mut self.controllers {
+                controller.load_state(&mut chunk)?;
+            }
+        }
+        self.ppu.load_state(state)?;
+        if let Some(cartridge) = self.cartridge.as_mut() {
+            cartridge.load_state(state)?;
+        }
+        Ok(())
+    }
 }
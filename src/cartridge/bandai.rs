@@ -0,0 +1,246 @@
+// Bandai FCG boards, iNES mappers 16 and 159
+//
+// FCG-1/2 (submapper 4) decode their registers at $6000-$7FFF, the LZ93D50 (submapper 5 and
+// mapper 159) at $8000-$FFFF. Old iNES dumps don't say which, so both ranges are decoded.
+// Saves live in a serial EEPROM: 24C02 on mapper 16, 24C01 on mapper 159.
+
+use super::eeprom::{Eeprom, EepromKind};
+use super::mapper::{Mapper, Mirroring};
+use crate::state::{StateReader, StateWriter};
+
+const STATE_VERSION: u16 = 1;
+
+pub(super) struct Bandai {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_banks: [u8; 8],
+    prg_bank: u8,
+    mirroring: Mirroring,
+    irq_enabled: bool,
+    irq_counter: u16,
+    irq_latch: u16,
+    irq_pending: bool,
+    // FCG-1/2 write the counter directly, the LZ93D50 loads it from the latch
+    has_irq_latch: bool,
+    registers_at_6000: bool,
+    registers_at_8000: bool,
+    eeprom: Option<Eeprom>,
+}
+
+impl Bandai {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr: Vec<u8>,
+        mirroring: Mirroring,
+        mapper_id: u8,
+        submapper: u8,
+    ) -> Self {
+        let (registers_at_6000, registers_at_8000, eeprom) = match (mapper_id, submapper) {
+            (159, _) => (false, true, Some(EepromKind::C24C01)),
+            (_, 4) => (true, false, None),
+            (_, 5) => (false, true, Some(EepromKind::C24C02)),
+            _ => (true, true, Some(EepromKind::C24C02)),
+        };
+
+        Self {
+            prg_rom,
+            chr,
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            mirroring,
+            irq_enabled: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq_pending: false,
+            has_irq_latch: registers_at_8000,
+            registers_at_6000,
+            registers_at_8000,
+            eeprom: eeprom.map(Eeprom::new),
+        }
+    }
+
+    fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0x0..=0x7 => self.chr_banks[register as usize] = value,
+            0x8 => self.prg_bank = value & 0x0F,
+            0x9 => {
+                self.mirroring = match value & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0xA => {
+                self.irq_enabled = value & 0x01 != 0;
+                if self.has_irq_latch {
+                    self.irq_counter = self.irq_latch;
+                }
+                self.irq_pending = false;
+            }
+            0xB => self.write_irq_value(0x00FF, value as u16),
+            0xC => self.write_irq_value(0xFF00, (value as u16) << 8),
+            0xD => {
+                if let Some(eeprom) = &mut self.eeprom {
+                    eeprom.write((value >> 5) & 0x01, (value >> 6) & 0x01);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn write_irq_value(&mut self, mask: u16, value: u16) {
+        let target = if self.has_irq_latch {
+            &mut self.irq_latch
+        } else {
+            &mut self.irq_counter
+        };
+        *target = (*target & !mask) | value;
+    }
+
+    fn prg_index(&self, address: u16) -> usize {
+        let bank = if address >= 0xC000 {
+            // Last bank fixed
+            self.prg_rom.len() / 0x4000 - 1
+        } else {
+            self.prg_bank as usize
+        };
+        (bank * 0x4000 + (address & 0x3FFF) as usize) % self.prg_rom.len()
+    }
+
+    fn chr_index(&self, address: u16) -> usize {
+        let bank = self.chr_banks[(address >> 10) as usize] as usize;
+        (bank * 0x400 + (address & 0x3FF) as usize) % self.chr.len()
+    }
+}
+
+impl Mapper for Bandai {
+    fn cpu_read(&self, address: u16) -> u8 {
+        match address {
+            // EEPROM data out is bit 4, the rest is open bus
+            0x6000..=0x7FFF => self.eeprom.as_ref().map_or(0, |eeprom| eeprom.read() << 4),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if self.is_register(address) {
+            self.write_register(address & 0x000F, value);
+        }
+    }
+
+    fn is_register(&self, address: u16) -> bool {
+        match address {
+            0x6000..=0x7FFF => self.registers_at_6000,
+            0x8000..=0xFFFF => self.registers_at_8000,
+            _ => false,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        if address >= 0x8000 {
+            Some(self.prg_index(address))
+        } else {
+            None
+        }
+    }
+
+    fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    fn prg_rom_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_rom
+    }
+
+    fn cpu_poke(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            let index = self.prg_index(address);
+            self.prg_rom[index] = value;
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.chr[self.chr_index(address)],
+            _ => 0,
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if address < 0x2000 {
+            let index = self.chr_index(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.chunk(b"MAPR", STATE_VERSION, |writer| {
+            writer.write_bytes(&self.chr_banks);
+            writer.write_u8(self.prg_bank);
+            writer.write_u8(match self.mirroring {
+                Mirroring::Vertical => 0,
+                Mirroring::Horizontal => 1,
+                Mirroring::SingleScreenLower => 2,
+                _ => 3,
+            });
+            writer.write_bool(self.irq_enabled);
+            writer.write_u16(self.irq_counter);
+            writer.write_u16(self.irq_latch);
+            writer.write_bool(self.irq_pending);
+            writer.write_bytes(&self.chr);
+            if let Some(eeprom) = &self.eeprom {
+                eeprom.save_state(writer);
+            }
+        });
+    }
+
+    fn load_state(&mut self, state: &StateReader) -> Result<(), &'static str> {
+        let Some(mut chunk) = state.chunk(b"MAPR") else {
+            return Ok(());
+        };
+        chunk.expect_version(STATE_VERSION)?;
+
+        chunk.read_bytes(&mut self.chr_banks)?;
+        self.prg_bank = chunk.read_u8()? & 0x0F;
+        let mirroring = chunk.read_u8()?;
+        self.write_register(0x9, mirroring);
+        self.irq_enabled = chunk.read_bool()?;
+        self.irq_counter = chunk.read_u16()?;
+        self.irq_latch = chunk.read_u16()?;
+        self.irq_pending = chunk.read_bool()?;
+        chunk.read_bytes(&mut self.chr)?;
+        if let Some(eeprom) = &mut self.eeprom {
+            eeprom.load_state(&mut chunk)?;
+        }
+        Ok(())
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.eeprom.as_ref().map(Eeprom::data)
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        if let Some(eeprom) = &mut self.eeprom {
+            eeprom.load_data(data);
+        }
+    }
+
+    fn cpu_tick(&mut self) {
+        if self.irq_enabled {
+            if self.irq_counter == 0 {
+                self.irq_pending = true;
+            }
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+}
@@ -0,0 +1,266 @@
+// Serial I2C EEPROMs used for saves on Bandai boards
+//
+// 24C01: 128 bytes, the first byte after START is a 7-bit address plus R/W, sent LSB first.
+// 24C02: 256 bytes, standard device select byte (1010xxxR) then an 8-bit address, MSB first.
+// Bits are latched on the rising edge of SCL, the chip changes its output on the falling edge.
+
+use crate::state::{ChunkReader, StateWriter};
+
+#[derive(Copy, Clone, PartialEq)]
+pub(super) enum EepromKind {
+    C24C01,
+    C24C02,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum EepromMode {
+    Idle,
+    DeviceSelect,
+    Address,
+    Read,
+    Write,
+    SendAck,
+    WaitAck,
+}
+
+impl EepromMode {
+    fn to_u8(self) -> u8 {
+        match self {
+            EepromMode::Idle => 0,
+            EepromMode::DeviceSelect => 1,
+            EepromMode::Address => 2,
+            EepromMode::Read => 3,
+            EepromMode::Write => 4,
+            EepromMode::SendAck => 5,
+            EepromMode::WaitAck => 6,
+        }
+    }
+
+    fn from_u8(value: u8) -> Result<Self, &'static str> {
+        Ok(match value {
+            0 => EepromMode::Idle,
+            1 => EepromMode::DeviceSelect,
+            2 => EepromMode::Address,
+            3 => EepromMode::Read,
+            4 => EepromMode::Write,
+            5 => EepromMode::SendAck,
+            6 => EepromMode::WaitAck,
+            _ => return Err("Invalid EEPROM mode in save state"),
+        })
+    }
+}
+
+pub(super) struct Eeprom {
+    kind: EepromKind,
+    data: Vec<u8>,
+    mode: EepromMode,
+    next_mode: EepromMode,
+    device_select: u8,
+    address: u8,
+    shift_register: u8,
+    bit_counter: u8,
+    output: u8,
+    previous_scl: u8,
+    previous_sda: u8,
+}
+
+impl Eeprom {
+    pub(super) fn new(kind: EepromKind) -> Self {
+        let size = match kind {
+            EepromKind::C24C01 => 128,
+            EepromKind::C24C02 => 256,
+        };
+
+        Self {
+            kind,
+            data: vec![0; size],
+            mode: EepromMode::Idle,
+            next_mode: EepromMode::Idle,
+            device_select: 0,
+            address: 0,
+            shift_register: 0,
+            bit_counter: 0,
+            output: 1,
+            previous_scl: 0,
+            previous_sda: 0,
+        }
+    }
+
+    pub(super) fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub(super) fn load_data(&mut self, data: &[u8]) {
+        self.data.copy_from_slice(data);
+    }
+
+    // Current level of the SDA line as driven by the chip
+    pub(super) fn read(&self) -> u8 {
+        self.output
+    }
+
+    pub(super) fn write(&mut self, scl: u8, sda: u8) {
+        if self.previous_scl == 1 && scl == 1 && sda < self.previous_sda {
+            // START, SDA falls while SCL is held high
+            self.mode = match self.kind {
+                EepromKind::C24C01 => EepromMode::Address,
+                EepromKind::C24C02 => EepromMode::DeviceSelect,
+            };
+            self.bit_counter = 0;
+            self.output = 1;
+        } else if self.previous_scl == 1 && scl == 1 && sda > self.previous_sda {
+            // STOP, SDA rises while SCL is held high
+            self.mode = EepromMode::Idle;
+            self.output = 1;
+        } else if scl > self.previous_scl {
+            self.clock_rising(sda);
+        } else if scl < self.previous_scl {
+            self.clock_falling();
+        }
+
+        self.previous_scl = scl;
+        self.previous_sda = sda;
+    }
+
+    fn clock_rising(&mut self, sda: u8) {
+        match self.mode {
+            EepromMode::Idle => {}
+            EepromMode::DeviceSelect => {
+                self.device_select = self.shift_in(self.device_select, sda);
+            }
+            EepromMode::Address => match self.kind {
+                // Seven address bits, then the R/W bit decides what follows the ack
+                EepromKind::C24C01 if self.bit_counter == 7 => {
+                    self.bit_counter = 8;
+                    if sda == 1 {
+                        self.next_mode = EepromMode::Read;
+                        self.shift_register = self.data[self.address as usize];
+                    } else {
+                        self.next_mode = EepromMode::Write;
+                    }
+                }
+                _ => self.address = self.shift_in(self.address, sda),
+            },
+            EepromMode::Read => self.shift_out(),
+            EepromMode::Write => self.shift_register = self.shift_in(self.shift_register, sda),
+            EepromMode::SendAck => self.output = 0,
+            EepromMode::WaitAck => {
+                // The host acknowledging a read asks for the next byte
+                if sda == 0 {
+                    self.next_mode = EepromMode::Read;
+                    self.shift_register = self.data[self.address as usize];
+                } else {
+                    self.next_mode = EepromMode::Idle;
+                }
+            }
+        }
+    }
+
+    fn clock_falling(&mut self) {
+        let address_mask = (self.data.len() - 1) as u8;
+
+        match self.mode {
+            EepromMode::Idle => {}
+            EepromMode::DeviceSelect => {
+                if self.bit_counter == 8 {
+                    self.bit_counter = 0;
+                    self.output = 1;
+                    if self.device_select & 0xF0 == 0xA0 {
+                        self.mode = EepromMode::SendAck;
+                        if self.device_select & 0x01 != 0 {
+                            // Current address read
+                            self.next_mode = EepromMode::Read;
+                            self.shift_register = self.data[self.address as usize];
+                        } else {
+                            self.next_mode = EepromMode::Address;
+                        }
+                    } else {
+                        // Another device on the bus, ignore everything until the next START
+                        self.mode = EepromMode::Idle;
+                    }
+                }
+            }
+            EepromMode::Address => {
+                if self.bit_counter == 8 {
+                    self.address &= address_mask;
+                    self.bit_counter = 0;
+                    self.mode = EepromMode::SendAck;
+                    if self.kind == EepromKind::C24C02 {
+                        self.next_mode = EepromMode::Write;
+                    }
+                    self.output = 1;
+                }
+            }
+            EepromMode::Read => {
+                if self.bit_counter == 8 {
+                    self.mode = EepromMode::WaitAck;
+                    self.address = self.address.wrapping_add(1) & address_mask;
+                }
+            }
+            EepromMode::Write => {
+                if self.bit_counter == 8 {
+                    self.bit_counter = 0;
+                    self.mode = EepromMode::SendAck;
+                    self.next_mode = EepromMode::Write;
+                    self.data[self.address as usize] = self.shift_register;
+                    self.address = self.address.wrapping_add(1) & address_mask;
+                }
+            }
+            EepromMode::SendAck | EepromMode::WaitAck => {
+                self.mode = self.next_mode;
+                self.bit_counter = 0;
+                self.output = 1;
+            }
+        }
+    }
+
+    fn shift_in(&mut self, destination: u8, bit: u8) -> u8 {
+        if self.bit_counter >= 8 {
+            return destination;
+        }
+        let position = self.bit_position();
+        self.bit_counter += 1;
+        (destination & !(1 << position)) | (bit << position)
+    }
+
+    fn shift_out(&mut self) {
+        if self.bit_counter < 8 {
+            self.output = (self.shift_register >> self.bit_position()) & 0x01;
+            self.bit_counter += 1;
+        }
+    }
+
+    fn bit_position(&self) -> u8 {
+        match self.kind {
+            EepromKind::C24C01 => self.bit_counter,
+            EepromKind::C24C02 => 7 - self.bit_counter,
+        }
+    }
+
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+        writer.write_u8(self.mode.to_u8());
+        writer.write_u8(self.next_mode.to_u8());
+        writer.write_u8(self.device_select);
+        writer.write_u8(self.address);
+        writer.write_u8(self.shift_register);
+        writer.write_u8(self.bit_counter);
+        writer.write_u8(self.output);
+        writer.write_u8(self.previous_scl);
+        writer.write_u8(self.previous_sda);
+    }
+
+    pub(super) fn load_state(&mut self, chunk: &mut ChunkReader) -> Result<(), &'static str> {
+        chunk.read_bytes(&mut self.data)?;
+        self.mode = EepromMode::from_u8(chunk.read_u8()?)?;
+        self.next_mode = EepromMode::from_u8(chunk.read_u8()?)?;
+        self.device_select = chunk.read_u8()?;
+        self.address = chunk.read_u8()? & (self.data.len() - 1) as u8;
+        self.shift_register = chunk.read_u8()?;
+        self.bit_counter = chunk.read_u8()?.min(8);
+        self.output = chunk.read_u8()? & 0x01;
+        self.previous_scl = chunk.read_u8()? & 0x01;
+        self.previous_sda = chunk.read_u8()? & 0x01;
+        Ok(())
+    }
+}
@@ -1,14 +1,60 @@
+use crate::state::{StateReader, StateWriter};
+
 #[derive(Clone, Copy)]
-pub(super) enum Mirroring {
+pub(crate) enum Mirroring {
     Horizontal,
     Vertical,
     FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+impl Mirroring {
+    // Maps a $2000-$3EFF nametable address onto the 2K of console VRAM
+    pub(crate) fn vram_index(self, address: u16) -> u16 {
+        let table = (address >> 10) & 0x03;
+        let physical_table = match self {
+            Mirroring::Horizontal => table >> 1,
+            // Four-screen boards add 2K of cartridge VRAM, which isn't modelled yet
+            Mirroring::Vertical | Mirroring::FourScreen => table & 0x01,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+        };
+        (physical_table << 10) | (address & 0x03FF)
+    }
 }
 
-pub(super) trait Mapper {
+// Send so the whole emulator can run on its own thread
+pub(super) trait Mapper: Send {
     fn cpu_read(&self, address: u16) -> u8;
     fn cpu_write(&mut self, address: u16, value: u8);
+    // Offset into PRG-ROM behind a CPU address under the current banking, None when the
+    // address isn't mapped to ROM
+    fn prg_rom_offset(&self, address: u16) -> Option<usize>;
+    // All of PRG-ROM, for save states to carry debugger patches
+    fn prg_rom(&self) -> &[u8];
+    fn prg_rom_mut(&mut self) -> &mut [u8];
+    // Whether a CPU write to the address reaches a mapper register rather than RAM
+    fn is_register(&self, address: u16) -> bool {
+        address >= 0x8000
+    }
+    // Debugger writes into whatever PRG-ROM or PRG-RAM is mapped, never into registers
+    fn cpu_poke(&mut self, address: u16, value: u8);
     fn ppu_read(&self, address: u16) -> u8;
     fn ppu_write(&mut self, address: u16, value: u8);
     fn mirroring(&self) -> Mirroring;
+    // Bank registers and RAM, each mapper owns and versions its "MAPR" chunk
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, state: &StateReader) -> Result<(), &'static str>;
+    // Non-volatile memory (battery-backed PRG-RAM or EEPROM), None when the board has none
+    fn save_ram(&self) -> Option<&[u8]> {
+        None
+    }
+    // Sizes are checked by the cartridge before this is called
+    fn load_save_ram(&mut self, _data: &[u8]) {}
+    // Clocked once per CPU cycle, for boards with cycle-based IRQ counters
+    fn cpu_tick(&mut self) {}
+    fn irq_pending(&self) -> bool {
+        false
+    }
 }
@@ -1,21 +1,37 @@
+mod bandai;
+mod eeprom;
 mod mapper;
 mod nrom;
 
-use mapper::{Mapper, Mirroring};
+use bandai::Bandai;
+use mapper::Mapper;
+pub(crate) use mapper::Mirroring;
 use nrom::Nrom;
 
+use crate::md5;
+use crate::region::Region;
+use crate::state::{StateReader, StateWriter};
+
+// Version 2 adds the ROM's CRC32
+const STATE_VERSION: u16 = 2;
+const PATCH_STATE_VERSION: u16 = 1;
+
 // TODO: Create an error enum
 
 pub struct Cartridge {
     mapper: Box<dyn Mapper>,
-    // Currently only handling iNES
-    // Metadata from header
+    // Metadata from iNES or NES 2.0 header
     prg_rom_size: usize,
     chr_rom_size: usize,
     mapper_id: u8,
-    mirroring: Mirroring,
-    has_battery: bool,
-    has_trainer: bool,
+    // None for multi-region boards and headers that don't say
+    region: Option<Region>,
+    // Of PRG and CHR ROM without header or trainer, the usual way ROMs are identified
+    crc32: u32,
+    // Of the same bytes, how FCEUX identifies ROMs in movies
+    md5: [u8; 16],
+    // PRG-ROM as loaded, kept once the debugger pokes into it so a state can put it back
+    original_prg_rom: Option<Vec<u8>>,
 }
 
 impl Cartridge {
@@ -29,10 +45,64 @@ impl Cartridge {
         }
 
         // Parse header
-        let prg_banks = rom[4] as usize;
-        let chr_banks = rom[5] as usize;
         let flags6 = rom[6];
         let flags7 = rom[7];
+        let is_nes2 = flags7 & 0x0C == 0x08;
+        let has_battery = flags6 & 0x02 != 0;
+        let has_trainer = flags6 & 0x04 != 0;
+
+        let region = if is_nes2 {
+            match rom[12] & 0x03 {
+                0 => Some(Region::Ntsc),
+                1 => Some(Region::Pal),
+                3 => Some(Region::Dendy),
+                _ => None,
+            }
+        } else if rom[12..16].iter().all(|&b| b == 0) && rom[9] & 0x01 != 0 {
+            // Old iNES TV system bit, only trusted when the unused bytes weren't overwritten
+            Some(Region::Pal)
+        } else {
+            None
+        };
+
+        let mut prg_banks = rom[4] as usize;
+        let mut chr_banks = rom[5] as usize;
+        let mut submapper = 0;
+        let prg_ram_size;
+        let prg_nvram_size;
+
+        if is_nes2 {
+            if rom[8] & 0x0F != 0 {
+                return Err("Unsupported mapper");
+            }
+            submapper = rom[8] >> 4;
+
+            // Size MSB nibbles of 0xF switch to exponent-multiplier notation
+            if rom[9] & 0x0F == 0x0F || rom[9] >> 4 == 0x0F {
+                return Err("Unsupported NES 2.0 ROM size");
+            }
+            prg_banks |= ((rom[9] & 0x0F) as usize) << 8;
+            chr_banks |= ((rom[9] >> 4) as usize) << 8;
+
+            // Shift counts, 0 means none, otherwise 64 << shift bytes
+            let shifted_size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+            prg_ram_size = shifted_size(rom[10] & 0x0F);
+            prg_nvram_size = shifted_size(rom[10] >> 4);
+        } else {
+            // Byte 8 is PRG-RAM in 8K units, with 0 meaning 8K for compatibility
+            let size = (rom[8].max(1) as usize) * 8192;
+            if has_battery {
+                prg_ram_size = 0;
+                prg_nvram_size = size;
+            } else {
+                prg_ram_size = size;
+                prg_nvram_size = 0;
+            }
+        }
+
+        if prg_banks == 0 {
+            return Err("ROM has no PRG data");
+        }
 
         let prg_rom_size = prg_banks * 16384;
         let chr_rom_size = chr_banks * 8192;
@@ -46,9 +116,6 @@ impl Cartridge {
             Mirroring::Horizontal
         };
 
-        let has_battery = flags6 & 0x02 != 0;
-        let has_trainer = flags6 & 0x04 != 0;
-
         // Calculate ROM data offsets
         let trainer_size = if has_trainer { 512 } else { 0 };
         let prg_start = 16 + trainer_size;
@@ -61,6 +128,9 @@ impl Cartridge {
             return Err("ROM file truncated");
         }
 
+        let crc32 = crc32fast::hash(&rom[prg_start..chr_end]);
+        let md5 = md5::hash(&rom[prg_start..chr_end]);
+
         // Extract ROM data
         let prg_rom = rom[prg_start..prg_end].to_vec();
         let chr_rom = if chr_rom_size > 0 {
@@ -71,7 +141,17 @@ impl Cartridge {
 
         // Create mapper
         let mapper: Box<dyn Mapper> = match mapper_id {
-            0 => Box::new(Nrom::new(prg_rom, chr_rom, mirroring)),
+            // A board has a single PRG-RAM chip, battery-backed or not
+            0 => Box::new(Nrom::new(
+                prg_rom,
+                chr_rom,
+                mirroring,
+                prg_ram_size.max(prg_nvram_size),
+                has_battery || prg_nvram_size > 0,
+            )),
+            16 | 159 => Box::new(Bandai::new(
+                prg_rom, chr_rom, mirroring, mapper_id, submapper,
+            )),
             _ => return Err("Unsupported mapper"),
         };
 
@@ -80,9 +160,10 @@ impl Cartridge {
             prg_rom_size,
             chr_rom_size,
             mapper_id,
-            mirroring,
-            has_battery,
-            has_trainer,
+            region,
+            crc32,
+            md5,
+            original_prg_rom: None,
         })
     }
 
@@ -94,6 +175,47 @@ impl Cartridge {
         self.mapper.cpu_write(address, value);
     }
 
+    pub(crate) fn region(&self) -> Option<Region> {
+        self.region
+    }
+
+    pub(crate) fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    pub(crate) fn md5(&self) -> [u8; 16] {
+        self.md5
+    }
+
+    pub(crate) fn prg_rom_size(&self) -> usize {
+        self.prg_rom_size
+    }
+
+    // Zero for boards with CHR-RAM
+    pub(crate) fn chr_rom_size(&self) -> usize {
+        self.chr_rom_size
+    }
+
+    pub(crate) fn is_mapper_register(&self, address: u16) -> bool {
+        self.mapper.is_register(address)
+    }
+
+    pub(crate) fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.mapper.prg_rom_offset(address)
+    }
+
+    pub(crate) fn cpu_poke(&mut self, address: u16, value: u8) {
+        if self.original_prg_rom.is_none() && self.mapper.prg_rom_offset(address).is_some() {
+            self.original_prg_rom = Some(self.mapper.prg_rom().to_vec());
+        }
+        self.mapper.cpu_poke(address, value);
+    }
+
+    // Current nametable arrangement, mappers can switch it at runtime
+    pub(crate) fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
     pub fn ppu_read(&self, address: u16) -> u8 {
         self.mapper.ppu_read(address)
     }
@@ -101,4 +223,88 @@ impl Cartridge {
     pub fn ppu_write(&mut self, address: u16, value: u8) {
         self.mapper.ppu_write(address, value);
     }
+
+    // Contents of battery-backed PRG-RAM or EEPROM, as stored in a .sav file
+    pub(crate) fn save_ram(&self) -> Option<&[u8]> {
+        self.mapper.save_ram()
+    }
+
+    pub(crate) fn load_save_ram(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        match self.mapper.save_ram() {
+            None => Err("Cartridge has no save RAM"),
+            Some(save_ram) if save_ram.len() != data.len() => Err("Save RAM size mismatch"),
+            Some(_) => {
+                self.mapper.load_save_ram(data);
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn cpu_tick(&mut self) {
+        self.mapper.cpu_tick();
+    }
+
+    pub(crate) fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending()
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.chunk(b"CART", STATE_VERSION, |writer| {
+            writer.write_u8(self.mapper_id);
+            writer.write_u32(self.prg_rom_size as u32);
+            writer.write_u32(self.chr_rom_size as u32);
+            writer.write_u32(self.crc32);
+        });
+        self.mapper.save_state(writer);
+
+        // The whole of a patched PRG-ROM, patches are rare enough that this stays simple
+        if self.original_prg_rom.is_some() {
+            writer.chunk(b"PRGP", PATCH_STATE_VERSION, |writer| {
+                writer.write_bytes(self.mapper.prg_rom());
+            });
+        }
+    }
+
+    // Checked before anything is loaded so a state for another cartridge is rejected cleanly
+    pub(crate) fn check_state(&self, state: &StateReader) -> Result<(), &'static str> {
+        let Some(mut chunk) = state.chunk(b"CART") else {
+            return Err("Save state has no cartridge");
+        };
+        chunk.expect_version(STATE_VERSION)?;
+
+        let mut matches = chunk.read_u8()? == self.mapper_id
+            && chunk.read_u32()? as usize == self.prg_rom_size
+            && chunk.read_u32()? as usize == self.chr_rom_size;
+        // Older states only tell boards apart by size
+        if matches && chunk.version() >= 2 {
+            matches = chunk.read_u32()? == self.crc32;
+        }
+
+        if matches {
+            Ok(())
+        } else {
+            Err("Save state is for a different cartridge")
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &StateReader) -> Result<(), &'static str> {
+        self.mapper.load_state(state)?;
+
+        // Unlike other chunks, a missing one means the ROM was unpatched when the state was saved
+        match state.chunk(b"PRGP") {
+            Some(mut chunk) => {
+                chunk.expect_version(PATCH_STATE_VERSION)?;
+                if self.original_prg_rom.is_none() {
+                    self.original_prg_rom = Some(self.mapper.prg_rom().to_vec());
+                }
+                chunk.read_bytes(self.mapper.prg_rom_mut())?;
+            }
+            None => {
+                if let Some(original) = self.original_prg_rom.take() {
+                    self.mapper.prg_rom_mut().copy_from_slice(&original);
+                }
+            }
+        }
+        Ok(())
+    }
 }
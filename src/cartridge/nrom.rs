@@ -1,15 +1,25 @@
 use super::mapper::{Mapper, Mirroring};
+use crate::state::{StateReader, StateWriter};
+
+const STATE_VERSION: u16 = 1;
 
 pub(super) struct Nrom {
     prg_rom: Vec<u8>,
     chr: Vec<u8>,
-    prg_ram: [u8; 8192],
+    prg_ram: Vec<u8>,
+    has_battery: bool,
     mirroring: Mirroring,
     prg_mask: usize,
 }
 
 impl Nrom {
-    pub fn new(prg_rom: Vec<u8>, chr: Vec<u8>, mirroring: Mirroring) -> Self {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr: Vec<u8>,
+        mirroring: Mirroring,
+        prg_ram_size: usize,
+        has_battery: bool,
+    ) -> Self {
         let prg_mask = if prg_rom.len() <= 0x4000 {
             0x3FFF // Mirror
         } else {
@@ -19,17 +29,30 @@ impl Nrom {
         Self {
             prg_rom,
             chr,
-            prg_ram: [0; 8192],
+            prg_ram: vec![0; prg_ram_size],
+            has_battery,
             mirroring,
             prg_mask,
         }
     }
+
+    // Smaller PRG-RAM chips (Family Basic has 2K or 4K) mirror through $6000-$7FFF
+    fn prg_ram_index(&self, address: u16) -> Option<usize> {
+        if self.prg_ram.is_empty() {
+            None
+        } else {
+            Some((address - 0x6000) as usize % self.prg_ram.len())
+        }
+    }
 }
 
 impl Mapper for Nrom {
     fn cpu_read(&self, address: u16) -> u8 {
         match address {
-            0x6000..=0x7FFF => self.prg_ram[(address - 0x6000) as usize],
+            0x6000..=0x7FFF => match self.prg_ram_index(address) {
+                Some(index) => self.prg_ram[index],
+                None => 0,
+            },
             0x8000..=0xFFFF => {
                 let index = (address - 0x8000) as usize & self.prg_mask;
                 self.prg_rom[index]
@@ -40,11 +63,41 @@ impl Mapper for Nrom {
 
     fn cpu_write(&mut self, address: u16, value: u8) {
         match address {
-            0x6000..=0x7FFF => self.prg_ram[(address - 0x6000) as usize] = value,
+            0x6000..=0x7FFF => {
+                if let Some(index) = self.prg_ram_index(address) {
+                    self.prg_ram[index] = value;
+                }
+            }
             _ => {} // PRG ROM is read-only, writes ignored
         }
     }
 
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        match address {
+            0x8000..=0xFFFF => Some((address - 0x8000) as usize & self.prg_mask),
+            _ => None,
+        }
+    }
+
+    fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    fn prg_rom_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_rom
+    }
+
+    fn cpu_poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF => self.cpu_write(address, value),
+            0x8000..=0xFFFF => {
+                let index = (address - 0x8000) as usize & self.prg_mask;
+                self.prg_rom[index] = value;
+            }
+            _ => {}
+        }
+    }
+
     fn ppu_read(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x1FFF => self.chr[address as usize],
@@ -61,4 +114,34 @@ impl Mapper for Nrom {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.chunk(b"MAPR", STATE_VERSION, |writer| {
+            writer.write_bytes(&self.prg_ram);
+            // CHR is written through ppu_write, so it is saved for CHR-RAM boards
+            writer.write_bytes(&self.chr);
+        });
+    }
+
+    fn load_state(&mut self, state: &StateReader) -> Result<(), &'static str> {
+        let Some(mut chunk) = state.chunk(b"MAPR") else {
+            return Ok(());
+        };
+        chunk.expect_version(STATE_VERSION)?;
+
+        chunk.read_bytes(&mut self.prg_ram)?;
+        chunk.read_bytes(&mut self.chr)
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        if self.has_battery && !self.prg_ram.is_empty() {
+            Some(&self.prg_ram)
+        } else {
+            None
+        }
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        self.prg_ram.copy_from_slice(data);
+    }
 }
@@ -0,0 +1,84 @@
+// Game Genie code decoding
+//
+// Each letter is a nibble, the bits are scrambled across the code. Six letter codes substitute
+// a value, eight letter codes only substitute when the ROM holds the compare value, which keeps
+// them from breaking other banks mapped at the same address.
+
+const ALPHABET: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+pub(super) struct GameGenieCode {
+    pub(super) address: u16,
+    pub(super) value: u8,
+    pub(super) compare: Option<u8>,
+}
+
+pub(super) fn is_game_genie(code: &str) -> bool {
+    (code.len() == 6 || code.len() == 8) && code.bytes().all(|c| ALPHABET.contains(&c))
+}
+
+pub(super) fn decode(code: &str) -> Result<GameGenieCode, &'static str> {
+    if !is_game_genie(code) {
+        return Err("Invalid Game Genie code");
+    }
+
+    let mut n = [0u16; 8];
+    for (nibble, character) in n.iter_mut().zip(code.bytes()) {
+        *nibble = ALPHABET.iter().position(|&c| c == character).unwrap_or(0) as u16;
+    }
+
+    let address = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+
+    let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+
+    if code.len() == 6 {
+        Ok(GameGenieCode {
+            address,
+            value: (value | (n[5] & 8)) as u8,
+            compare: None,
+        })
+    } else {
+        let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+        Ok(GameGenieCode {
+            address,
+            value: (value | (n[7] & 8)) as u8,
+            compare: Some(compare as u8),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(code: &str) -> (u16, u8, Option<u8>) {
+        let code = decode(code).unwrap();
+        (code.address, code.value, code.compare)
+    }
+
+    #[test]
+    fn six_letter_codes() {
+        assert_eq!(decoded("SXIOPO"), (0x91D9, 0xAD, None));
+        assert_eq!(decoded("GOSSIP"), (0xD1DD, 0x14, None));
+    }
+
+    #[test]
+    fn eight_letter_codes() {
+        assert_eq!(decoded("ZEXPYGLA"), (0x94A7, 0x02, Some(0x03)));
+        // The sixth letter's high bit moves from the value to the compare
+        assert_eq!(decoded("SXIOPOZA"), (0x91D9, 0xA5, Some(0x0A)));
+    }
+
+    #[test]
+    fn invalid_codes() {
+        for code in ["", "SXIOP", "SXIOPOZ", "SXIOPOB", "SXIOPOZAA"] {
+            assert!(decode(code).is_err(), "{code} decoded");
+        }
+    }
+}
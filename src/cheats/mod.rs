@@ -0,0 +1,280 @@
+mod game_genie;
+
+use crate::bus::Bus;
+
+// Accepted codes:
+//   Game Genie      6 or 8 letters, e.g. SXIOPO or AEUOZGAP
+//   RAM freeze      6 hex digits AAAAVV (Pro Action Replay style), rewritten every frame
+//   Patch           AAAA:VV or AAAA:VV:CC, substitutes CPU reads, only when the byte is CC
+#[derive(Copy, Clone, PartialEq)]
+pub enum CheatKind {
+    GameGenie,
+    RamFreeze,
+    Patch,
+}
+
+pub struct Cheat {
+    id: u32,
+    kind: CheatKind,
+    code: String,
+    name: String,
+    address: u16,
+    value: u8,
+    compare: Option<u8>,
+    enabled: bool,
+}
+
+impl Cheat {
+    fn parse(id: u32, code: &str, name: &str) -> Result<Self, &'static str> {
+        let code = code.trim().to_ascii_uppercase();
+
+        let (kind, address, value, compare) = if code.contains(':') {
+            let mut fields = code.split(':').map(parse_hex);
+            let address = fields.next().flatten().ok_or("Invalid patch address")?;
+            let value = fields.next().flatten().ok_or("Invalid patch value")?;
+            let compare = match fields.next() {
+                Some(compare) => Some(compare.ok_or("Invalid patch compare value")?),
+                None => None,
+            };
+            if fields.next().is_some() || value > 0xFF || compare.is_some_and(|c| c > 0xFF) {
+                return Err("Patches are address:value[:compare]");
+            }
+            (
+                CheatKind::Patch,
+                address,
+                value as u8,
+                compare.map(|c| c as u8),
+            )
+        } else if game_genie::is_game_genie(&code) {
+            let decoded = game_genie::decode(&code)?;
+            (
+                CheatKind::GameGenie,
+                decoded.address,
+                decoded.value,
+                decoded.compare,
+            )
+        } else if code.len() == 6 && code.is_ascii() {
+            let address = parse_hex(&code[0..4]).ok_or("Invalid RAM freeze code")?;
+            let value = parse_hex(&code[4..6]).ok_or("Invalid RAM freeze code")?;
+            if !matches!(address, 0x0000..=0x1FFF | 0x6000..=0x7FFF) {
+                return Err("RAM freezes must target RAM or PRG-RAM");
+            }
+            (CheatKind::RamFreeze, address, value as u8, None)
+        } else {
+            return Err("Unrecognized cheat code");
+        };
+
+        Ok(Self {
+            id,
+            kind,
+            code,
+            name: name.trim().to_owned(),
+            address,
+            value,
+            compare,
+            enabled: true,
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn kind(&self) -> CheatKind {
+        self.kind
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    pub fn value(&self) -> u8 {
+        self.value
+    }
+
+    pub fn compare(&self) -> Option<u8> {
+        self.compare
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    let text = text.trim().trim_start_matches('$');
+    if text.is_empty() || text.len() > 4 {
+        return None;
+    }
+    u16::from_str_radix(text, 16).ok()
+}
+
+#[derive(Copy, Clone)]
+struct ReadPatch {
+    address: u16,
+    value: u8,
+    compare: Option<u8>,
+}
+
+// Lives on the bus: patched addresses as a bitmap, so unpatched reads cost a single bit test
+pub(crate) struct ReadPatches {
+    map: [u64; 1024],
+    patches: Vec<ReadPatch>,
+}
+
+impl ReadPatches {
+    pub(crate) fn new() -> Self {
+        Self {
+            map: [0; 1024],
+            patches: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn apply(&self, address: u16, value: u8) -> u8 {
+        if self.map[(address >> 6) as usize] & (1 << (address & 0x3F)) == 0 {
+            return value;
+        }
+
+        self.patches
+            .iter()
+            .find(|p| p.address == address && p.compare.is_none_or(|c| c == value))
+            .map_or(value, |p| p.value)
+    }
+}
+
+pub(crate) struct CheatEngine {
+    cheats: Vec<Cheat>,
+    next_id: u32,
+}
+
+impl CheatEngine {
+    pub(crate) fn new() -> Self {
+        Self {
+            cheats: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    pub(crate) fn add(
+        &mut self,
+        code: &str,
+        name: &str,
+        bus: &mut Bus,
+    ) -> Result<u32, &'static str> {
+        let cheat = Cheat::parse(self.next_id, code, name)?;
+        self.next_id += 1;
+        self.cheats.push(cheat);
+        self.sync_read_patches(bus);
+        Ok(self.next_id - 1)
+    }
+
+    pub(crate) fn remove(&mut self, id: u32, bus: &mut Bus) -> bool {
+        let length = self.cheats.len();
+        self.cheats.retain(|cheat| cheat.id != id);
+        self.sync_read_patches(bus);
+        self.cheats.len() != length
+    }
+
+    pub(crate) fn set_enabled(&mut self, id: u32, enabled: bool, bus: &mut Bus) {
+        if let Some(cheat) = self.cheats.iter_mut().find(|c| c.id == id) {
+            cheat.enabled = enabled;
+            self.sync_read_patches(bus);
+        }
+    }
+
+    pub(crate) fn rename(&mut self, id: u32, name: &str) {
+        if let Some(cheat) = self.cheats.iter_mut().find(|c| c.id == id) {
+            cheat.name = name.to_owned();
+        }
+    }
+
+    pub(crate) fn clear(&mut self, bus: &mut Bus) {
+        self.cheats.clear();
+        self.sync_read_patches(bus);
+    }
+
+    pub(crate) fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    // RAM freezes are rewritten once per frame, the game can still change them in between
+    pub(crate) fn apply_freezes(&self, bus: &mut Bus) {
+        for cheat in &self.cheats {
+            if cheat.enabled && cheat.kind == CheatKind::RamFreeze {
+                bus.write_frozen(cheat.address, cheat.value);
+            }
+        }
+    }
+
+    // One cheat per line: '+' or '-' for enabled, the code, then the name
+    pub(crate) fn export(&self) -> String {
+        let mut text = String::new();
+        for cheat in &self.cheats {
+            text.push(if cheat.enabled { '+' } else { '-' });
+            text.push_str(&cheat.code);
+            if !cheat.name.is_empty() {
+                text.push(' ');
+                text.push_str(&cheat.name);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    // Replaces the current list, which is left untouched if any line is invalid
+    pub(crate) fn import(&mut self, text: &str, bus: &mut Bus) -> Result<(), &'static str> {
+        let mut cheats = Vec::new();
+        let mut next_id = self.next_id;
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (enabled, line) = match line.as_bytes()[0] {
+                b'+' => (true, &line[1..]),
+                b'-' => (false, &line[1..]),
+                _ => (true, line),
+            };
+            let (code, name) = line.split_once(' ').unwrap_or((line, ""));
+
+            let mut cheat = Cheat::parse(next_id, code, name)?;
+            cheat.enabled = enabled;
+            next_id += 1;
+            cheats.push(cheat);
+        }
+
+        self.cheats = cheats;
+        self.next_id = next_id;
+        self.sync_read_patches(bus);
+        Ok(())
+    }
+
+    // Rebuild the bus-side patch list from the enabled substitution cheats
+    fn sync_read_patches(&self, bus: &mut Bus) {
+        let read_patches = bus.read_patches_mut();
+        read_patches.map = [0; 1024];
+        read_patches.patches.clear();
+
+        for cheat in self.cheats.iter().filter(|c| c.enabled) {
+            if cheat.kind == CheatKind::RamFreeze {
+                continue;
+            }
+            read_patches.patches.push(ReadPatch {
+                address: cheat.address,
+                value: cheat.value,
+                compare: cheat.compare,
+            });
+            read_patches.map[(cheat.address >> 6) as usize] |= 1 << (cheat.address & 0x3F);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// Code/Data Logger, in the FCEUX .cdl layout: one flag byte per PRG-ROM byte followed by one
+// per CHR-ROM byte. Entries are ROM offsets, so every bank is logged separately.
+//
+// PRG flags  xPdcAADC
+//   C   executed as code
+//   D   read as data
+//   AA  which 8K CPU window ($8000/$A000/$C000/$E000) the byte was last accessed through
+//   c   code reached through an indirect jump
+//   d   data read through an indirect pointer
+//   P   fetched by the DMC as PCM samples
+// CHR flags  xxxxxxRD
+//   D   fetched for rendering
+//   R   read by the CPU through $2007
+pub struct CodeDataLog {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+}
+
+impl CodeDataLog {
+    pub const CODE: u8 = 0x01;
+    pub const DATA: u8 = 0x02;
+    pub const INDIRECT_CODE: u8 = 0x10;
+    pub const INDIRECT_DATA: u8 = 0x20;
+    pub const PCM: u8 = 0x40;
+
+    pub const CHR_RENDERED: u8 = 0x01;
+    pub const CHR_READ: u8 = 0x02;
+
+    pub(crate) fn new(prg_rom_size: usize, chr_rom_size: usize) -> Self {
+        Self {
+            prg: vec![0; prg_rom_size],
+            chr: vec![0; chr_rom_size],
+        }
+    }
+
+    #[inline]
+    pub(crate) fn log_prg(&mut self, offset: usize, address: u16, flags: u8) {
+        if let Some(entry) = self.prg.get_mut(offset) {
+            let window = (((address >> 13) & 0x03) as u8) << 2;
+            *entry = (*entry & !0x0C) | window | flags;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.prg.fill(0);
+        self.chr.fill(0);
+    }
+
+    pub fn prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    pub fn prg_flags(&self, offset: usize) -> u8 {
+        self.prg.get(offset).copied().unwrap_or(0)
+    }
+
+    pub fn chr_flags(&self, offset: usize) -> u8 {
+        self.chr.get(offset).copied().unwrap_or(0)
+    }
+
+    pub fn code_bytes(&self) -> usize {
+        self.prg.iter().filter(|&&f| f & Self::CODE != 0).count()
+    }
+
+    pub fn data_bytes(&self) -> usize {
+        self.prg
+            .iter()
+            .filter(|&&f| f & (Self::DATA | Self::PCM) != 0)
+            .count()
+    }
+
+    pub fn logged_prg_bytes(&self) -> usize {
+        self.prg
+            .iter()
+            .filter(|&&f| f & (Self::CODE | Self::DATA | Self::PCM) != 0)
+            .count()
+    }
+
+    pub fn logged_chr_bytes(&self) -> usize {
+        self.chr
+            .iter()
+            .filter(|&&f| f & (Self::CHR_RENDERED | Self::CHR_READ) != 0)
+            .count()
+    }
+
+    // The .cdl file contents
+    pub fn export(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.prg.len() + self.chr.len());
+        data.extend_from_slice(&self.prg);
+        data.extend_from_slice(&self.chr);
+        data
+    }
+
+    // Logs are merged, so a session can continue from an earlier file
+    pub(crate) fn import(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        if data.len() != self.prg.len() + self.chr.len() {
+            return Err("Code/data log size does not match the cartridge");
+        }
+
+        let (prg, chr) = data.split_at(self.prg.len());
+        for (entry, flags) in self.prg.iter_mut().zip(prg) {
+            *entry |= flags;
+        }
+        for (entry, flags) in self.chr.iter_mut().zip(chr) {
+            *entry |= flags;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use crate::state::{ChunkReader, StateWriter};
+
+// Standard controller button bits, in the order they are shifted out of $4016/$4017
+#[derive(Copy, Clone, PartialEq)]
+pub enum Button {
+    A = 0x01,
+    B = 0x02,
+    Select = 0x04,
+    Start = 0x08,
+    Up = 0x10,
+    Down = 0x20,
+    Left = 0x40,
+    Right = 0x80,
+}
+
+pub(crate) struct Controller {
+    buttons: u8,
+    shift_register: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    pub(crate) fn new() -> Self {
+        Self {
+            buttons: 0,
+            shift_register: 0,
+            strobe: false,
+        }
+    }
+
+    pub(crate) fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub(crate) fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+    }
+
+    pub(crate) fn write_strobe(&mut self, value: u8) {
+        self.strobe = value & 0x01 != 0;
+        if self.strobe {
+            self.shift_register = self.buttons;
+        }
+    }
+
+    pub(crate) fn read(&mut self) -> u8 {
+        // While strobe is high the register keeps reloading, so A is returned every time
+        if self.strobe {
+            return self.buttons & 0x01;
+        }
+
+        let bit = self.shift_register & 0x01;
+        // Official controllers return 1 once all eight buttons have been read
+        self.shift_register = (self.shift_register >> 1) | 0x80;
+        bit
+    }
+
+    // The bit the next read would return, without shifting
+    pub(crate) fn peek(&self) -> u8 {
+        if self.strobe {
+            self.buttons & 0x01
+        } else {
+            self.shift_register & 0x01
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.buttons);
+        writer.write_u8(self.shift_register);
+        writer.write_bool(self.strobe);
+    }
+
+    pub(crate) fn load_state(&mut self, chunk: &mut ChunkReader) -> Result<(), &'static str> {
+        self.buttons = chunk.read_u8()?;
+        self.shift_register = chunk.read_u8()?;
+        self.strobe = chunk.read_bool()?;
+        Ok(())
+    }
+}
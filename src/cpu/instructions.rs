@@ -1,4 +1,5 @@
-use super::{Cpu, opcodes::AddressingMode, registers};
+use super::{CallKind, ControlFlow, Cpu, opcodes::AddressingMode, registers};
+use crate::code_data_log::CodeDataLog;
 
 impl Cpu {
     // Helpers for instruction handlers
@@ -33,6 +34,8 @@ impl Cpu {
             AddressingMode::Immediate => {
                 let address = self.registers.program_counter;
                 self.registers.increment_pc();
+                // The operand is part of the instruction, not data
+                self.data_flags = self.code_flags;
                 address
             }
 
@@ -110,6 +113,7 @@ impl Cpu {
                 // reads at zero page hence casts to u16
                 let low = self.read_bus(pointer as u16);
                 let high = self.read_bus(pointer.wrapping_add(1) as u16);
+                self.data_flags = CodeDataLog::DATA | CodeDataLog::INDIRECT_DATA;
                 u16::from_le_bytes([low, high])
             }
 
@@ -118,6 +122,7 @@ impl Cpu {
                 let low = self.read_bus(pointer as u16);
                 let high = self.read_bus(pointer.wrapping_add(1) as u16);
                 let base = u16::from_le_bytes([low, high]);
+                self.data_flags = CodeDataLog::DATA | CodeDataLog::INDIRECT_DATA;
                 let final_address = base.wrapping_add(self.registers.index_y as u16);
                 let crossed =
                     (registers::PAGE_MASK & base) != (registers::PAGE_MASK & final_address);
@@ -190,7 +195,8 @@ impl Cpu {
         // To get PC + 2 including original BRK fetch
         self.fetch_byte();
 
-        self.push_word(self.registers.program_counter);
+        let return_address = self.registers.program_counter;
+        self.push_word(return_address);
 
         self.push_byte(self.registers.status_for_stack_push(true));
 
@@ -200,8 +206,10 @@ impl Cpu {
         // this will run on the 4th cycle and check for hijacking
         if self.bus_mut().take_nmi_edge() {
             self.load_nmi_vector();
+            self.record_call(CallKind::Nmi, return_address);
         } else {
             self.load_irq_vector();
+            self.record_call(CallKind::Break, return_address);
         }
 
         // Then we add the remainder of the cycle burn
@@ -288,8 +296,10 @@ impl Cpu {
         self.push_word(self.registers.program_counter);
 
         let target_high = self.fetch_byte();
+        let return_address = self.registers.program_counter;
 
         self.registers.program_counter = u16::from_le_bytes([target_low, target_high]);
+        self.record_call(CallKind::Subroutine, return_address);
     }
 
     pub(super) fn and(&mut self) {
@@ -375,6 +385,9 @@ impl Cpu {
 
         let program_counter_value = self.pop_word();
         self.registers.program_counter = program_counter_value;
+        self.control_flow = Some(ControlFlow::Return {
+            from_interrupt: true,
+        });
     }
 
     pub(super) fn eor(&mut self) {
@@ -438,6 +451,10 @@ impl Cpu {
     pub(super) fn jmp(&mut self) {
         let address = self.get_operand_address(self.opcode_record.addressing_mode);
         self.registers.program_counter = address;
+
+        if self.opcode_record.addressing_mode == AddressingMode::Indirect {
+            self.next_code_flags = CodeDataLog::CODE | CodeDataLog::INDIRECT_CODE;
+        }
     }
 
     pub(super) fn bvc(&mut self) {
@@ -453,6 +470,9 @@ impl Cpu {
         let address = self.pop_word();
         self.registers.program_counter = address;
         self.registers.increment_pc();
+        self.control_flow = Some(ControlFlow::Return {
+            from_interrupt: false,
+        });
     }
 
     pub(super) fn adc(&mut self) {
@@ -1,9 +1,47 @@
 mod instructions;
 mod opcodes;
 mod registers;
+pub(crate) use self::opcodes::AddressingMode;
 use self::opcodes::OpcodeRecord;
-use self::registers::CpuRegisters;
+pub use self::registers::CpuRegisters;
 use crate::bus::Bus;
+use crate::code_data_log::CodeDataLog;
+use crate::state::{StateReader, StateWriter};
+
+const STATE_VERSION: u16 = 1;
+
+// Opcode metadata for the disassembler
+pub(crate) fn opcode_mnemonic(opcode: u8) -> &'static str {
+    opcodes::OPCODE_TABLE[opcode as usize].mnemonic
+}
+
+pub(crate) fn opcode_addressing_mode(opcode: u8) -> AddressingMode {
+    opcodes::OPCODE_TABLE[opcode as usize].addressing_mode
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum CallKind {
+    Subroutine,
+    Break,
+    Irq,
+    Nmi,
+}
+
+// Subroutine and interrupt entries and exits, picked up after each tick by the profiler and
+// the call stack
+#[derive(Copy, Clone)]
+pub(crate) enum ControlFlow {
+    // return_address is where the matching RTS or RTI should resume
+    Call {
+        kind: CallKind,
+        target: u16,
+        return_address: u16,
+    },
+    // RTS or RTI, the stack pointer tells which calls were unwound
+    Return {
+        from_interrupt: bool,
+    },
+}
 
 pub(crate) struct Cpu {
     registers: CpuRegisters,
@@ -12,6 +50,8 @@ pub(crate) struct Cpu {
     total_cycles: u64,
     opcode_handler: Option<fn(&mut Cpu)>,
     opcode_record: &'static OpcodeRecord,
+    // Index of opcode_record, so the pending handler can be saved without the fn pointer
+    opcode: u8,
     halted: bool,
     // Latch for handling the flag delay of CLI and PLP since we're not tracking IRQ per cycle
     interrupt_disable_clear_delay: bool,
@@ -19,6 +59,13 @@ pub(crate) struct Cpu {
     interrupt_disable_set_delay: bool,
     // Latch for checking for NMI hijack during IRQ handling
     irq_vector_pending: bool,
+    // Code/data log flags for the current instruction's fetches and data reads
+    code_flags: u8,
+    next_code_flags: u8,
+    data_flags: u8,
+    control_flow: Option<ControlFlow>,
+    // Address of the opcode being executed
+    instruction_address: u16,
 }
 
 impl Cpu {
@@ -35,10 +82,16 @@ impl Cpu {
             total_cycles: 0,
             opcode_handler: None,
             opcode_record: &opcodes::OPCODE_TABLE[0xEA],
+            opcode: 0xEA,
             halted: false,
             interrupt_disable_clear_delay: false,
             interrupt_disable_set_delay: false,
             irq_vector_pending: false,
+            code_flags: CodeDataLog::CODE,
+            next_code_flags: CodeDataLog::CODE,
+            data_flags: CodeDataLog::DATA,
+            control_flow: None,
+            instruction_address: 0,
         }
     }
 
@@ -64,11 +117,13 @@ impl Cpu {
     fn execute_nmi(&mut self) {
         self.read_bus(self.registers.program_counter); // Dummy read
         self.read_bus(self.registers.program_counter); // Dummy read
-        self.push_word(self.registers.program_counter);
+        let return_address = self.registers.program_counter;
+        self.push_word(return_address);
         self.push_byte(self.registers.status_for_stack_push(false));
         self.registers.set_interrupt_disable(true);
         self.load_nmi_vector();
         self.cycle_counter = 7;
+        self.record_call(CallKind::Nmi, return_address);
     }
 
     fn execute_irq(&mut self) {
@@ -83,6 +138,14 @@ impl Cpu {
     }
 
     pub fn power_on(&mut self) {
+        self.registers = CpuRegisters::new();
+        self.total_cycles = 0;
+        self.halted = false;
+        self.opcode_handler = None;
+        self.interrupt_disable_clear_delay = false;
+        self.interrupt_disable_set_delay = false;
+        self.irq_vector_pending = false;
+        self.control_flow = None;
         self.load_reset_vector();
     }
 
@@ -94,6 +157,7 @@ impl Cpu {
         self.interrupt_disable_clear_delay = false;
         self.interrupt_disable_set_delay = false;
         self.irq_vector_pending = false;
+        self.control_flow = None;
         self.load_reset_vector();
     }
 
@@ -113,10 +177,14 @@ impl Cpu {
             self.cycle_counter -= 1;
             if self.irq_vector_pending && self.cycle_counter == 3 {
                 self.irq_vector_pending = false;
+                // Nothing has moved the program counter since it was pushed
+                let return_address = self.registers.program_counter;
                 if self.bus_mut().take_nmi_edge() {
                     self.load_nmi_vector();
+                    self.record_call(CallKind::Nmi, return_address);
                 } else {
                     self.load_irq_vector();
+                    self.record_call(CallKind::Irq, return_address);
                 }
             }
             if self.cycle_counter == 0 {
@@ -149,7 +217,13 @@ impl Cpu {
             return;
         }
 
+        self.code_flags = self.next_code_flags;
+        self.next_code_flags = CodeDataLog::CODE;
+        self.data_flags = CodeDataLog::DATA;
+
+        self.instruction_address = self.registers.program_counter;
         let opcode = self.fetch_byte();
+        self.opcode = opcode;
         self.opcode_record = &opcodes::OPCODE_TABLE[opcode as usize];
         // Burn one cycle for the fetch and decode
         self.cycle_counter = self.opcode_record.cycles - 1;
@@ -157,8 +231,89 @@ impl Cpu {
         self.opcode_handler = Some(self.opcode_record.handler);
     }
 
+    // Call targets are the program counter once it has been loaded
+    fn record_call(&mut self, kind: CallKind, return_address: u16) {
+        self.control_flow = Some(ControlFlow::Call {
+            kind,
+            target: self.registers.program_counter,
+            return_address,
+        });
+    }
+
+    pub(crate) fn instruction_address(&self) -> u16 {
+        self.instruction_address
+    }
+
+    pub(crate) fn take_control_flow(&mut self) -> Option<ControlFlow> {
+        self.control_flow.take()
+    }
+
+    pub(crate) fn registers(&self) -> &CpuRegisters {
+        &self.registers
+    }
+
+    pub(crate) fn registers_mut(&mut self) -> &mut CpuRegisters {
+        &mut self.registers
+    }
+
+    pub(crate) fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    // Stopped by a KIL opcode until the next reset
+    pub(crate) fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // True when the next tick fetches an opcode (or services an interrupt instead)
+    pub(crate) fn is_fetch_pending(&self) -> bool {
+        self.cycle_counter == 0 && !self.halted
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.chunk(b"CPU ", STATE_VERSION, |writer| {
+            self.registers.save_state(writer);
+            writer.write_u16(self.cycle_counter);
+            writer.write_u64(self.total_cycles);
+            writer.write_u8(self.opcode);
+            writer.write_bool(self.opcode_handler.is_some());
+            writer.write_bool(self.halted);
+            writer.write_bool(self.interrupt_disable_clear_delay);
+            writer.write_bool(self.interrupt_disable_set_delay);
+            writer.write_bool(self.irq_vector_pending);
+        });
+    }
+
+    pub(crate) fn load_state(&mut self, state: &StateReader) -> Result<(), &'static str> {
+        let Some(mut chunk) = state.chunk(b"CPU ") else {
+            return Ok(());
+        };
+        chunk.expect_version(STATE_VERSION)?;
+
+        self.registers.load_state(&mut chunk)?;
+        self.cycle_counter = chunk.read_u16()?;
+        self.total_cycles = chunk.read_u64()?;
+        self.opcode = chunk.read_u8()?;
+        self.opcode_record = &opcodes::OPCODE_TABLE[self.opcode as usize];
+        // The handler is always the decoded opcode's, so it is rebuilt from the index
+        self.opcode_handler = if chunk.read_bool()? {
+            Some(self.opcode_record.handler)
+        } else {
+            None
+        };
+        self.halted = chunk.read_bool()?;
+        self.interrupt_disable_clear_delay = chunk.read_bool()?;
+        self.interrupt_disable_set_delay = chunk.read_bool()?;
+        self.irq_vector_pending = chunk.read_bool()?;
+        Ok(())
+    }
+
     fn fetch_byte(&mut self) -> u8 {
-        let byte = self.read_bus(self.registers.program_counter);
+        let address = self.registers.program_counter;
+        let flags = self.code_flags;
+        let bus = self.bus_mut();
+        let byte = bus.cpu_read(address);
+        bus.log_code_data(address, flags);
         self.registers.increment_pc();
         byte
     }
@@ -177,13 +332,18 @@ impl Cpu {
 
     #[inline]
     fn read_bus(&mut self, address: u16) -> u8 {
-        self.bus_mut().cpu_read(address)
+        let flags = self.data_flags;
+        let bus = self.bus_mut();
+        let value = bus.cpu_read(address);
+        bus.log_code_data(address, flags);
+        value
     }
 
     #[inline]
     fn write_bus(&mut self, address: u16, value: u8) {
         // Single exception trap for OAMDMA write, never reaches the bus but keeps everything else simple
         if address == 0x4014 {
+            self.bus_mut().record_write_event(address, value);
             self.perform_oamdma_write(value);
             return;
         }
@@ -1,7 +1,7 @@
 use super::Cpu;
 
 #[derive(Copy, Clone, PartialEq)]
-pub(super) enum AddressingMode {
+pub(crate) enum AddressingMode {
     ZeroPage,
     ZeroPageX,
     ZeroPageY,
@@ -1,4 +1,6 @@
-pub(super) struct CpuRegisters {
+use crate::state::{ChunkReader, StateWriter};
+
+pub struct CpuRegisters {
     pub(super) accumulator: u8,
     pub(super) index_x: u8,
     pub(super) index_y: u8,
@@ -45,7 +47,7 @@ pub(super) const NEGATIVE_MASK: u8 = 0b1000_0000;
 
 impl CpuRegisters {
     // Initialized to post reset state
-    pub(super) fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             accumulator: 0,
             index_x: 0,
@@ -78,6 +80,37 @@ impl CpuRegisters {
         self.stack_pointer
     }
 
+    pub fn status(&self) -> u8 {
+        self.status_flags
+    }
+
+    // Register setters for debuggers, meant for between instructions
+
+    pub fn set_accumulator(&mut self, value: u8) {
+        self.accumulator = value;
+    }
+
+    pub fn set_index_x(&mut self, value: u8) {
+        self.index_x = value;
+    }
+
+    pub fn set_index_y(&mut self, value: u8) {
+        self.index_y = value;
+    }
+
+    pub fn set_program_counter(&mut self, value: u16) {
+        self.program_counter = value;
+    }
+
+    pub fn set_stack_pointer(&mut self, value: u8) {
+        self.stack_pointer = value;
+    }
+
+    // B and bit 5 only exist on the stack
+    pub fn set_status(&mut self, value: u8) {
+        self.set_status_from_stack_pop(value);
+    }
+
     // Status flag getters and setters
 
     pub fn carry(&self) -> bool {
@@ -179,4 +212,23 @@ impl CpuRegisters {
     pub(super) fn increment_pc(&mut self) {
         self.program_counter = self.program_counter.wrapping_add(1)
     }
+
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.accumulator);
+        writer.write_u8(self.index_x);
+        writer.write_u8(self.index_y);
+        writer.write_u16(self.program_counter);
+        writer.write_u8(self.stack_pointer);
+        writer.write_u8(self.status_flags);
+    }
+
+    pub(super) fn load_state(&mut self, chunk: &mut ChunkReader) -> Result<(), &'static str> {
+        self.accumulator = chunk.read_u8()?;
+        self.index_x = chunk.read_u8()?;
+        self.index_y = chunk.read_u8()?;
+        self.program_counter = chunk.read_u16()?;
+        self.stack_pointer = chunk.read_u8()?;
+        self.status_flags = chunk.read_u8()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,163 @@
+use crate::cpu::CallKind;
+
+// Why a frame's view of the stack can't be trusted, noted on the frame execution continued in
+#[derive(Copy, Clone, PartialEq)]
+pub enum StackMismatch {
+    // RTS or RTI that closed no call: an RTS jump table or a hand pushed return address
+    ReturnWithoutCall,
+    // Calls above this frame were dropped without returning, the stack pointer moved past them
+    Unwound,
+    // The return went somewhere other than the address the call pushed
+    ReturnAddressChanged,
+    // RTS out of an interrupt or RTI out of a subroutine
+    WrongReturnKind,
+}
+
+pub struct CallFrame {
+    kind: CallKind,
+    target: u16,
+    return_address: u16,
+    // Stack pointer right after the call pushed its return address
+    stack_pointer: u8,
+    mismatch: Option<StackMismatch>,
+}
+
+impl CallFrame {
+    pub fn kind(&self) -> CallKind {
+        self.kind
+    }
+
+    // Entry point of the routine
+    pub fn target(&self) -> u16 {
+        self.target
+    }
+
+    pub fn return_address(&self) -> u16 {
+        self.return_address
+    }
+
+    // The JSR instruction, or for interrupts the instruction that was interrupted
+    pub fn call_site(&self) -> u16 {
+        match self.kind {
+            CallKind::Subroutine => self.return_address.wrapping_sub(3),
+            _ => self.return_address,
+        }
+    }
+
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    // Most recent mismatch seen while this frame was on top
+    pub fn mismatch(&self) -> Option<StackMismatch> {
+        self.mismatch
+    }
+}
+
+// Shadow of the hardware stack, built from JSR/BRK/IRQ/NMI entries and RTS/RTI exits. Frames
+// are matched to returns by stack pointer, so stack tricks leave it consistent if not exact.
+pub(crate) struct CallStack {
+    frames: Vec<CallFrame>,
+    // Depth run-to-return is waiting to drop below
+    return_depth: Option<usize>,
+}
+
+impl CallStack {
+    pub(crate) fn new() -> Self {
+        Self {
+            frames: Vec::new(),
+            return_depth: None,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.frames.clear();
+        self.return_depth = None;
+    }
+
+    pub(crate) fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    fn flag_top(&mut self, mismatch: StackMismatch) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.mismatch = Some(mismatch);
+        }
+    }
+
+    pub(crate) fn call(
+        &mut self,
+        kind: CallKind,
+        target: u16,
+        return_address: u16,
+        stack_pointer: u8,
+    ) {
+        // A live caller always sits higher on the stack, frames at or below this call were
+        // abandoned (PLA PLA, TXS) without returning
+        let depth = self.frames.len();
+        while self
+            .frames
+            .last()
+            .is_some_and(|frame| frame.stack_pointer <= stack_pointer)
+        {
+            self.frames.pop();
+        }
+        if self.frames.len() != depth {
+            self.flag_top(StackMismatch::Unwound);
+        }
+
+        self.frames.push(CallFrame {
+            kind,
+            target,
+            return_address,
+            stack_pointer,
+            mismatch: None,
+        });
+    }
+
+    pub(crate) fn ret(&mut self, from_interrupt: bool, destination: u16, stack_pointer: u8) {
+        let mut popped = 0;
+        let mut returned_from = None;
+        while self
+            .frames
+            .last()
+            .is_some_and(|frame| frame.stack_pointer < stack_pointer)
+        {
+            returned_from = self.frames.pop();
+            popped += 1;
+        }
+
+        let Some(frame) = returned_from else {
+            self.flag_top(StackMismatch::ReturnWithoutCall);
+            return;
+        };
+
+        let is_interrupt = frame.kind != CallKind::Subroutine;
+        if popped > 1 {
+            self.flag_top(StackMismatch::Unwound);
+        } else if is_interrupt != from_interrupt {
+            self.flag_top(StackMismatch::WrongReturnKind);
+        } else if frame.return_address != destination {
+            self.flag_top(StackMismatch::ReturnAddressChanged);
+        }
+    }
+
+    // Breaks once the frame at index has returned
+    pub(crate) fn run_to_return(&mut self, index: usize) -> bool {
+        if index >= self.frames.len() {
+            return false;
+        }
+        self.return_depth = Some(index);
+        true
+    }
+
+    pub(crate) fn take_return_reached(&mut self) -> bool {
+        match self.return_depth {
+            Some(depth) if self.frames.len() <= depth => {
+                self.return_depth = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,122 @@
+#[derive(Copy, Clone, PartialEq)]
+pub enum EventKind {
+    // $2000-$2007 and mirrors
+    PpuRegisterWrite,
+    OamDma,
+    ControllerStrobe,
+    MapperRegisterWrite,
+    Irq,
+    Nmi,
+    Sprite0Hit,
+}
+
+// Something that happened at a PPU position during a frame. For writes address and value
+// are the write, for interrupts address is the handler.
+#[derive(Copy, Clone)]
+pub struct FrameEvent {
+    pub kind: EventKind,
+    pub scanline: u16,
+    pub dot: u16,
+    pub address: u16,
+    pub value: u8,
+    // Instruction that made the write, or the one interrupted
+    pub program_counter: u16,
+}
+
+// Lives on the bus, where writes and the PPU position meet. Collects the running frame and
+// keeps the last complete one for viewers.
+pub(crate) struct EventLog {
+    enabled: bool,
+    events: Vec<FrameEvent>,
+    completed: Vec<FrameEvent>,
+    // Events from here on still need the program counter of the instruction that made them
+    unattributed: usize,
+}
+
+impl EventLog {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: false,
+            events: Vec::new(),
+            completed: Vec::new(),
+            unattributed: 0,
+        }
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.events.clear();
+        self.completed.clear();
+        self.unattributed = 0;
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    #[inline]
+    pub(crate) fn record(
+        &mut self,
+        kind: EventKind,
+        scanline: u16,
+        dot: u16,
+        address: u16,
+        value: u8,
+    ) {
+        if self.enabled {
+            self.events.push(FrameEvent {
+                kind,
+                scanline,
+                dot,
+                address,
+                value,
+                program_counter: 0,
+            });
+        }
+    }
+
+    // Interrupts are placed by the CPU, with the interrupted address already known
+    pub(crate) fn record_interrupt(
+        &mut self,
+        kind: EventKind,
+        scanline: u16,
+        dot: u16,
+        handler: u16,
+        program_counter: u16,
+    ) {
+        if self.enabled {
+            self.events.push(FrameEvent {
+                kind,
+                scanline,
+                dot,
+                address: handler,
+                value: 0,
+                program_counter,
+            });
+            self.unattributed = self.events.len();
+        }
+    }
+
+    pub(crate) fn attribute(&mut self, program_counter: u16) {
+        for event in &mut self.events[self.unattributed..] {
+            event.program_counter = program_counter;
+        }
+        self.unattributed = self.events.len();
+    }
+
+    // Drops what the running frame recorded so far, for when it is run again from its start
+    pub(crate) fn restart_frame(&mut self) {
+        self.events.clear();
+        self.unattributed = 0;
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        std::mem::swap(&mut self.events, &mut self.completed);
+        self.events.clear();
+        self.unattributed = 0;
+    }
+
+    pub(crate) fn completed(&self) -> &[FrameEvent] {
+        &self.completed
+    }
+}
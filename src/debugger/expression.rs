@@ -0,0 +1,534 @@
+// Small expression language for breakpoint conditions
+//
+// Expressions are parsed once into a flat node list and evaluated by index, so evaluating a
+// condition on every hit never allocates.
+//
+// Operands:
+//   A X Y SP PC P           CPU registers (P is the raw status byte)
+//   C Z I D V N             Status flags, 0 or 1
+//   SCANLINE DOT FRAME      PPU timing
+//   CYCLES                  Total CPU cycles
+//   HITS                    Hit count of the breakpoint, including the current hit
+//   ADDRESS VALUE           Accessed address and value (watchpoints), PC and 0 for execute
+//   [expr]                  Byte read, {expr} word read (little endian)
+//   $FF 0xFF 255 %1010      Hex, hex, decimal and binary constants
+//
+// Operators, lowest to highest precedence:
+//   || && | ^ & (== !=) (< <= > >=) (+ -) (* / %) and unary ! - ~
+
+use crate::bus::Bus;
+use crate::cpu::CpuRegisters;
+
+pub(crate) struct EvaluationContext<'a> {
+    pub(crate) registers: &'a CpuRegisters,
+    pub(crate) bus: &'a Bus,
+    pub(crate) total_cycles: u64,
+    pub(crate) hits: u32,
+    pub(crate) address: u16,
+    pub(crate) value: u8,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Operand {
+    Accumulator,
+    IndexX,
+    IndexY,
+    StackPointer,
+    ProgramCounter,
+    Status,
+    Carry,
+    Zero,
+    InterruptDisable,
+    Decimal,
+    Overflow,
+    Negative,
+    Scanline,
+    Dot,
+    Frame,
+    Cycles,
+    Hits,
+    Address,
+    Value,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum UnaryOperator {
+    LogicalNot,
+    Negate,
+    BitwiseNot,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum BinaryOperator {
+    LogicalOr,
+    LogicalAnd,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl BinaryOperator {
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::LogicalOr => 1,
+            BinaryOperator::LogicalAnd => 2,
+            BinaryOperator::BitwiseOr => 3,
+            BinaryOperator::BitwiseXor => 4,
+            BinaryOperator::BitwiseAnd => 5,
+            BinaryOperator::Equal | BinaryOperator::NotEqual => 6,
+            BinaryOperator::Less
+            | BinaryOperator::LessEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual => 7,
+            BinaryOperator::Add | BinaryOperator::Subtract => 8,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 9,
+        }
+    }
+}
+
+// Children are indices into the expression's node list
+#[derive(Copy, Clone)]
+enum Node {
+    Constant(i64),
+    Operand(Operand),
+    ReadByte(usize),
+    ReadWord(usize),
+    Unary(UnaryOperator, usize),
+    Binary(BinaryOperator, usize, usize),
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Operand(Operand),
+    Binary(BinaryOperator),
+    Not,
+    Minus,
+    Tilde,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    OpenBrace,
+    CloseBrace,
+}
+
+pub struct Expression {
+    source: String,
+    nodes: Vec<Node>,
+    root: usize,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, &'static str> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            nodes: Vec::new(),
+        };
+
+        let root = parser.parse_binary(0)?;
+        if parser.position != tokens.len() {
+            return Err("Unexpected token after expression");
+        }
+
+        Ok(Self {
+            source: source.to_owned(),
+            nodes: parser.nodes,
+            root,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub(crate) fn evaluate(&self, context: &EvaluationContext) -> i64 {
+        self.evaluate_node(self.root, context)
+    }
+
+    pub(crate) fn is_true(&self, context: &EvaluationContext) -> bool {
+        self.evaluate(context) != 0
+    }
+
+    fn evaluate_node(&self, index: usize, context: &EvaluationContext) -> i64 {
+        match self.nodes[index] {
+            Node::Constant(value) => value,
+            Node::Operand(operand) => read_operand(operand, context),
+            Node::ReadByte(child) => {
+                let address = self.evaluate_node(child, context) as u16;
+                context.bus.peek(address) as i64
+            }
+            Node::ReadWord(child) => {
+                let address = self.evaluate_node(child, context) as u16;
+                let low = context.bus.peek(address);
+                let high = context.bus.peek(address.wrapping_add(1));
+                u16::from_le_bytes([low, high]) as i64
+            }
+            Node::Unary(operator, child) => {
+                let value = self.evaluate_node(child, context);
+                match operator {
+                    UnaryOperator::LogicalNot => (value == 0) as i64,
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::BitwiseNot => !value,
+                }
+            }
+            Node::Binary(operator, left, right) => {
+                let left = self.evaluate_node(left, context);
+
+                // Short circuit so memory reads on the right are skipped when not needed
+                match operator {
+                    BinaryOperator::LogicalOr if left != 0 => return 1,
+                    BinaryOperator::LogicalAnd if left == 0 => return 0,
+                    _ => (),
+                }
+
+                let right = self.evaluate_node(right, context);
+                match operator {
+                    BinaryOperator::LogicalOr | BinaryOperator::LogicalAnd => (right != 0) as i64,
+                    BinaryOperator::BitwiseOr => left | right,
+                    BinaryOperator::BitwiseXor => left ^ right,
+                    BinaryOperator::BitwiseAnd => left & right,
+                    BinaryOperator::Equal => (left == right) as i64,
+                    BinaryOperator::NotEqual => (left != right) as i64,
+                    BinaryOperator::Less => (left < right) as i64,
+                    BinaryOperator::LessEqual => (left <= right) as i64,
+                    BinaryOperator::Greater => (left > right) as i64,
+                    BinaryOperator::GreaterEqual => (left >= right) as i64,
+                    BinaryOperator::Add => left.wrapping_add(right),
+                    BinaryOperator::Subtract => left.wrapping_sub(right),
+                    BinaryOperator::Multiply => left.wrapping_mul(right),
+                    // Division by zero evaluates to 0 rather than faulting mid-emulation
+                    BinaryOperator::Divide => left.checked_div(right).unwrap_or(0),
+                    BinaryOperator::Remainder => left.checked_rem(right).unwrap_or(0),
+                }
+            }
+        }
+    }
+}
+
+fn read_operand(operand: Operand, context: &EvaluationContext) -> i64 {
+    let registers = context.registers;
+    let ppu = context.bus.ppu();
+
+    match operand {
+        Operand::Accumulator => registers.accumulator() as i64,
+        Operand::IndexX => registers.index_x() as i64,
+        Operand::IndexY => registers.index_y() as i64,
+        Operand::StackPointer => registers.stack_pointer() as i64,
+        Operand::ProgramCounter => registers.program_counter() as i64,
+        Operand::Status => registers.status() as i64,
+        Operand::Carry => registers.carry() as i64,
+        Operand::Zero => registers.zero() as i64,
+        Operand::InterruptDisable => registers.interrupt_disable() as i64,
+        Operand::Decimal => registers.decimal() as i64,
+        Operand::Overflow => registers.overflow() as i64,
+        Operand::Negative => registers.negative() as i64,
+        Operand::Scanline => ppu.scanline() as i64,
+        Operand::Dot => ppu.dot() as i64,
+        Operand::Frame => ppu.frame() as i64,
+        Operand::Cycles => context.total_cycles as i64,
+        Operand::Hits => context.hits as i64,
+        Operand::Address => context.address as i64,
+        Operand::Value => context.value as i64,
+    }
+}
+
+fn operand_from_name(name: &str) -> Option<Operand> {
+    let operand = match name.to_ascii_uppercase().as_str() {
+        "A" => Operand::Accumulator,
+        "X" => Operand::IndexX,
+        "Y" => Operand::IndexY,
+        "SP" => Operand::StackPointer,
+        "PC" => Operand::ProgramCounter,
+        "P" => Operand::Status,
+        "C" => Operand::Carry,
+        "Z" => Operand::Zero,
+        "I" => Operand::InterruptDisable,
+        "D" => Operand::Decimal,
+        "V" => Operand::Overflow,
+        "N" => Operand::Negative,
+        "SCANLINE" => Operand::Scanline,
+        "DOT" => Operand::Dot,
+        "FRAME" => Operand::Frame,
+        "CYCLES" => Operand::Cycles,
+        "HITS" => Operand::Hits,
+        "ADDRESS" => Operand::Address,
+        "VALUE" => Operand::Value,
+        _ => return None,
+    };
+    Some(operand)
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, &'static str> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let current = bytes[position];
+        let next = bytes.get(position + 1).copied();
+
+        if current.is_ascii_whitespace() {
+            position += 1;
+            continue;
+        }
+
+        // A '%' directly after a value is the remainder operator, otherwise a binary prefix
+        let follows_value = matches!(
+            tokens.last(),
+            Some(
+                Token::Number(_)
+                    | Token::Operand(_)
+                    | Token::CloseParen
+                    | Token::CloseBracket
+                    | Token::CloseBrace
+            )
+        );
+
+        // Constants
+        let radix_prefix = match (current, next) {
+            (b'$', _) => Some((16, 1)),
+            (b'%', Some(b'0' | b'1')) if !follows_value => Some((2, 1)),
+            (b'0', Some(b'x' | b'X')) => Some((16, 2)),
+            (b'0'..=b'9', _) => Some((10, 0)),
+            _ => None,
+        };
+        if let Some((radix, prefix_length)) = radix_prefix {
+            let start = position + prefix_length;
+            let mut end = start;
+            while end < bytes.len() && (bytes[end] as char).is_digit(radix) {
+                end += 1;
+            }
+            if end == start {
+                return Err("Expected digits after number prefix");
+            }
+            let value = i64::from_str_radix(&source[start..end], radix)
+                .map_err(|_| "Number out of range")?;
+            tokens.push(Token::Number(value));
+            position = end;
+            continue;
+        }
+
+        // Identifiers
+        if current.is_ascii_alphabetic() {
+            let start = position;
+            while position < bytes.len() && bytes[position].is_ascii_alphanumeric() {
+                position += 1;
+            }
+            let operand =
+                operand_from_name(&source[start..position]).ok_or("Unknown identifier")?;
+            tokens.push(Token::Operand(operand));
+            continue;
+        }
+
+        // Operators and delimiters, two character forms first
+        let (token, length) = match (current, next) {
+            (b'|', Some(b'|')) => (Token::Binary(BinaryOperator::LogicalOr), 2),
+            (b'&', Some(b'&')) => (Token::Binary(BinaryOperator::LogicalAnd), 2),
+            (b'=', Some(b'=')) => (Token::Binary(BinaryOperator::Equal), 2),
+            (b'!', Some(b'=')) => (Token::Binary(BinaryOperator::NotEqual), 2),
+            (b'<', Some(b'=')) => (Token::Binary(BinaryOperator::LessEqual), 2),
+            (b'>', Some(b'=')) => (Token::Binary(BinaryOperator::GreaterEqual), 2),
+            (b'|', _) => (Token::Binary(BinaryOperator::BitwiseOr), 1),
+            (b'^', _) => (Token::Binary(BinaryOperator::BitwiseXor), 1),
+            (b'&', _) => (Token::Binary(BinaryOperator::BitwiseAnd), 1),
+            (b'<', _) => (Token::Binary(BinaryOperator::Less), 1),
+            (b'>', _) => (Token::Binary(BinaryOperator::Greater), 1),
+            (b'+', _) => (Token::Binary(BinaryOperator::Add), 1),
+            (b'*', _) => (Token::Binary(BinaryOperator::Multiply), 1),
+            (b'/', _) => (Token::Binary(BinaryOperator::Divide), 1),
+            (b'%', _) => (Token::Binary(BinaryOperator::Remainder), 1),
+            // Minus is resolved to unary or binary by the parser
+            (b'-', _) => (Token::Minus, 1),
+            (b'!', _) => (Token::Not, 1),
+            (b'~', _) => (Token::Tilde, 1),
+            (b'(', _) => (Token::OpenParen, 1),
+            (b')', _) => (Token::CloseParen, 1),
+            (b'[', _) => (Token::OpenBracket, 1),
+            (b']', _) => (Token::CloseBracket, 1),
+            (b'{', _) => (Token::OpenBrace, 1),
+            (b'}', _) => (Token::CloseBrace, 1),
+            _ => return Err("Unexpected character in expression"),
+        };
+        tokens.push(token);
+        position += length;
+    }
+
+    if tokens.is_empty() {
+        return Err("Empty expression");
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    nodes: Vec<Node>,
+}
+
+impl Parser<'_> {
+    fn push(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn peek_binary(&self) -> Option<BinaryOperator> {
+        match self.tokens.get(self.position) {
+            Some(Token::Binary(operator)) => Some(*operator),
+            Some(Token::Minus) => Some(BinaryOperator::Subtract),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, token: Token, error: &'static str) -> Result<(), &'static str> {
+        if self.tokens.get(self.position) == Some(&token) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    // Precedence climbing, all binary operators are left associative
+    fn parse_binary(&mut self, minimum_precedence: u8) -> Result<usize, &'static str> {
+        let mut left = self.parse_unary()?;
+
+        while let Some(operator) = self.peek_binary() {
+            let precedence = operator.precedence();
+            if precedence <= minimum_precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.parse_binary(precedence)?;
+            left = self.push(Node::Binary(operator, left, right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<usize, &'static str> {
+        let operator = match self.tokens.get(self.position) {
+            Some(Token::Not) => UnaryOperator::LogicalNot,
+            Some(Token::Minus) => UnaryOperator::Negate,
+            Some(Token::Tilde) => UnaryOperator::BitwiseNot,
+            _ => return self.parse_primary(),
+        };
+        self.position += 1;
+        let child = self.parse_unary()?;
+        Ok(self.push(Node::Unary(operator, child)))
+    }
+
+    fn parse_primary(&mut self) -> Result<usize, &'static str> {
+        let token = *self
+            .tokens
+            .get(self.position)
+            .ok_or("Unexpected end of expression")?;
+        self.position += 1;
+
+        match token {
+            Token::Number(value) => Ok(self.push(Node::Constant(value))),
+            Token::Operand(operand) => Ok(self.push(Node::Operand(operand))),
+            Token::OpenParen => {
+                let inner = self.parse_binary(0)?;
+                self.expect(Token::CloseParen, "Missing closing parenthesis")?;
+                Ok(inner)
+            }
+            Token::OpenBracket => {
+                let inner = self.parse_binary(0)?;
+                self.expect(Token::CloseBracket, "Missing closing bracket")?;
+                Ok(self.push(Node::ReadByte(inner)))
+            }
+            Token::OpenBrace => {
+                let inner = self.parse_binary(0)?;
+                self.expect(Token::CloseBrace, "Missing closing brace")?;
+                Ok(self.push(Node::ReadWord(inner)))
+            }
+            _ => Err("Expected a value"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str) -> i64 {
+        let mut registers = CpuRegisters::new();
+        registers.set_accumulator(0x42);
+        registers.set_index_x(3);
+        let mut bus = Bus::new();
+        bus.poke(0x0010, 0x34);
+        bus.poke(0x0011, 0x12);
+
+        let context = EvaluationContext {
+            registers: &registers,
+            bus: &bus,
+            total_cycles: 0,
+            hits: 1,
+            address: 0x0010,
+            value: 0x34,
+        };
+        Expression::parse(source).unwrap().evaluate(&context)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(evaluate("1 + 2 * 3"), 7);
+        assert_eq!(evaluate("(1 + 2) * 3"), 9);
+        assert_eq!(evaluate("10 - 4 - 3"), 3);
+        // 1 | (6 ^ (3 & 5)), left to right it would be 4
+        assert_eq!(evaluate("1 | 6 ^ 3 & 5"), 7);
+        assert_eq!(evaluate("1 + 1 == 2 && 3 < 2 || X == 3"), 1);
+        assert_eq!(evaluate("A == $42 && X >= 4"), 0);
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(evaluate("-X"), -3);
+        assert_eq!(evaluate("!0"), 1);
+        assert_eq!(evaluate("!X"), 0);
+        assert_eq!(evaluate("~0 & $FF"), 0xFF);
+        assert_eq!(evaluate("- -X * 2"), 6);
+    }
+
+    #[test]
+    fn memory_dereference() {
+        assert_eq!(evaluate("[$10]"), 0x34);
+        assert_eq!(evaluate("{$10}"), 0x1234);
+        assert_eq!(evaluate("[ADDRESS + 1]"), 0x12);
+        assert_eq!(evaluate("[[$10] - $23]"), 0x12);
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(evaluate("$FF + 0x10 + 10 + %101"), 0xFF + 0x10 + 10 + 5);
+    }
+
+    #[test]
+    fn division_by_zero_is_zero() {
+        assert_eq!(evaluate("5 / 0"), 0);
+        assert_eq!(evaluate("5 % 0"), 0);
+        assert_eq!(evaluate("7 / 2"), 3);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for source in ["", "1 +", "(1", "[1", "1 2", "FOO", "$", "1 # 2"] {
+            assert!(Expression::parse(source).is_err(), "{source:?} parsed");
+        }
+    }
+}
@@ -0,0 +1,533 @@
+// GDB remote serial protocol server, so existing GDB frontends can debug the 6502
+//
+// The stub doesn't own the emulator: the frontend keeps running frames as usual and calls
+// poll() from its loop. Continue only switches the run mode, the stop reply is sent once
+// a breakpoint or the frontend has paused emulation again.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+use crate::debugger::{BreakHit, BreakpointKind};
+use crate::nes::{Nes, RunMode};
+
+// GDB has no 6502 architecture, so the registers are described to it. The g packet sends
+// them in this order, little endian.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rustendulator.6502">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="x" bitsize="8"/>
+    <reg name="y" bitsize="8"/>
+    <reg name="p" bitsize="8"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+const REGISTER_COUNT: usize = 6;
+
+// Largest m packet answered in one go, the client splits bigger reads
+const MAX_MEMORY_READ: usize = 0x800;
+
+// Z packet types
+const SOFTWARE_BREAKPOINT: u8 = 0;
+const HARDWARE_BREAKPOINT: u8 = 1;
+const WRITE_WATCHPOINT: u8 = 2;
+const READ_WATCHPOINT: u8 = 3;
+const ACCESS_WATCHPOINT: u8 = 4;
+
+// A breakpoint the client inserted, access watchpoints need a read and a write breakpoint
+struct ClientBreakpoint {
+    kind: u8,
+    address: u16,
+    length: u16,
+    ids: Vec<u32>,
+}
+
+pub struct GdbStub {
+    listener: TcpListener,
+    connection: Option<TcpStream>,
+    input: Vec<u8>,
+    no_ack: bool,
+    // The client resumed the target and is owed a stop reply
+    running: bool,
+    breakpoints: Vec<ClientBreakpoint>,
+}
+
+impl GdbStub {
+    // Usually "127.0.0.1:<port>", a client connects with "target remote :<port>"
+    pub fn listen(address: &str) -> Result<Self, &'static str> {
+        let listener = TcpListener::bind(address).map_err(|_| "Could not open GDB server port")?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|_| "Could not open GDB server port")?;
+
+        Ok(Self {
+            listener,
+            connection: None,
+            input: Vec::new(),
+            no_ack: false,
+            running: false,
+            breakpoints: Vec::new(),
+        })
+    }
+
+    pub fn local_address(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    // Accepts a client, answers its packets and reports when a resumed target stopped
+    pub fn poll(&mut self, nes: &mut Nes) {
+        if self.connection.is_none() {
+            match self.listener.accept() {
+                Ok((stream, _)) if stream.set_nonblocking(true).is_ok() => {
+                    let _ = stream.set_nodelay(true);
+                    self.connection = Some(stream);
+                    self.input.clear();
+                    self.no_ack = false;
+                    self.running = false;
+                }
+                _ => return,
+            }
+        }
+
+        if !self.receive() {
+            self.disconnect(nes);
+            return;
+        }
+        self.process_input(nes);
+
+        if self.running && nes.get_run_mode() != RunMode::Running {
+            self.running = false;
+            let reply = self.stop_reply(nes.take_break_hit());
+            self.send_packet(&reply);
+        }
+    }
+
+    // Reads whatever has arrived, false once the client is gone
+    fn receive(&mut self) -> bool {
+        let Some(connection) = self.connection.as_mut() else {
+            return false;
+        };
+
+        let mut buffer = [0; 1024];
+        loop {
+            match connection.read(&mut buffer) {
+                Ok(0) => return false,
+                Ok(length) => self.input.extend_from_slice(&buffer[..length]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return true,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    // Breakpoints only make sense to the client that set them
+    fn disconnect(&mut self, nes: &mut Nes) {
+        for breakpoint in self.breakpoints.drain(..) {
+            for id in breakpoint.ids {
+                nes.remove_breakpoint(id);
+            }
+        }
+        self.connection = None;
+        self.input.clear();
+        self.running = false;
+    }
+
+    fn process_input(&mut self, nes: &mut Nes) {
+        let mut position = 0;
+
+        while position < self.input.len() {
+            match self.input[position] {
+                // Ctrl+C from the client
+                0x03 => {
+                    position += 1;
+                    nes.set_run_mode(RunMode::Paused);
+                    if self.running {
+                        self.running = false;
+                        self.send_packet("T02");
+                    }
+                }
+                b'$' => {
+                    let Some(end) = self.input[position..].iter().position(|&b| b == b'#') else {
+                        break;
+                    };
+                    let end = position + end;
+                    if end + 3 > self.input.len() {
+                        break;
+                    }
+
+                    let body = self.input[position + 1..end].to_vec();
+                    let checksum = std::str::from_utf8(&self.input[end + 1..end + 3])
+                        .ok()
+                        .and_then(|text| u8::from_str_radix(text, 16).ok());
+                    position = end + 3;
+
+                    if checksum != Some(checksum_of(&body)) {
+                        if !self.no_ack {
+                            self.send_raw(b"-");
+                        }
+                        continue;
+                    }
+                    if !self.no_ack {
+                        self.send_raw(b"+");
+                    }
+
+                    let body = String::from_utf8_lossy(&body).into_owned();
+                    if let Some(reply) = self.handle_packet(&body, nes) {
+                        self.send_packet(&reply);
+                    }
+                    if self.connection.is_none() {
+                        return;
+                    }
+                }
+                // Acks and line noise
+                _ => position += 1,
+            }
+        }
+
+        self.input.drain(..position);
+    }
+
+    // The reply to send, None when there is none yet (continue) or none at all
+    fn handle_packet(&mut self, packet: &str, nes: &mut Nes) -> Option<String> {
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        let reply = match command {
+            "?" => {
+                // Clients expect a stopped target when they attach
+                nes.set_run_mode(RunMode::Paused);
+                self.running = false;
+                "T05".to_owned()
+            }
+            "q" => self.handle_query(arguments),
+            "Q" if arguments == "StartNoAckMode" => {
+                self.send_packet("OK");
+                self.no_ack = true;
+                return None;
+            }
+            "H" => "OK".to_owned(),
+            "g" => {
+                let registers = read_registers(nes);
+                registers.iter().map(|b| format!("{:02x}", b)).collect()
+            }
+            "G" => match parse_hex_bytes(arguments) {
+                Some(bytes) if bytes.len() == REGISTER_COUNT + 1 => {
+                    for (register, &value) in bytes[..REGISTER_COUNT - 1].iter().enumerate() {
+                        write_register(nes, register, value as u16);
+                    }
+                    let program_counter = u16::from_le_bytes([bytes[5], bytes[6]]);
+                    write_register(nes, REGISTER_COUNT - 1, program_counter);
+                    "OK".to_owned()
+                }
+                _ => "E01".to_owned(),
+            },
+            "p" => match usize::from_str_radix(arguments, 16) {
+                Ok(5) => {
+                    let [low, high] = nes.cpu_registers().program_counter().to_le_bytes();
+                    format!("{:02x}{:02x}", low, high)
+                }
+                Ok(register) if register < REGISTER_COUNT => {
+                    format!("{:02x}", read_registers(nes)[register])
+                }
+                _ => "E01".to_owned(),
+            },
+            "P" => {
+                let parsed = arguments.split_once('=').and_then(|(register, value)| {
+                    let register = usize::from_str_radix(register, 16).ok()?;
+                    let bytes = parse_hex_bytes(value)?;
+                    let value = match bytes.as_slice() {
+                        [low] => *low as u16,
+                        [low, high] => u16::from_le_bytes([*low, *high]),
+                        _ => return None,
+                    };
+                    (register < REGISTER_COUNT).then_some((register, value))
+                });
+                match parsed {
+                    Some((register, value)) => {
+                        write_register(nes, register, value);
+                        "OK".to_owned()
+                    }
+                    None => "E01".to_owned(),
+                }
+            }
+            "m" => match parse_address_length(arguments) {
+                Some((address, length)) => (0..length.min(MAX_MEMORY_READ))
+                    .map(|i| format!("{:02x}", nes.peek(address.wrapping_add(i as u16))))
+                    .collect(),
+                None => "E01".to_owned(),
+            },
+            "M" => {
+                let parsed = arguments.split_once(':').and_then(|(range, data)| {
+                    let (address, length) = parse_address_length(range)?;
+                    let bytes = parse_hex_bytes(data)?;
+                    (bytes.len() == length).then_some((address, bytes))
+                });
+                match parsed {
+                    Some((address, bytes)) => {
+                        for (i, byte) in bytes.into_iter().enumerate() {
+                            nes.poke(address.wrapping_add(i as u16), byte);
+                        }
+                        "OK".to_owned()
+                    }
+                    None => "E01".to_owned(),
+                }
+            }
+            "Z" => self.insert_breakpoint(arguments, nes),
+            "z" => self.remove_breakpoint(arguments, nes),
+            "c" => return self.resume(arguments, false, nes),
+            "s" => return self.resume(arguments, true, nes),
+            "v" => {
+                if arguments == "Cont?" {
+                    "vCont;c;C;s;S".to_owned()
+                } else if let Some(actions) = arguments.strip_prefix("Cont;") {
+                    // Only one thread, so the first action is the one that applies
+                    let step = matches!(actions.as_bytes().first(), Some(b's' | b'S'));
+                    return self.resume("", step, nes);
+                } else {
+                    String::new()
+                }
+            }
+            "D" => {
+                self.send_packet("OK");
+                self.disconnect(nes);
+                nes.set_run_mode(RunMode::Running);
+                return None;
+            }
+            "k" => {
+                self.disconnect(nes);
+                return None;
+            }
+            // Empty means unsupported
+            _ => String::new(),
+        };
+
+        Some(reply)
+    }
+
+    fn handle_query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+"
+                .to_owned();
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = range.split_once(',').and_then(|(offset, length)| {
+                Some((
+                    usize::from_str_radix(offset, 16).ok()?,
+                    usize::from_str_radix(length, 16).ok()?,
+                ))
+            }) else {
+                return "E01".to_owned();
+            };
+            let start = offset.min(TARGET_XML.len());
+            let end = start.saturating_add(length).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+            return format!("{}{}", more, &TARGET_XML[start..end]);
+        }
+
+        match query {
+            "Attached" => "1".to_owned(),
+            "C" => "QC1".to_owned(),
+            "fThreadInfo" => "m1".to_owned(),
+            "sThreadInfo" => "l".to_owned(),
+            _ => String::new(),
+        }
+    }
+
+    // Z<type>,<address>,<kind>, kind is the watched length for watchpoints
+    fn insert_breakpoint(&mut self, arguments: &str, nes: &mut Nes) -> String {
+        let Some((kind, address, length)) = parse_breakpoint(arguments) else {
+            return "E01".to_owned();
+        };
+        if self
+            .breakpoints
+            .iter()
+            .any(|b| b.kind == kind && b.address == address && b.length == length)
+        {
+            return "OK".to_owned();
+        }
+
+        let kinds: &[BreakpointKind] = match kind {
+            SOFTWARE_BREAKPOINT | HARDWARE_BREAKPOINT => &[BreakpointKind::Execute],
+            WRITE_WATCHPOINT => &[BreakpointKind::Write],
+            READ_WATCHPOINT => &[BreakpointKind::Read],
+            ACCESS_WATCHPOINT => &[BreakpointKind::Read, BreakpointKind::Write],
+            _ => return String::new(),
+        };
+        // For execute breakpoints kind is the instruction size, not a range
+        let end_address = match kind {
+            SOFTWARE_BREAKPOINT | HARDWARE_BREAKPOINT => address,
+            _ => address.saturating_add(length.max(1) - 1),
+        };
+
+        let mut ids = Vec::new();
+        for &breakpoint_kind in kinds {
+            match nes.add_breakpoint(breakpoint_kind, address, end_address, None) {
+                Ok(id) => ids.push(id),
+                Err(_) => {
+                    for id in ids {
+                        nes.remove_breakpoint(id);
+                    }
+                    return "E02".to_owned();
+                }
+            }
+        }
+
+        self.breakpoints.push(ClientBreakpoint {
+            kind,
+            address,
+            length,
+            ids,
+        });
+        "OK".to_owned()
+    }
+
+    fn remove_breakpoint(&mut self, arguments: &str, nes: &mut Nes) -> String {
+        let Some((kind, address, length)) = parse_breakpoint(arguments) else {
+            return "E01".to_owned();
+        };
+        if let Some(index) = self
+            .breakpoints
+            .iter()
+            .position(|b| b.kind == kind && b.address == address && b.length == length)
+        {
+            for id in self.breakpoints.remove(index).ids {
+                nes.remove_breakpoint(id);
+            }
+        }
+        "OK".to_owned()
+    }
+
+    // c/s [address]. A step replies straight away, a continue once the target stops.
+    fn resume(&mut self, arguments: &str, step: bool, nes: &mut Nes) -> Option<String> {
+        if let Ok(address) = u16::from_str_radix(arguments, 16) {
+            nes.cpu_registers_mut().set_program_counter(address);
+        }
+        // Always moves on, also when a breakpoint was set at PC after stopping
+        nes.resume_from_program_counter();
+
+        if step {
+            let hit = if nes.step_instruction() {
+                nes.take_break_hit()
+            } else {
+                None
+            };
+            return Some(self.stop_reply(hit));
+        }
+
+        nes.set_run_mode(RunMode::Running);
+        self.running = true;
+        None
+    }
+
+    // SIGTRAP, with the breakpoint that caused it when it was one of the client's
+    fn stop_reply(&self, hit: Option<BreakHit>) -> String {
+        let Some(hit) = hit else {
+            return "T05".to_owned();
+        };
+        let Some(breakpoint) = self
+            .breakpoints
+            .iter()
+            .find(|b| b.ids.contains(&hit.breakpoint_id))
+        else {
+            return "T05".to_owned();
+        };
+
+        match breakpoint.kind {
+            SOFTWARE_BREAKPOINT => "T05swbreak:;".to_owned(),
+            HARDWARE_BREAKPOINT => "T05hwbreak:;".to_owned(),
+            WRITE_WATCHPOINT => format!("T05watch:{:x};", hit.address),
+            READ_WATCHPOINT => format!("T05rwatch:{:x};", hit.address),
+            _ => format!("T05awatch:{:x};", hit.address),
+        }
+    }
+
+    fn send_packet(&mut self, body: &str) {
+        let packet = format!("${}#{:02x}", body, checksum_of(body.as_bytes()));
+        self.send_raw(packet.as_bytes());
+    }
+
+    fn send_raw(&mut self, mut data: &[u8]) {
+        let Some(connection) = self.connection.as_mut() else {
+            return;
+        };
+
+        // Replies are small, a full socket buffer only needs a moment to drain
+        while !data.is_empty() {
+            match connection.write(data) {
+                Ok(0) => break,
+                Ok(written) => data = &data[written..],
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) =>
+                {
+                    std::thread::yield_now();
+                }
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+// a, x, y, p, sp, then pc low and high
+fn read_registers(nes: &Nes) -> [u8; REGISTER_COUNT + 1] {
+    let registers = nes.cpu_registers();
+    let [low, high] = registers.program_counter().to_le_bytes();
+    [
+        registers.accumulator(),
+        registers.index_x(),
+        registers.index_y(),
+        registers.status(),
+        registers.stack_pointer(),
+        low,
+        high,
+    ]
+}
+
+fn write_register(nes: &mut Nes, register: usize, value: u16) {
+    let registers = nes.cpu_registers_mut();
+    match register {
+        0 => registers.set_accumulator(value as u8),
+        1 => registers.set_index_x(value as u8),
+        2 => registers.set_index_y(value as u8),
+        3 => registers.set_status(value as u8),
+        4 => registers.set_stack_pointer(value as u8),
+        _ => registers.set_program_counter(value),
+    }
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn parse_address_length(text: &str) -> Option<(u16, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn parse_breakpoint(text: &str) -> Option<(u8, u16, u16)> {
+    let mut fields = text.splitn(3, ',');
+    let kind = fields.next()?.parse().ok()?;
+    let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+    // Conditions and commands after a ';' are GDB side
+    let length = fields.next()?.split(';').next()?;
+    Some((kind, address, u16::from_str_radix(length, 16).ok()?))
+}
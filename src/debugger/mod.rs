@@ -0,0 +1,365 @@
+mod call_stack;
+mod event_log;
+mod expression;
+mod gdb_stub;
+
+pub(crate) use self::call_stack::CallStack;
+pub use self::call_stack::{CallFrame, StackMismatch};
+pub(crate) use self::event_log::EventLog;
+pub use self::event_log::{EventKind, FrameEvent};
+use self::expression::EvaluationContext;
+pub use self::expression::Expression;
+pub use self::gdb_stub::GdbStub;
+use crate::bus::Bus;
+use crate::cpu::CpuRegisters;
+
+// Most accesses a single CPU tick can queue for checking, extra watched accesses are dropped
+const ACCESS_LOG_CAPACITY: usize = 16;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum BreakpointKind {
+    Execute,
+    Read,
+    Write,
+}
+
+pub struct Breakpoint {
+    id: u32,
+    kind: BreakpointKind,
+    start_address: u16,
+    end_address: u16,
+    condition: Option<Expression>,
+    enabled: bool,
+    hit_count: u32,
+}
+
+impl Breakpoint {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn kind(&self) -> BreakpointKind {
+        self.kind
+    }
+
+    pub fn start_address(&self) -> u16 {
+        self.start_address
+    }
+
+    pub fn end_address(&self) -> u16 {
+        self.end_address
+    }
+
+    pub fn condition(&self) -> Option<&Expression> {
+        self.condition.as_ref()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn hit_count(&self) -> u32 {
+        self.hit_count
+    }
+
+    fn matches(&self, kind: BreakpointKind, address: u16) -> bool {
+        self.enabled
+            && self.kind == kind
+            && (self.start_address..=self.end_address).contains(&address)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct BreakHit {
+    pub breakpoint_id: u32,
+    pub kind: BreakpointKind,
+    pub address: u16,
+    pub value: u8,
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct MemoryAccess {
+    kind: BreakpointKind,
+    address: u16,
+    value: u8,
+}
+
+// Lives on the bus: watched addresses as bitmaps plus the accesses that hit them this tick
+pub(crate) struct AccessLog {
+    read_map: [u64; 1024],
+    write_map: [u64; 1024],
+    entries: [MemoryAccess; ACCESS_LOG_CAPACITY],
+    length: usize,
+}
+
+impl AccessLog {
+    pub(crate) fn new() -> Self {
+        Self {
+            read_map: [0; 1024],
+            write_map: [0; 1024],
+            entries: [MemoryAccess {
+                kind: BreakpointKind::Read,
+                address: 0,
+                value: 0,
+            }; ACCESS_LOG_CAPACITY],
+            length: 0,
+        }
+    }
+
+    fn is_watched(map: &[u64; 1024], address: u16) -> bool {
+        map[(address >> 6) as usize] & (1 << (address & 0x3F)) != 0
+    }
+
+    fn push(&mut self, kind: BreakpointKind, address: u16, value: u8) {
+        if self.length < ACCESS_LOG_CAPACITY {
+            self.entries[self.length] = MemoryAccess {
+                kind,
+                address,
+                value,
+            };
+            self.length += 1;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record_read(&mut self, address: u16, value: u8) {
+        if Self::is_watched(&self.read_map, address) {
+            self.push(BreakpointKind::Read, address, value);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record_write(&mut self, address: u16, value: u8) {
+        if Self::is_watched(&self.write_map, address) {
+            self.push(BreakpointKind::Write, address, value);
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub(crate) fn take(&mut self) -> ([MemoryAccess; ACCESS_LOG_CAPACITY], usize) {
+        let length = self.length;
+        self.length = 0;
+        (self.entries, length)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.length = 0;
+    }
+}
+
+pub(crate) struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    next_id: u32,
+    break_hit: Option<BreakHit>,
+    // Address of the last execute break, skipped once so resuming doesn't break again
+    resume_address: Option<u16>,
+}
+
+impl Debugger {
+    pub(crate) fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            next_id: 1,
+            break_hit: None,
+            resume_address: None,
+        }
+    }
+
+    pub(crate) fn add_breakpoint(
+        &mut self,
+        kind: BreakpointKind,
+        start_address: u16,
+        end_address: u16,
+        condition: Option<&str>,
+        bus: &mut Bus,
+    ) -> Result<u32, &'static str> {
+        if end_address < start_address {
+            return Err("Breakpoint range end is before start");
+        }
+
+        let condition = match condition {
+            Some(source) if !source.trim().is_empty() => Some(Expression::parse(source)?),
+            _ => None,
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.push(Breakpoint {
+            id,
+            kind,
+            start_address,
+            end_address,
+            condition,
+            enabled: true,
+            hit_count: 0,
+        });
+        self.sync_access_log(bus);
+
+        Ok(id)
+    }
+
+    pub(crate) fn remove_breakpoint(&mut self, id: u32, bus: &mut Bus) -> bool {
+        let length = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.sync_access_log(bus);
+        self.breakpoints.len() != length
+    }
+
+    pub(crate) fn set_breakpoint_enabled(&mut self, id: u32, enabled: bool, bus: &mut Bus) {
+        if let Some(breakpoint) = self.breakpoints.iter_mut().find(|b| b.id == id) {
+            breakpoint.enabled = enabled;
+            self.sync_access_log(bus);
+        }
+    }
+
+    pub(crate) fn reset_hit_counts(&mut self) {
+        for breakpoint in &mut self.breakpoints {
+            breakpoint.hit_count = 0;
+        }
+    }
+
+    // Replaying for reverse stepping re-counts hits, so they are put back afterwards
+    pub(crate) fn hit_counts(&self) -> Vec<u32> {
+        self.breakpoints.iter().map(|b| b.hit_count).collect()
+    }
+
+    pub(crate) fn restore_hit_counts(&mut self, hit_counts: &[u32]) {
+        for (breakpoint, &hit_count) in self.breakpoints.iter_mut().zip(hit_counts) {
+            breakpoint.hit_count = hit_count;
+        }
+    }
+
+    pub(crate) fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub(crate) fn take_break_hit(&mut self) -> Option<BreakHit> {
+        self.break_hit.take()
+    }
+
+    pub(crate) fn has_execute_breakpoints(&self) -> bool {
+        self.breakpoints
+            .iter()
+            .any(|b| b.enabled && b.kind == BreakpointKind::Execute)
+    }
+
+    // Rebuild the bus-side address bitmaps from the enabled watchpoints
+    fn sync_access_log(&self, bus: &mut Bus) {
+        let log = bus.access_log_mut();
+        log.read_map = [0; 1024];
+        log.write_map = [0; 1024];
+
+        for breakpoint in self.breakpoints.iter().filter(|b| b.enabled) {
+            let map = match breakpoint.kind {
+                BreakpointKind::Execute => continue,
+                BreakpointKind::Read => &mut log.read_map,
+                BreakpointKind::Write => &mut log.write_map,
+            };
+            for address in breakpoint.start_address..=breakpoint.end_address {
+                map[(address >> 6) as usize] |= 1 << (address & 0x3F);
+            }
+        }
+    }
+
+    // The next execute check at this address passes, resuming then runs the instruction there
+    pub(crate) fn resume_at(&mut self, address: u16) {
+        self.resume_address = Some(address);
+    }
+
+    // Called with the CPU about to fetch an opcode at program_counter, returns true to break
+    pub(crate) fn check_execute(
+        &mut self,
+        registers: &CpuRegisters,
+        bus: &Bus,
+        total_cycles: u64,
+    ) -> bool {
+        let program_counter = registers.program_counter();
+        if self.resume_address.take() == Some(program_counter) {
+            return false;
+        }
+
+        let access = MemoryAccess {
+            kind: BreakpointKind::Execute,
+            address: program_counter,
+            value: 0,
+        };
+        if self.check(access, registers, bus, total_cycles) {
+            self.resume_address = Some(program_counter);
+            return true;
+        }
+        false
+    }
+
+    // Called after a CPU tick with the watched accesses it made, returns true to break
+    pub(crate) fn check_accesses(
+        &mut self,
+        registers: &CpuRegisters,
+        bus: &mut Bus,
+        total_cycles: u64,
+    ) -> bool {
+        if bus.access_log_mut().is_empty() {
+            return false;
+        }
+
+        let (entries, length) = bus.access_log_mut().take();
+        let mut first_hit = None;
+        for access in &entries[..length] {
+            // Every access still counts towards hit counts, only the first break is reported
+            if self.check(*access, registers, bus, total_cycles) && first_hit.is_none() {
+                first_hit = self.break_hit;
+            }
+        }
+
+        if first_hit.is_some() {
+            self.break_hit = first_hit;
+            return true;
+        }
+        false
+    }
+
+    fn check(
+        &mut self,
+        access: MemoryAccess,
+        registers: &CpuRegisters,
+        bus: &Bus,
+        total_cycles: u64,
+    ) -> bool {
+        let mut hit = false;
+
+        for breakpoint in &mut self.breakpoints {
+            if !breakpoint.matches(access.kind, access.address) {
+                continue;
+            }
+
+            breakpoint.hit_count = breakpoint.hit_count.saturating_add(1);
+
+            let triggered = match &breakpoint.condition {
+                Some(condition) => condition.is_true(&EvaluationContext {
+                    registers,
+                    bus,
+                    total_cycles,
+                    hits: breakpoint.hit_count,
+                    address: access.address,
+                    value: access.value,
+                }),
+                None => true,
+            };
+
+            // Keep the first hit, later breakpoints still count theirs
+            if triggered && !hit {
+                hit = true;
+                self.break_hit = Some(BreakHit {
+                    breakpoint_id: breakpoint.id,
+                    kind: access.kind,
+                    address: access.address,
+                    value: access.value,
+                });
+            }
+        }
+
+        hit
+    }
+}
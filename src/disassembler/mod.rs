@@ -0,0 +1,137 @@
+mod symbols;
+
+pub use self::symbols::{Symbol, SymbolAddress, SymbolTable};
+use crate::code_data_log::CodeDataLog;
+use crate::cpu::{AddressingMode, opcode_addressing_mode, opcode_mnemonic};
+use crate::nes::Nes;
+
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: [u8; 3],
+    pub length: u8,
+    pub mnemonic: &'static str,
+    // Formatted operand, with labels in place of addresses that have one
+    pub operand: String,
+    // Address the operand refers to, for branches, jumps and memory accesses
+    pub target: Option<u16>,
+    // Code/data log flags of the first byte, zero when it was never logged
+    pub code_data_flags: u8,
+}
+
+impl Instruction {
+    pub fn text(&self) -> String {
+        if self.operand.is_empty() {
+            self.mnemonic.to_owned()
+        } else {
+            format!("{} {}", self.mnemonic, self.operand)
+        }
+    }
+}
+
+fn instruction_length(mode: AddressingMode) -> u8 {
+    match mode {
+        AddressingMode::Implicit | AddressingMode::Accumulator => 1,
+        AddressingMode::Absolute
+        | AddressingMode::AbsoluteX
+        | AddressingMode::AbsoluteY
+        | AddressingMode::Indirect => 3,
+        _ => 2,
+    }
+}
+
+// Decodes the instruction at address through the current bank mapping. Memory is read with
+// peek, so registers are never disturbed. Bytes the code/data log has only seen read as data
+// come out as .byte lines.
+pub fn disassemble_instruction(
+    nes: &Nes,
+    symbols: Option<&SymbolTable>,
+    address: u16,
+) -> Instruction {
+    let opcode = nes.peek(address);
+    let code_data_flags = match (nes.code_data_log(), nes.prg_rom_offset(address)) {
+        (Some(log), Some(offset)) => log.prg_flags(offset),
+        _ => 0,
+    };
+
+    let is_code = code_data_flags & CodeDataLog::CODE != 0;
+    let is_data = code_data_flags & (CodeDataLog::DATA | CodeDataLog::PCM) != 0;
+    if is_data && !is_code {
+        return Instruction {
+            address,
+            bytes: [opcode, 0, 0],
+            length: 1,
+            mnemonic: ".byte",
+            operand: format!("${:02X}", opcode),
+            target: None,
+            code_data_flags,
+        };
+    }
+
+    let mode = opcode_addressing_mode(opcode);
+    let length = instruction_length(mode);
+
+    let mut bytes = [opcode, 0, 0];
+    for offset in 1..length {
+        bytes[offset as usize] = nes.peek(address.wrapping_add(offset as u16));
+    }
+    let byte = bytes[1];
+    let word = u16::from_le_bytes([bytes[1], bytes[2]]);
+
+    let name = |target: u16, digits: usize| -> String {
+        symbols
+            .and_then(|symbols| symbols.lookup(nes, target))
+            .filter(|symbol| !symbol.label.is_empty())
+            .map_or_else(
+                || format!("${:0width$X}", target, width = digits),
+                |symbol| symbol.label.clone(),
+            )
+    };
+
+    let (operand, target) = match mode {
+        AddressingMode::Implicit => (String::new(), None),
+        AddressingMode::Accumulator => ("A".to_owned(), None),
+        AddressingMode::Immediate => (format!("#${:02X}", byte), None),
+        AddressingMode::ZeroPage => (name(byte as u16, 2), Some(byte as u16)),
+        AddressingMode::ZeroPageX => (format!("{},X", name(byte as u16, 2)), Some(byte as u16)),
+        AddressingMode::ZeroPageY => (format!("{},Y", name(byte as u16, 2)), Some(byte as u16)),
+        AddressingMode::Absolute => (name(word, 4), Some(word)),
+        AddressingMode::AbsoluteX => (format!("{},X", name(word, 4)), Some(word)),
+        AddressingMode::AbsoluteY => (format!("{},Y", name(word, 4)), Some(word)),
+        AddressingMode::Indirect => (format!("({})", name(word, 4)), Some(word)),
+        AddressingMode::IndirectX => (format!("({},X)", name(byte as u16, 2)), Some(byte as u16)),
+        AddressingMode::IndirectY => (format!("({}),Y", name(byte as u16, 2)), Some(byte as u16)),
+        AddressingMode::Relative => {
+            let branch_target = address.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            (name(branch_target, 4), Some(branch_target))
+        }
+    };
+
+    Instruction {
+        address,
+        bytes,
+        length,
+        mnemonic: opcode_mnemonic(opcode),
+        operand,
+        target,
+        code_data_flags,
+    }
+}
+
+// Linear sweep from start, instructions starting after end are left out
+pub fn disassemble(
+    nes: &Nes,
+    symbols: Option<&SymbolTable>,
+    start: u16,
+    end: u16,
+) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut address = start as u32;
+
+    while address <= end as u32 {
+        let instruction = disassemble_instruction(nes, symbols, address as u16);
+        address += instruction.length as u32;
+        instructions.push(instruction);
+    }
+
+    instructions
+}
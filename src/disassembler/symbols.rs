@@ -0,0 +1,286 @@
+// Labels and comments, imported from assembler/emulator symbol files or added by the user
+//
+// ROM symbols are keyed by PRG-ROM offset so they follow their bank, everything else by CPU
+// address. The table persists as a Mesen .mlb file.
+
+use std::collections::BTreeMap;
+
+use crate::nes::Nes;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SymbolAddress {
+    Cpu(u16),
+    PrgRom(usize),
+}
+
+#[derive(Clone, Default)]
+pub struct Symbol {
+    pub label: String,
+    pub comment: String,
+}
+
+#[derive(Clone)]
+pub struct SymbolTable {
+    symbols: BTreeMap<SymbolAddress, Symbol>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            symbols: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.symbols.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (SymbolAddress, &Symbol)> {
+        self.symbols
+            .iter()
+            .map(|(address, symbol)| (*address, symbol))
+    }
+
+    pub fn get(&self, location: SymbolAddress) -> Option<&Symbol> {
+        self.symbols.get(&location)
+    }
+
+    // Where a symbol for this CPU address belongs under the current bank mapping
+    pub fn location(nes: &Nes, address: u16) -> SymbolAddress {
+        match nes.prg_rom_offset(address) {
+            Some(offset) => SymbolAddress::PrgRom(offset),
+            None => SymbolAddress::Cpu(address),
+        }
+    }
+
+    pub fn lookup(&self, nes: &Nes, address: u16) -> Option<&Symbol> {
+        self.get(Self::location(nes, address))
+            .or_else(|| self.get(SymbolAddress::Cpu(address)))
+    }
+
+    pub fn set_label(&mut self, location: SymbolAddress, label: &str) {
+        self.symbols.entry(location).or_default().label = label.trim().to_owned();
+        self.remove_if_empty(location);
+    }
+
+    pub fn set_comment(&mut self, location: SymbolAddress, comment: &str) {
+        self.symbols.entry(location).or_default().comment = comment.to_owned();
+        self.remove_if_empty(location);
+    }
+
+    fn remove_if_empty(&mut self, location: SymbolAddress) {
+        if self
+            .symbols
+            .get(&location)
+            .is_some_and(|s| s.label.is_empty() && s.comment.is_empty())
+        {
+            self.symbols.remove(&location);
+        }
+    }
+
+    // Entries from other replace this table's at the same location
+    pub fn overlay(&mut self, other: &SymbolTable) {
+        for (location, symbol) in &other.symbols {
+            self.symbols.insert(*location, symbol.clone());
+        }
+    }
+
+    // Imports keep existing text where the file has none, so files can be layered
+    fn merge(&mut self, location: SymbolAddress, label: &str, comment: &str) {
+        let symbol = self.symbols.entry(location).or_default();
+        if !label.is_empty() {
+            symbol.label = label.to_owned();
+        }
+        if !comment.is_empty() {
+            symbol.comment = comment.to_owned();
+        }
+        self.remove_if_empty(location);
+    }
+
+    // FCEUX .nl: "$C000#Label#Comment" per line. Bank files (game.nes.0.nl) hold ROM labels
+    // for that 16K bank, game.nes.ram.nl has no bank.
+    pub fn import_nl(&mut self, text: &str, bank: Option<usize>) -> usize {
+        let mut count = 0;
+
+        for line in text.lines() {
+            let mut fields = line.trim_end_matches('\r').splitn(3, '#');
+            let Some(address) = fields.next().and_then(|a| a.strip_prefix('$')) else {
+                continue;
+            };
+            // Arrays are written as $0300/10
+            let address = address.split('/').next().unwrap_or(address);
+            let Ok(address) = u16::from_str_radix(address.trim(), 16) else {
+                continue;
+            };
+
+            let location = match bank {
+                Some(bank) => SymbolAddress::PrgRom(bank * 0x4000 + (address & 0x3FFF) as usize),
+                None => SymbolAddress::Cpu(address),
+            };
+            let label = fields.next().unwrap_or("").trim();
+            let comment = fields.next().unwrap_or("").trim();
+            self.merge(location, label, comment);
+            count += 1;
+        }
+
+        count
+    }
+
+    // Mesen .mlb: "Type:Address[-End]:Label[:Comment]", with Mesen 1 or Mesen 2 type names
+    pub fn import_mlb(&mut self, text: &str) -> usize {
+        let mut count = 0;
+
+        for line in text.lines() {
+            let mut fields = line.trim_end_matches('\r').splitn(4, ':');
+            let (Some(kind), Some(address)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let address = address.split('-').next().unwrap_or(address);
+            let Ok(address) = usize::from_str_radix(address.trim(), 16) else {
+                continue;
+            };
+
+            let location = match kind {
+                "P" | "NesPrgRom" => SymbolAddress::PrgRom(address),
+                "R" | "NesInternalRam" => SymbolAddress::Cpu((address & 0x07FF) as u16),
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" if address < 0x2000 => {
+                    SymbolAddress::Cpu(0x6000 + address as u16)
+                }
+                "G" | "NesMemory" if address <= 0xFFFF => SymbolAddress::Cpu(address as u16),
+                _ => continue,
+            };
+            let label = fields.next().unwrap_or("").trim();
+            let comment = fields.next().unwrap_or("").replace("\\n", "\n");
+            self.merge(location, label, &comment);
+            count += 1;
+        }
+
+        count
+    }
+
+    pub fn export_mlb(&self) -> String {
+        let mut text = String::new();
+
+        for (location, symbol) in &self.symbols {
+            let (kind, address) = match *location {
+                SymbolAddress::PrgRom(offset) => ("P", offset),
+                SymbolAddress::Cpu(address @ 0x0000..=0x1FFF) => ("R", (address & 0x07FF) as usize),
+                SymbolAddress::Cpu(address @ 0x6000..=0x7FFF) => ("S", (address - 0x6000) as usize),
+                SymbolAddress::Cpu(address) => ("G", address as usize),
+            };
+            text.push_str(&format!("{}:{:04X}:{}", kind, address, symbol.label));
+            if !symbol.comment.is_empty() {
+                text.push(':');
+                text.push_str(&symbol.comment.replace('\n', "\\n"));
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+
+    // ca65/ld65 debug info (ld65 --dbgfile). Labels in ROM segments are placed by their
+    // output file offset, which includes the 16-byte iNES header.
+    pub fn import_dbg(&mut self, text: &str) -> Result<usize, &'static str> {
+        if !text.starts_with("version") {
+            return Err("Not a ca65 debug info file");
+        }
+
+        // Segment id to (start address, ROM offset of start)
+        let mut segments = BTreeMap::new();
+        for line in text.lines() {
+            let Some(attributes) = line.strip_prefix("seg\t") else {
+                continue;
+            };
+            let attributes = parse_dbg_attributes(attributes);
+            let id = dbg_number(&attributes, "id");
+            let start = dbg_number(&attributes, "start");
+            let rom_offset = dbg_number(&attributes, "ooffs")
+                .filter(|&offset| offset >= 16)
+                .map(|offset| offset - 16);
+            if let (Some(id), Some(start)) = (id, start) {
+                segments.insert(id, (start, rom_offset));
+            }
+        }
+
+        let mut count = 0;
+        for line in text.lines() {
+            let Some(attributes) = line.strip_prefix("sym\t") else {
+                continue;
+            };
+            let attributes = parse_dbg_attributes(attributes);
+            if dbg_value(&attributes, "type") != Some("lab") {
+                continue;
+            }
+            let (Some(name), Some(value)) = (
+                dbg_value(&attributes, "name"),
+                dbg_number(&attributes, "val"),
+            ) else {
+                continue;
+            };
+
+            let segment = dbg_number(&attributes, "seg").and_then(|id| segments.get(&id));
+            let location = match segment {
+                Some(&(start, Some(rom_offset))) if value >= start => {
+                    SymbolAddress::PrgRom(rom_offset + value - start)
+                }
+                _ if value <= 0xFFFF => SymbolAddress::Cpu(value as u16),
+                _ => continue,
+            };
+            self.merge(location, name, "");
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+// key=value pairs separated by commas, values may be quoted
+fn parse_dbg_attributes(text: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let Some((key, value_start)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, remainder) = if let Some(quoted) = value_start.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            let remainder = quoted[end..].trim_start_matches('"');
+            (&quoted[..end], remainder)
+        } else {
+            let end = value_start.find(',').unwrap_or(value_start.len());
+            (&value_start[..end], &value_start[end..])
+        };
+        attributes.push((key.trim(), value));
+        rest = remainder.trim_start_matches(',');
+    }
+
+    attributes
+}
+
+fn dbg_value<'a>(attributes: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+    attributes.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+fn dbg_number(attributes: &[(&str, &str)], key: &str) -> Option<usize> {
+    let value = dbg_value(attributes, key)?;
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
@@ -0,0 +1,316 @@
+// Runs the emulator on its own thread, paced by a high resolution timer rather than by the
+// frontend's repaints. Finished frames come out through a triple buffer and the frontend
+// steers the emulator with commands, so neither side waits on the other. Debug views lock
+// the emulator for a consistent look at it between two frames.
+
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::filter::RgbaImage;
+use crate::nes::{Nes, RunMode, Speed};
+use crate::palette::ColorPalette;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Sleeps can overshoot, the last stretch before a frame is due is spun instead
+const SPIN_TIME: Duration = Duration::from_millis(1);
+// Catch up after short stalls, but don't race to recover long ones
+const MAX_CATCH_UP: Duration = Duration::from_millis(100);
+// Longest an idle thread sleeps before checking on the emulator, commands wake it sooner
+const IDLE_WAIT: Duration = Duration::from_millis(50);
+
+pub enum Command {
+    PowerOn,
+    PowerOff,
+    Reset,
+    SetRunMode(RunMode),
+    SetButtons(usize, u8),
+    // Swaps the cartridge, a ROM that fails to load is reported by take_error
+    LoadRom(Vec<u8>),
+    // Runs one frame and pauses
+    AdvanceFrame,
+    // Plays backwards at normal speed while set
+    Rewind(bool),
+    // Paces to this speed instead of the emulator's own while set, for fast-forward
+    OverrideSpeed(Option<Speed>),
+}
+
+pub struct Frame {
+    // As Nes::frame_buffer and Nes::frame_phase gave them
+    pub pixels: Box<[u16]>,
+    pub phase: u8,
+    // Frames published so far, this one included
+    pub number: u64,
+}
+
+impl Frame {
+    fn new() -> Self {
+        Self {
+            pixels: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+            phase: 0,
+            number: 0,
+        }
+    }
+
+    // The frame through the palette, ready for the filter chain
+    pub fn image(&self, palette: &ColorPalette) -> RgbaImage {
+        RgbaImage::from_rgba(SCREEN_WIDTH, SCREEN_HEIGHT, &palette.to_rgba(&self.pixels))
+    }
+}
+
+// Everything the emulation thread needs, behind one lock
+struct Core {
+    nes: Nes,
+    commands: Receiver<Command>,
+    error: Option<&'static str>,
+    rewinding: bool,
+    speed_override: Option<Speed>,
+    // The emulation thread's third of the triple buffer
+    back: Box<Frame>,
+    published: u64,
+}
+
+impl Core {
+    fn apply_commands(&mut self, middle: &Mutex<(Box<Frame>, bool)>) {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                Command::PowerOn => self.nes.power_on(),
+                Command::PowerOff => self.nes.power_off(),
+                Command::Reset => self.nes.reset(),
+                Command::SetRunMode(run_mode) => self.nes.set_run_mode(run_mode),
+                Command::SetButtons(port, buttons) => self.nes.set_buttons(port, buttons),
+                Command::LoadRom(data) => {
+                    self.nes.eject_cartridge();
+                    if let Err(error) = self.nes.insert_cartridge(&data) {
+                        self.error = Some(error);
+                    }
+                }
+                Command::AdvanceFrame => {
+                    if self.nes.is_powered_on() {
+                        self.nes.advance_frame();
+                        self.publish(middle);
+                    }
+                }
+                Command::Rewind(rewinding) => self.rewinding = rewinding,
+                Command::OverrideSpeed(speed) => self.speed_override = speed,
+            }
+        }
+    }
+
+    // Fills the back buffer and swaps it with the middle one for the frontend to take
+    fn publish(&mut self, middle: &Mutex<(Box<Frame>, bool)>) {
+        self.published += 1;
+        self.back.pixels.copy_from_slice(self.nes.frame_buffer());
+        self.back.phase = self.nes.frame_phase();
+        self.back.number = self.published;
+
+        let mut middle = middle.lock().unwrap();
+        mem::swap(&mut middle.0, &mut self.back);
+        middle.1 = true;
+    }
+}
+
+struct Shared {
+    core: Mutex<Core>,
+    // The middle of the triple buffer, and whether it holds a frame not taken yet
+    middle: Mutex<(Box<Frame>, bool)>,
+    // Frontend locks waiting for the emulator, the thread stands back for them between frames
+    waiting: AtomicUsize,
+    quit: AtomicBool,
+}
+
+impl Shared {
+    // A panic on the emulation thread takes the frontend down with it, as it did when the
+    // emulator ran on the frontend's thread
+    fn lock_core(&self) -> MutexGuard<'_, Core> {
+        self.core.lock().expect("Emulation thread panicked")
+    }
+}
+
+pub struct EmulationThread {
+    shared: Arc<Shared>,
+    commands: Sender<Command>,
+    // The frontend's third of the triple buffer
+    front: Box<Frame>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EmulationThread {
+    // on_frame runs on the emulation thread after each published frame, to wake the frontend
+    pub fn spawn(nes: Nes, on_frame: impl Fn() + Send + 'static) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            core: Mutex::new(Core {
+                nes,
+                commands: receiver,
+                error: None,
+                rewinding: false,
+                speed_override: None,
+                back: Box::new(Frame::new()),
+                published: 0,
+            }),
+            middle: Mutex::new((Box::new(Frame::new()), false)),
+            waiting: AtomicUsize::new(0),
+            quit: AtomicBool::new(false),
+        });
+
+        let thread_shared = Arc::clone(&shared);
+        let thread = thread::Builder::new()
+            .name("emulation".to_owned())
+            .spawn(move || run(&thread_shared, on_frame))
+            .expect("Could not start emulation thread");
+
+        Self {
+            shared,
+            commands,
+            front: Box::new(Frame::new()),
+            thread: Some(thread),
+        }
+    }
+
+    // Commands apply in order, before the next frame or the next lock
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+        self.wake();
+    }
+
+    // The emulator between two frames, with every command sent so far applied. The thread
+    // waits while the guard is held, so it should not be kept long.
+    pub fn lock(&self) -> NesGuard<'_> {
+        self.shared.waiting.fetch_add(1, Ordering::AcqRel);
+        let mut core = self.shared.lock_core();
+        self.shared.waiting.fetch_sub(1, Ordering::AcqRel);
+        core.apply_commands(&self.shared.middle);
+        NesGuard { core, owner: self }
+    }
+
+    // The error of the last command that failed since the previous call
+    pub fn take_error(&self) -> Option<&'static str> {
+        let mut core = self.shared.lock_core();
+        core.apply_commands(&self.shared.middle);
+        core.error.take()
+    }
+
+    // The newest frame, when one was published since the last call
+    pub fn take_frame(&mut self) -> Option<&Frame> {
+        let mut middle = self.shared.middle.lock().unwrap();
+        if !middle.1 {
+            return None;
+        }
+        mem::swap(&mut middle.0, &mut self.front);
+        middle.1 = false;
+        drop(middle);
+        Some(&self.front)
+    }
+
+    // The frame last returned by take_frame
+    pub fn frame(&self) -> &Frame {
+        &self.front
+    }
+
+    fn wake(&self) {
+        if let Some(thread) = self.thread.as_ref() {
+            thread.thread().unpark();
+        }
+    }
+}
+
+impl Drop for EmulationThread {
+    fn drop(&mut self) {
+        self.shared.quit.store(true, Ordering::Release);
+        self.wake();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Derefs to the locked emulator and wakes the thread when dropped, changes like a new run
+// mode then take effect right away
+pub struct NesGuard<'a> {
+    core: MutexGuard<'a, Core>,
+    owner: &'a EmulationThread,
+}
+
+impl Deref for NesGuard<'_> {
+    type Target = Nes;
+
+    fn deref(&self) -> &Nes {
+        &self.core.nes
+    }
+}
+
+impl DerefMut for NesGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Nes {
+        &mut self.core.nes
+    }
+}
+
+impl Drop for NesGuard<'_> {
+    fn drop(&mut self) {
+        self.owner.wake();
+    }
+}
+
+fn run(shared: &Shared, on_frame: impl Fn()) {
+    let mut next_frame = Instant::now();
+
+    while !shared.quit.load(Ordering::Acquire) {
+        if shared.waiting.load(Ordering::Acquire) > 0 {
+            thread::yield_now();
+            continue;
+        }
+
+        let mut core = shared.lock_core();
+        core.apply_commands(&shared.middle);
+
+        let powered_on = core.nes.is_powered_on();
+        let rewinding = core.rewinding && powered_on;
+        if !rewinding && (!powered_on || core.nes.get_run_mode() != RunMode::Running) {
+            drop(core);
+            thread::park_timeout(IDLE_WAIT);
+            next_frame = Instant::now();
+            continue;
+        }
+
+        let speed = match core.speed_override {
+            _ if rewinding => Speed::NORMAL,
+            Some(speed) => speed,
+            None => core.nes.speed(),
+        };
+        let frame_duration = speed.frame_duration(core.nes.region());
+
+        let now = Instant::now();
+        if frame_duration.is_none() || now.saturating_duration_since(next_frame) > MAX_CATCH_UP {
+            next_frame = now;
+        }
+        if now < next_frame {
+            drop(core);
+            let remaining = next_frame - now;
+            if remaining > SPIN_TIME {
+                thread::park_timeout(remaining - SPIN_TIME);
+            } else {
+                while Instant::now() < next_frame {
+                    std::hint::spin_loop();
+                }
+            }
+            continue;
+        }
+
+        if rewinding {
+            core.nes.rewind(1);
+        } else {
+            core.nes.run_frame();
+        }
+        core.publish(&shared.middle);
+        if let Some(frame_duration) = frame_duration {
+            next_frame += frame_duration;
+        }
+        drop(core);
+        on_frame();
+    }
+}
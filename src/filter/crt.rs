@@ -0,0 +1,39 @@
+// Scanlines and an aperture grille, a cheap stand-in for a CRT shader
+
+use super::RgbaImage;
+
+// Brightness of the gap between scanlines
+const SCANLINE_BRIGHTNESS: u16 = 160;
+// Brightness of the two channels a grille column doesn't let through
+const GRILLE_BRIGHTNESS: u16 = 200;
+
+// line_height is output rows per NES scanline: the last row of each is darkened, or every
+// other row when the image isn't scaled vertically
+pub(super) fn mask(image: &RgbaImage, line_height: usize) -> RgbaImage {
+    let mut output = RgbaImage::blank(image.width, image.height);
+    for y in 0..image.height {
+        let gap = if line_height > 1 {
+            y % line_height == line_height - 1
+        } else {
+            y % 2 == 1
+        };
+
+        for x in 0..image.width {
+            let mut pixel = image.get(x as isize, y as isize);
+            // Red, green and blue stripes
+            let stripe = x % 3;
+            for (channel, value) in pixel.iter_mut().take(3).enumerate() {
+                let mut brightness = 255;
+                if channel != stripe {
+                    brightness = brightness * GRILLE_BRIGHTNESS / 255;
+                }
+                if gap {
+                    brightness = brightness * SCANLINE_BRIGHTNESS / 255;
+                }
+                *value = (*value as u16 * brightness / 255) as u8;
+            }
+            output.set(x, y, pixel);
+        }
+    }
+    output
+}
@@ -0,0 +1,102 @@
+// hq2x, hq3x and hq4x
+//
+// Uses the hqx similarity test on YUV and its corner blends, but in rule form instead of the
+// original 256 entry pattern tables: each output pixel takes its corner's blend, fading out
+// towards the middle of the block. Flat areas are left alone and edges get hqx's blends,
+// long shallow lines come out a little less smooth than with the tables.
+
+use super::{Pixel, RgbaImage, mix};
+
+const THRESHOLD_Y: i32 = 48;
+const THRESHOLD_U: i32 = 7;
+const THRESHOLD_V: i32 = 6;
+
+fn yuv([red, green, blue, _]: Pixel) -> [i32; 3] {
+    let (red, green, blue) = (red as i32, green as i32, blue as i32);
+    [
+        (299 * red + 587 * green + 114 * blue) / 1000,
+        (-169 * red - 331 * green + 500 * blue) / 1000,
+        (500 * red - 419 * green - 81 * blue) / 1000,
+    ]
+}
+
+fn differ(a: Pixel, b: Pixel) -> bool {
+    if a == b {
+        return false;
+    }
+    let (a, b) = (yuv(a), yuv(b));
+    (a[0] - b[0]).abs() > THRESHOLD_Y
+        || (a[1] - b[1]).abs() > THRESHOLD_U
+        || (a[2] - b[2]).abs() > THRESHOLD_V
+}
+
+// Color for the corner of the center pixel between two side neighbors and a diagonal one
+fn corner(center: Pixel, side1: Pixel, side2: Pixel, diagonal: Pixel) -> Pixel {
+    if differ(center, side1) && differ(center, side2) && !differ(side1, side2) {
+        // An edge cuts the corner. Cut deeper when it carries on through the diagonal, a
+        // one pixel notch only gets rounded off a little.
+        let sides = mix(side1, side2, 0.5);
+        mix(
+            center,
+            sides,
+            if differ(center, diagonal) { 0.5 } else { 0.25 },
+        )
+    } else {
+        center
+    }
+}
+
+pub(super) fn scale(image: &RgbaImage, factor: usize) -> RgbaImage {
+    let mut output = RgbaImage::blank(image.width * factor, image.height * factor);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (xi, yi) = (x as isize, y as isize);
+            let center = image.get(xi, yi);
+            let up = image.get(xi, yi - 1);
+            let down = image.get(xi, yi + 1);
+            let left = image.get(xi - 1, yi);
+            let right = image.get(xi + 1, yi);
+            // Top left, top right, bottom left, bottom right
+            let corners = [
+                corner(center, up, left, image.get(xi - 1, yi - 1)),
+                corner(center, up, right, image.get(xi + 1, yi - 1)),
+                corner(center, down, left, image.get(xi - 1, yi + 1)),
+                corner(center, down, right, image.get(xi + 1, yi + 1)),
+            ];
+
+            for sub_y in 0..factor {
+                for sub_x in 0..factor {
+                    // Position of the output pixel's center within the block, 0 to 1
+                    let u = (sub_x as f32 + 0.5) / factor as f32;
+                    let v = (sub_y as f32 + 0.5) / factor as f32;
+                    let pixel = blend_corners(center, &corners, u, v);
+                    output.set(x * factor + sub_x, y * factor + sub_y, pixel);
+                }
+            }
+        }
+    }
+    output
+}
+
+// Each corner's blend counts fully up to half a block away and not at all from the middle
+// on, pixels between two corners share them
+fn blend_corners(center: Pixel, corners: &[Pixel; 4], u: f32, v: f32) -> Pixel {
+    let mut total = 0.0;
+    let mut sum = [0.0f32; 4];
+    for (index, pixel) in corners.iter().enumerate() {
+        let corner_u = (index & 1) as f32;
+        let corner_v = (index >> 1) as f32;
+        let distance = (u - corner_u).abs() + (v - corner_v).abs();
+        let weight = ((1.0 - distance) * 2.0).clamp(0.0, 1.0);
+        total += weight;
+        for (sum, &channel) in sum.iter_mut().zip(pixel) {
+            *sum += channel as f32 * weight;
+        }
+    }
+    if total == 0.0 {
+        return center;
+    }
+
+    let average = sum.map(|channel| (channel / total).round() as u8);
+    mix(center, average, total.min(1.0))
+}
@@ -0,0 +1,196 @@
+// Post-processing of the PPU's output, run on the CPU so it needs no GPU
+
+mod crt;
+mod hqx;
+mod ntsc;
+mod scale2x;
+mod xbr;
+
+pub use self::ntsc::{NTSC_OUTPUT_WIDTH, NtscFilter, NtscSettings};
+use crate::ppu::SCREEN_HEIGHT;
+
+type Pixel = [u8; 4];
+
+pub struct RgbaImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<Pixel>,
+}
+
+impl RgbaImage {
+    // Four bytes per pixel, row by row
+    pub fn new(width: usize, height: usize, rgba: &[u8]) -> Result<Self, &'static str> {
+        if rgba.len() != width * height * 4 {
+            return Err("Image data does not match its size");
+        }
+        Ok(Self::from_rgba(width, height, rgba))
+    }
+
+    // For sizes that are known to match
+    pub(crate) fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Self {
+        let pixels = rgba
+            .chunks_exact(4)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+            .collect();
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn blank(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0; 4]; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels.iter().flatten().copied().collect()
+    }
+
+    // Out of range coordinates are clamped to the edge
+    fn get(&self, x: isize, y: isize) -> Pixel {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, pixel: Pixel) {
+        self.pixels[y * self.width + x] = pixel;
+    }
+}
+
+// Weight is how much of b ends up in the result
+fn mix(a: Pixel, b: Pixel, weight: f32) -> Pixel {
+    let mut result = a;
+    for (channel, (&a, &b)) in result.iter_mut().zip(a.iter().zip(b.iter())) {
+        *channel = (a as f32 + (b as f32 - a as f32) * weight).round() as u8;
+    }
+    result
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ScaleFilter {
+    Scale2x,
+    Scale3x,
+    Hq2x,
+    Hq3x,
+    Hq4x,
+    Xbr2x,
+    Xbr3x,
+    Xbr4x,
+    // Scanlines and an aperture grille over the image at its current size
+    Crt,
+}
+
+impl ScaleFilter {
+    pub const ALL: [ScaleFilter; 9] = [
+        ScaleFilter::Scale2x,
+        ScaleFilter::Scale3x,
+        ScaleFilter::Hq2x,
+        ScaleFilter::Hq3x,
+        ScaleFilter::Hq4x,
+        ScaleFilter::Xbr2x,
+        ScaleFilter::Xbr3x,
+        ScaleFilter::Xbr4x,
+        ScaleFilter::Crt,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScaleFilter::Scale2x => "scale2x",
+            ScaleFilter::Scale3x => "scale3x",
+            ScaleFilter::Hq2x => "hq2x",
+            ScaleFilter::Hq3x => "hq3x",
+            ScaleFilter::Hq4x => "hq4x",
+            ScaleFilter::Xbr2x => "xbr2x",
+            ScaleFilter::Xbr3x => "xbr3x",
+            ScaleFilter::Xbr4x => "xbr4x",
+            ScaleFilter::Crt => "crt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|filter| filter.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn apply(self, image: &RgbaImage) -> RgbaImage {
+        match self {
+            ScaleFilter::Scale2x => scale2x::scale(image, 2),
+            ScaleFilter::Scale3x => scale2x::scale(image, 3),
+            ScaleFilter::Hq2x => hqx::scale(image, 2),
+            ScaleFilter::Hq3x => hqx::scale(image, 3),
+            ScaleFilter::Hq4x => hqx::scale(image, 4),
+            ScaleFilter::Xbr2x => xbr::scale(image, 2),
+            ScaleFilter::Xbr3x => xbr::scale(image, 3),
+            ScaleFilter::Xbr4x => xbr::scale(image, 4),
+            // Taller images get one dark row per NES scanline rather than every other row
+            ScaleFilter::Crt => crt::mask(image, (image.height / SCREEN_HEIGHT).max(1)),
+        }
+    }
+}
+
+// Filters run in order, each on the output of the one before
+#[derive(Clone, Default, PartialEq)]
+pub struct FilterChain {
+    filters: Vec<ScaleFilter>,
+}
+
+impl FilterChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Comma separated filter names, "hq2x,crt". Empty or "none" is no filtering.
+    pub fn parse(text: &str) -> Result<Self, &'static str> {
+        let mut chain = Self::new();
+        for name in text.split(',').map(str::trim) {
+            if name.is_empty() || name.eq_ignore_ascii_case("none") {
+                continue;
+            }
+            chain.push(ScaleFilter::from_name(name).ok_or("Unknown filter name")?);
+        }
+        Ok(chain)
+    }
+
+    pub fn filters(&self) -> &[ScaleFilter] {
+        &self.filters
+    }
+
+    pub fn push(&mut self, filter: ScaleFilter) {
+        self.filters.push(filter);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.filters.len() {
+            self.filters.remove(index);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn apply(&self, image: RgbaImage) -> RgbaImage {
+        self.filters
+            .iter()
+            .fold(image, |image, filter| filter.apply(&image))
+    }
+}
@@ -0,0 +1,212 @@
+// Composite NTSC signal simulation
+//
+// Each pixel is turned into the 8 samples of square wave the PPU puts on the composite line,
+// at 12 samples per color subcarrier cycle, and decoded back the way a TV would: luma by
+// low-pass filtering, chroma by demodulating against the subcarrier. Luma that isn't fully
+// filtered gives dot crawl, luma edges picked up as chroma give artifact colors and fringes.
+
+use std::f32::consts::PI;
+
+use super::RgbaImage;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// 256 pixels at 8:7 pixel aspect, wide enough to keep most of the chroma detail
+pub const NTSC_OUTPUT_WIDTH: usize = 602;
+
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_LINE: usize = SCREEN_WIDTH * SAMPLES_PER_PIXEL;
+// A line is 341 dots, which leaves the next one 4 samples further into the subcarrier
+const LINE_PHASE_STEP: usize = 341 * SAMPLES_PER_PIXEL % 12;
+
+// Line voltages for the four luma levels, low and high half of the wave
+const LEVELS_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const LEVELS_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK: f32 = 0.518;
+const WHITE: f32 = 1.962;
+// Emphasized phases of the wave are pulled down by this much
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+// Lines up the decoder's subcarrier with the color burst, in twelfths of a cycle
+const BURST_PHASE: f32 = 3.9;
+
+// Each setting runs from -1 to 1, 0 is the look of a typical TV
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct NtscSettings {
+    // Rotates all colors, 1 is half a turn
+    pub hue: f32,
+    pub saturation: f32,
+    // Narrower luma filtering: crisper edges but more dot crawl
+    pub sharpness: f32,
+    // How much luma edges show up as color
+    pub fringing: f32,
+    // How far color smears sideways
+    pub bleed: f32,
+}
+
+pub struct NtscFilter {
+    settings: NtscSettings,
+    // Subcarrier reference for each sample phase, hue applied
+    cos: [f32; 12],
+    sin: [f32; 12],
+    signal: Vec<f32>,
+    // Prefix sums over the line, for box filters of any width
+    signal_sum: Vec<f32>,
+    cos_sum: Vec<f32>,
+    sin_sum: Vec<f32>,
+    // The same over the signal with luma removed
+    clean_cos_sum: Vec<f32>,
+    clean_sin_sum: Vec<f32>,
+}
+
+impl NtscFilter {
+    pub fn new(settings: NtscSettings) -> Self {
+        let mut filter = Self {
+            settings,
+            cos: [0.0; 12],
+            sin: [0.0; 12],
+            signal: vec![0.0; SAMPLES_PER_LINE],
+            signal_sum: vec![0.0; SAMPLES_PER_LINE + 1],
+            cos_sum: vec![0.0; SAMPLES_PER_LINE + 1],
+            sin_sum: vec![0.0; SAMPLES_PER_LINE + 1],
+            clean_cos_sum: vec![0.0; SAMPLES_PER_LINE + 1],
+            clean_sin_sum: vec![0.0; SAMPLES_PER_LINE + 1],
+        };
+        filter.set_settings(settings);
+        filter
+    }
+
+    pub fn settings(&self) -> NtscSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: NtscSettings) {
+        self.settings = settings;
+        let hue = settings.hue.clamp(-1.0, 1.0) * PI;
+        for phase in 0..12 {
+            let angle = PI * (phase as f32 + BURST_PHASE) / 6.0 + hue;
+            self.cos[phase] = angle.cos();
+            self.sin[phase] = angle.sin();
+        }
+    }
+
+    // Takes PPU output (palette index with emphasis bits) and the subcarrier phase of its
+    // first pixel, returns an NTSC_OUTPUT_WIDTH wide image
+    pub fn apply(&mut self, pixels: &[u16], phase: u8) -> RgbaImage {
+        let mut rgba = Vec::with_capacity(NTSC_OUTPUT_WIDTH * SCREEN_HEIGHT * 4);
+        for (line, line_pixels) in pixels.chunks_exact(SCREEN_WIDTH).enumerate() {
+            let line_phase = (phase as usize + line * LINE_PHASE_STEP) % 12;
+            self.encode_line(line_pixels, line_phase);
+            self.decode_line(&mut rgba);
+        }
+        RgbaImage::from_rgba(
+            NTSC_OUTPUT_WIDTH,
+            rgba.len() / (NTSC_OUTPUT_WIDTH * 4),
+            &rgba,
+        )
+    }
+
+    fn encode_line(&mut self, pixels: &[u16], line_phase: usize) {
+        for (x, &pixel) in pixels.iter().enumerate() {
+            let start = x * SAMPLES_PER_PIXEL;
+            for sample in start..start + SAMPLES_PER_PIXEL {
+                let voltage = composite_voltage(pixel, (line_phase + sample) % 12);
+                self.signal[sample] = (voltage - BLACK) / (WHITE - BLACK);
+            }
+        }
+
+        for sample in 0..SAMPLES_PER_LINE {
+            let signal = self.signal[sample];
+            let phase = (line_phase + sample) % 12;
+            self.signal_sum[sample + 1] = self.signal_sum[sample] + signal;
+            self.cos_sum[sample + 1] = self.cos_sum[sample] + signal * self.cos[phase];
+            self.sin_sum[sample + 1] = self.sin_sum[sample] + signal * self.sin[phase];
+        }
+
+        // A full subcarrier cycle of luma filtering leaves no chroma to speak of
+        for sample in 0..SAMPLES_PER_LINE {
+            let chroma = self.signal[sample] - box_average(&self.signal_sum, sample, 12);
+            let phase = (line_phase + sample) % 12;
+            self.clean_cos_sum[sample + 1] = self.clean_cos_sum[sample] + chroma * self.cos[phase];
+            self.clean_sin_sum[sample + 1] = self.clean_sin_sum[sample] + chroma * self.sin[phase];
+        }
+    }
+
+    fn decode_line(&self, rgba: &mut Vec<u8>) {
+        let settings = &self.settings;
+        let sharpness = (settings.sharpness.clamp(-1.0, 1.0) + 1.0) / 2.0;
+        let fringing = settings.fringing.clamp(-1.0, 1.0) + 1.0;
+        let saturation = settings.saturation.clamp(-1.0, 1.0) + 1.0;
+        // One to three subcarrier cycles of chroma filtering
+        let chroma_width = (24.0 + settings.bleed.clamp(-1.0, 1.0) * 12.0).round() as usize;
+
+        for x in 0..NTSC_OUTPUT_WIDTH {
+            let center = ((2 * x + 1) * SAMPLES_PER_LINE) / (2 * NTSC_OUTPUT_WIDTH);
+
+            let luma = box_average(&self.signal_sum, center, 12);
+            let narrow_luma = box_average(&self.signal_sum, center, 6);
+            let y = luma + (narrow_luma - luma) * sharpness;
+
+            let clean_i = box_average(&self.clean_cos_sum, center, chroma_width);
+            let clean_q = box_average(&self.clean_sin_sum, center, chroma_width);
+            let raw_i = box_average(&self.cos_sum, center, chroma_width);
+            let raw_q = box_average(&self.sin_sum, center, chroma_width);
+            // Averaging a wave against its own carrier gives half its amplitude
+            let i = 2.0 * saturation * (clean_i + (raw_i - clean_i) * fringing);
+            let q = 2.0 * saturation * (clean_q + (raw_q - clean_q) * fringing);
+
+            let red = y + 0.946882 * i + 0.623557 * q;
+            let green = y - 0.274788 * i - 0.635691 * q;
+            let blue = y - 1.108545 * i + 1.709007 * q;
+            rgba.extend_from_slice(&[to_channel(red), to_channel(green), to_channel(blue), 0xFF]);
+        }
+    }
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        Self::new(NtscSettings::default())
+    }
+}
+
+// Voltage of one sample of a pixel's wave. The hue picks which half of the subcarrier cycle
+// is high, emphasis attenuates the phases of its color.
+fn composite_voltage(pixel: u16, phase: usize) -> f32 {
+    let hue = (pixel & 0x0F) as usize;
+    let mut level = ((pixel >> 4) & 0x03) as usize;
+    let emphasis = (pixel >> 6) & 0x07;
+
+    // Columns $E and $F are black
+    if hue >= 0x0E {
+        level = 1;
+    }
+    let in_phase = |color: usize| (color + phase) % 12 < 6;
+
+    let mut voltage = match hue {
+        0x00 => LEVELS_HIGH[level],
+        0x0D.. => LEVELS_LOW[level],
+        _ if in_phase(hue) => LEVELS_HIGH[level],
+        _ => LEVELS_LOW[level],
+    };
+
+    // Red, green and blue emphasis line up with hues $C, $4 and $8
+    if hue < 0x0E
+        && ((emphasis & 0x01 != 0 && in_phase(0x0C))
+            || (emphasis & 0x02 != 0 && in_phase(0x04))
+            || (emphasis & 0x04 != 0 && in_phase(0x08)))
+    {
+        voltage *= EMPHASIS_ATTENUATION;
+    }
+    voltage
+}
+
+// Average of the width samples around center, reading past the line's ends as black
+fn box_average(sums: &[f32], center: usize, width: usize) -> f32 {
+    let last = sums.len() - 1;
+    let start = center.saturating_sub(width / 2).min(last);
+    let end = (center + width - width / 2).min(last);
+    (sums[end] - sums[start]) / width as f32
+}
+
+fn to_channel(value: f32) -> u8 {
+    (value * 255.0).round().clamp(0.0, 255.0) as u8
+}
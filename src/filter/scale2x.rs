@@ -0,0 +1,84 @@
+// Scale2x and Scale3x (AdvMAME), exact: output pixels only ever copy input pixels
+
+use super::{Pixel, RgbaImage};
+
+pub(super) fn scale(image: &RgbaImage, factor: usize) -> RgbaImage {
+    let mut output = RgbaImage::blank(image.width * factor, image.height * factor);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (xi, yi) = (x as isize, y as isize);
+            // a b c
+            // d e f
+            // g h i
+            let neighbors = [
+                image.get(xi - 1, yi - 1),
+                image.get(xi, yi - 1),
+                image.get(xi + 1, yi - 1),
+                image.get(xi - 1, yi),
+                image.get(xi, yi),
+                image.get(xi + 1, yi),
+                image.get(xi - 1, yi + 1),
+                image.get(xi, yi + 1),
+                image.get(xi + 1, yi + 1),
+            ];
+            let block = if factor == 2 {
+                scale2x(&neighbors).to_vec()
+            } else {
+                scale3x(&neighbors).to_vec()
+            };
+            for (index, pixel) in block.into_iter().enumerate() {
+                output.set(
+                    x * factor + index % factor,
+                    y * factor + index / factor,
+                    pixel,
+                );
+            }
+        }
+    }
+    output
+}
+
+fn scale2x(&[_, b, _, d, e, f, _, h, _]: &[Pixel; 9]) -> [Pixel; 4] {
+    if b == h || d == f {
+        return [e; 4];
+    }
+    [
+        if d == b { d } else { e },
+        if b == f { f } else { e },
+        if d == h { d } else { e },
+        if h == f { f } else { e },
+    ]
+}
+
+fn scale3x(&[a, b, c, d, e, f, g, h, i]: &[Pixel; 9]) -> [Pixel; 9] {
+    if b == h || d == f {
+        return [e; 9];
+    }
+    [
+        if d == b { d } else { e },
+        if (d == b && e != c) || (b == f && e != a) {
+            b
+        } else {
+            e
+        },
+        if b == f { f } else { e },
+        if (d == b && e != g) || (d == h && e != a) {
+            d
+        } else {
+            e
+        },
+        e,
+        if (b == f && e != i) || (h == f && e != c) {
+            f
+        } else {
+            e
+        },
+        if d == h { d } else { e },
+        if (d == h && e != i) || (h == f && e != g) {
+            h
+        } else {
+            e
+        },
+        if h == f { f } else { e },
+    ]
+}
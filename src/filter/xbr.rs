@@ -0,0 +1,170 @@
+// xBR (Hyllian), level 2 edge rules
+//
+// Each corner of a pixel checks whether an edge runs across it by comparing weighted color
+// distances along the two diagonals, then looks one pixel further for shallow and steep
+// lines. The edge is drawn as a straight cut through the block, output pixels are blended
+// by how much of them lies past it, so every scale factor uses the same rules.
+
+use super::{Pixel, RgbaImage, mix};
+
+// Distances below this count as the same color
+const EQUAL_DISTANCE: u32 = 155;
+const SUPERSAMPLES: usize = 4;
+
+#[derive(Copy, Clone)]
+enum Edge {
+    Diagonal,
+    Shallow,
+    Steep,
+    ShallowAndSteep,
+}
+
+impl Edge {
+    // Whether a point of a block lies past the edge, with the corner at (1, 1)
+    fn covers(self, u: f32, v: f32) -> bool {
+        let diagonal = u + v >= 1.5;
+        let shallow = u + 2.0 * v >= 2.0;
+        let steep = 2.0 * u + v >= 2.0;
+        match self {
+            Edge::Diagonal => diagonal,
+            Edge::Shallow => shallow,
+            Edge::Steep => steep,
+            Edge::ShallowAndSteep => shallow || steep,
+        }
+    }
+}
+
+fn distance(a: Pixel, b: Pixel) -> u32 {
+    let yuv = |[red, green, blue, _]: Pixel| {
+        let (red, green, blue) = (red as f32, green as f32, blue as f32);
+        [
+            0.299 * red + 0.587 * green + 0.114 * blue,
+            -0.169 * red - 0.331 * green + 0.5 * blue,
+            0.5 * red - 0.419 * green - 0.081 * blue,
+        ]
+    };
+    let (a, b) = (yuv(a), yuv(b));
+    (48.0 * (a[0] - b[0]).abs() + 7.0 * (a[1] - b[1]).abs() + 6.0 * (a[2] - b[2]).abs()) as u32
+}
+
+fn equal(a: Pixel, b: Pixel) -> bool {
+    distance(a, b) < EQUAL_DISTANCE
+}
+
+// Edge across the bottom right corner, in a neighborhood flipped so that corner is the one
+// being looked at. Returns the color on the far side of the edge.
+fn corner_edge(pixel: impl Fn(isize, isize) -> Pixel) -> Option<(Edge, Pixel)> {
+    //     B  C
+    //  D  E  F  F4
+    //  G  H  I  I4
+    //     H5 I5
+    let (b, c) = (pixel(0, -1), pixel(1, -1));
+    let (d, e, f, f4) = (pixel(-1, 0), pixel(0, 0), pixel(1, 0), pixel(2, 0));
+    let (g, h, i, i4) = (pixel(-1, 1), pixel(0, 1), pixel(1, 1), pixel(2, 1));
+    let (h5, i5) = (pixel(0, 2), pixel(1, 2));
+
+    if e == f || e == h {
+        return None;
+    }
+
+    let along =
+        distance(e, c) + distance(e, g) + distance(i, f4) + distance(i, h5) + 4 * distance(h, f);
+    let across =
+        distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4 * distance(e, i);
+    let continues = (!equal(f, b) && !equal(h, d))
+        || (equal(e, i) && !equal(f, i4) && !equal(h, i5))
+        || equal(e, g)
+        || equal(e, c);
+    if along >= across || !continues {
+        return None;
+    }
+
+    let color = if distance(e, f) <= distance(e, h) {
+        f
+    } else {
+        h
+    };
+    let (shallow_distance, steep_distance) = (distance(f, g), distance(h, c));
+    let shallow = 2 * shallow_distance <= steep_distance && e != g && d != g;
+    let steep = shallow_distance >= 2 * steep_distance && e != c && b != c;
+    let edge = match (shallow, steep) {
+        (true, true) => Edge::ShallowAndSteep,
+        (true, false) => Edge::Shallow,
+        (false, true) => Edge::Steep,
+        (false, false) => Edge::Diagonal,
+    };
+    Some((edge, color))
+}
+
+// Fraction of each output pixel of a block past the edge, bottom right corner
+fn coverage(edge: Edge, factor: usize) -> Vec<f32> {
+    let samples = factor * SUPERSAMPLES;
+    let mut coverage = vec![0.0; factor * factor];
+    for sample_y in 0..samples {
+        for sample_x in 0..samples {
+            let u = (sample_x as f32 + 0.5) / samples as f32;
+            let v = (sample_y as f32 + 0.5) / samples as f32;
+            if edge.covers(u, v) {
+                let index = (sample_y / SUPERSAMPLES) * factor + sample_x / SUPERSAMPLES;
+                coverage[index] += 1.0 / (SUPERSAMPLES * SUPERSAMPLES) as f32;
+            }
+        }
+    }
+    coverage
+}
+
+pub(super) fn scale(image: &RgbaImage, factor: usize) -> RgbaImage {
+    let edges = [
+        Edge::Diagonal,
+        Edge::Shallow,
+        Edge::Steep,
+        Edge::ShallowAndSteep,
+    ];
+    let coverages: Vec<Vec<f32>> = edges.iter().map(|&edge| coverage(edge, factor)).collect();
+
+    let mut output = RgbaImage::blank(image.width * factor, image.height * factor);
+    let mut block = vec![[0; 4]; factor * factor];
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (xi, yi) = (x as isize, y as isize);
+            block.fill(image.get(xi, yi));
+
+            // Flipping the neighborhood turns each corner into the bottom right one
+            for (flip_x, flip_y) in [(1, 1), (-1, 1), (1, -1), (-1, -1)] {
+                let pixel = |dx: isize, dy: isize| image.get(xi + dx * flip_x, yi + dy * flip_y);
+                let Some((edge, color)) = corner_edge(pixel) else {
+                    continue;
+                };
+                let coverage = &coverages[edge as usize];
+                for sub_y in 0..factor {
+                    for sub_x in 0..factor {
+                        let local_x = if flip_x < 0 {
+                            factor - 1 - sub_x
+                        } else {
+                            sub_x
+                        };
+                        let local_y = if flip_y < 0 {
+                            factor - 1 - sub_y
+                        } else {
+                            sub_y
+                        };
+                        let weight = coverage[local_y * factor + local_x];
+                        if weight > 0.0 {
+                            let index = sub_y * factor + sub_x;
+                            block[index] = mix(block[index], color, weight);
+                        }
+                    }
+                }
+            }
+
+            for (index, &pixel) in block.iter().enumerate() {
+                output.set(
+                    x * factor + index % factor,
+                    y * factor + index / factor,
+                    pixel,
+                );
+            }
+        }
+    }
+    output
+}
@@ -1,8 +1,50 @@
 mod bus;
 mod cartridge;
+mod cheats;
+mod code_data_log;
+mod controller;
 mod cpu;
+mod debugger;
+mod disassembler;
+mod emulation_thread;
+mod filter;
+mod md5;
 mod memory;
+mod movie;
 mod nes;
+mod palette;
 mod ppu;
+mod profiler;
+mod ram_search;
+mod recorder;
+mod region;
+mod rewind;
+mod run_ahead;
+mod screenshot;
+mod state;
 
-pub use nes::{Nes, RunMode};
+pub use cheats::{Cheat, CheatKind};
+pub use code_data_log::CodeDataLog;
+pub use controller::Button;
+pub use cpu::{CallKind, CpuRegisters};
+pub use debugger::{
+    BreakHit, Breakpoint, BreakpointKind, CallFrame, EventKind, Expression, FrameEvent, GdbStub,
+    StackMismatch,
+};
+pub use disassembler::{
+    Instruction, Symbol, SymbolAddress, SymbolTable, disassemble, disassemble_instruction,
+};
+pub use emulation_thread::{Command, EmulationThread, Frame, NesGuard};
+pub use filter::{
+    FilterChain, NTSC_OUTPUT_WIDTH, NtscFilter, NtscSettings, RgbaImage, ScaleFilter,
+};
+pub use movie::{Movie, MovieFrame, MovieMode};
+pub use nes::{Nes, RunMode, Speed};
+pub use palette::ColorPalette;
+pub use ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use profiler::{ProfileEntry, RoutineKind};
+pub use ram_search::{CompareTo, Comparison, RamSearch, ValueSize, Watch};
+pub use recorder::VideoFormat;
+pub use region::Region;
+pub use run_ahead::RunAheadMode;
+pub use screenshot::Screenshot;
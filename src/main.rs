@@ -1,9 +1,176 @@
-use rustendulator_core::Nes;
+use rustendulator_core::{ColorPalette, FilterChain, Nes, NtscFilter, Screenshot, VideoFormat};
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
 
-fn main() {
+// Headless runner: plays a ROM for a number of frames and writes out frames along the way
+const USAGE: &str = "Usage: rustendulator_core ROM [--frames N] [--palette FILE.pal] [--ntsc] \
+                     [--filter hq2x,crt,...] [--screenshot-at-frame N]... \
+                     [--screenshot-dir DIR] [--output FILE.png] \
+                     [--record FILE.y4m|FILE.avi] [--record-audio FILE.wav] \
+                     [--gif FILE.gif] [--gif-seconds N]";
+
+struct Options {
+    rom: String,
+    frames: u64,
+    palette: Option<String>,
+    ntsc: bool,
+    filters: FilterChain,
+    // Frame numbers to write <rom>-<frame>.png at, for golden image tests
+    screenshot_frames: Vec<u64>,
+    screenshot_directory: String,
+    output: Option<String>,
+    // Every frame from power on, format picked by the extension
+    record: Option<String>,
+    record_audio: Option<String>,
+    // The whole run as a GIF, or only its last seconds
+    gif: Option<String>,
+    gif_seconds: Option<u32>,
+}
+
+fn parse_options() -> Result<Options, &'static str> {
+    let mut args = std::env::args().skip(1);
+    let mut options = Options {
+        rom: String::new(),
+        frames: 1,
+        palette: None,
+        ntsc: false,
+        filters: FilterChain::new(),
+        screenshot_frames: Vec::new(),
+        screenshot_directory: ".".to_owned(),
+        output: None,
+        record: None,
+        record_audio: None,
+        gif: None,
+        gif_seconds: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                let value = args.next().ok_or("--frames needs a count")?;
+                options.frames = value.parse().map_err(|_| "Invalid frame count")?;
+            }
+            "--palette" => options.palette = Some(args.next().ok_or("--palette needs a file")?),
+            "--ntsc" => options.ntsc = true,
+            "--filter" => {
+                options.filters = FilterChain::parse(&args.next().ok_or("--filter needs a list")?)?
+            }
+            "--screenshot-at-frame" => {
+                let value = args.next().ok_or("--screenshot-at-frame needs a frame")?;
+                let frame = value.parse().map_err(|_| "Invalid screenshot frame")?;
+                options.screenshot_frames.push(frame);
+            }
+            "--screenshot-dir" => {
+                options.screenshot_directory =
+                    args.next().ok_or("--screenshot-dir needs a directory")?
+            }
+            "--output" => options.output = Some(args.next().ok_or("--output needs a file")?),
+            "--record" => options.record = Some(args.next().ok_or("--record needs a file")?),
+            "--record-audio" => {
+                options.record_audio = Some(args.next().ok_or("--record-audio needs a file")?)
+            }
+            "--gif" => options.gif = Some(args.next().ok_or("--gif needs a file")?),
+            "--gif-seconds" => {
+                let value = args.next().ok_or("--gif-seconds needs a count")?;
+                options.gif_seconds = Some(value.parse().map_err(|_| "Invalid GIF length")?);
+            }
+            _ if arg.starts_with("--") => return Err("Unknown option"),
+            _ => options.rom = arg,
+        }
+    }
+
+    if options.rom.is_empty() {
+        return Err("No ROM given");
+    }
+    if options.record_audio.is_some() && options.record.is_none() {
+        return Err("--record-audio needs --record");
+    }
+    if options.gif_seconds.is_some() && options.gif.is_none() {
+        return Err("--gif-seconds needs --gif");
+    }
+    Ok(options)
+}
+
+// The last frame through the same filters as on screen, without them when none are given
+fn screenshot(nes: &Nes, options: &Options, ntsc: &mut NtscFilter) -> Screenshot {
+    let mut screenshot = nes.screenshot();
+    let rom_name = Path::new(&options.rom)
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    screenshot.set_rom_name(&rom_name);
+
+    if options.ntsc || !options.filters.is_empty() {
+        let frame = if options.ntsc {
+            ntsc.apply(nes.frame_buffer(), nes.frame_phase())
+        } else {
+            nes.frame_image()
+        };
+        screenshot.set_image(options.filters.apply(frame));
+    }
+    screenshot
+}
+
+fn write_png(path: &Path, screenshot: &Screenshot) -> Result<(), &'static str> {
+    fs::write(path, screenshot.to_png()?).map_err(|_| "Could not write image file")
+}
+
+fn run(options: Options) -> Result<(), &'static str> {
     let mut nes = Nes::new();
+    let rom = fs::read(&options.rom).map_err(|_| "Could not read ROM file")?;
+    nes.insert_cartridge(&rom)?;
+    if let Some(path) = &options.palette {
+        let data = fs::read(path).map_err(|_| "Could not read palette file")?;
+        nes.set_palette(ColorPalette::from_pal(&data)?);
+    }
+
+    let mut ntsc = NtscFilter::default();
+    let last_screenshot = options.screenshot_frames.iter().copied().max().unwrap_or(0);
+    let frames = options.frames.max(last_screenshot);
+    if !options.screenshot_frames.is_empty() {
+        fs::create_dir_all(&options.screenshot_directory)
+            .map_err(|_| "Could not create screenshot directory")?;
+    }
 
     nes.power_on();
+    if let Some(path) = &options.record {
+        let path = Path::new(path);
+        let format = VideoFormat::from_extension(path).ok_or("Record to a .y4m or .avi file")?;
+        nes.start_recording(path, format, options.record_audio.as_deref().map(Path::new))?;
+    }
+    if options.gif.is_some() {
+        nes.start_gif_capture(options.gif_seconds);
+    }
+    while nes.frame_count() < frames {
+        nes.run_frame();
+        if options.screenshot_frames.contains(&nes.frame_count()) {
+            let screenshot = screenshot(&nes, &options, &mut ntsc);
+            let name = format!("{}-{}.png", screenshot.rom_name(), screenshot.frame());
+            write_png(
+                &Path::new(&options.screenshot_directory).join(name),
+                &screenshot,
+            )?;
+        }
+    }
+
+    nes.stop_recording()?;
+    if let Some(path) = &options.gif {
+        fs::write(path, nes.gif()?).map_err(|_| "Could not write GIF file")?;
+    }
+
+    if let Some(path) = &options.output {
+        write_png(Path::new(path), &screenshot(&nes, &options, &mut ntsc))?;
+    }
+    Ok(())
+}
 
-    nes.run_frame();
+fn main() -> ExitCode {
+    let result = parse_options().and_then(run);
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
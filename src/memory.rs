@@ -1,3 +1,5 @@
+use crate::state::{ChunkReader, StateWriter};
+
 pub(crate) struct Ram {
     data: [u8; 0x0800],
 }
@@ -16,6 +18,14 @@ impl Ram {
         let index = (address & 0x07ff) as usize;
         self.data[index] = value;
     }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+    }
+
+    pub(crate) fn load_state(&mut self, chunk: &mut ChunkReader) -> Result<(), &'static str> {
+        chunk.read_bytes(&mut self.data)
+    }
 }
 
 pub(crate) struct Vram {
@@ -37,6 +47,14 @@ impl Vram {
         let index = (address & 0x07ff) as usize;
         self.data[index] = value;
     }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+    }
+
+    pub(crate) fn load_state(&mut self, chunk: &mut ChunkReader) -> Result<(), &'static str> {
+        chunk.read_bytes(&mut self.data)
+    }
 }
 
 pub(crate) struct Palette {
@@ -65,6 +83,14 @@ impl Palette {
     pub(crate) fn write(&mut self, address: u16, value: u8) {
         self.data[Self::normalize(address)] = value;
     }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+    }
+
+    pub(crate) fn load_state(&mut self, chunk: &mut ChunkReader) -> Result<(), &'static str> {
+        chunk.read_bytes(&mut self.data)
+    }
 }
 
 pub(crate) struct Oam {
@@ -78,6 +104,14 @@ impl Oam {
         }
     }
 
+    pub(crate) fn read(&self, address: u8) -> u8 {
+        self.data[address as usize]
+    }
+
+    pub(crate) fn write(&mut self, address: u8, value: u8) {
+        self.data[address as usize] = value;
+    }
+
     pub(crate) fn dma_write(&mut self, start_address: u8, source: &[u8; 0x100]) {
         let start = start_address as usize;
         let split = 256 - start;
@@ -87,4 +121,12 @@ impl Oam {
         // Wrap around to beginning and finish
         self.data[..start].copy_from_slice(&source[split..]);
     }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+    }
+
+    pub(crate) fn load_state(&mut self, chunk: &mut ChunkReader) -> Result<(), &'static str> {
+        chunk.read_bytes(&mut self.data)
+    }
 }
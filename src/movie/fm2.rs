@@ -0,0 +1,222 @@
+// FCEUX FM2 text movie format
+//
+// Header lines are "key value", input lines are "|commands|RLDUTSBA|RLDUTSBA||" with one
+// character per button, where '.' or ' ' means released.
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Write;
+use std::hash::{BuildHasher, Hasher};
+
+use super::{Movie, MovieFrame};
+
+// FM2 button columns from left to right, as controller::Button bits
+const BUTTON_ORDER: [u8; 8] = [0x80, 0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x01];
+const BUTTON_CHARACTERS: &[u8; 8] = b"RLDUTSBA";
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(super) fn import(text: &str) -> Result<Movie, &'static str> {
+    let mut movie = Movie::new(None);
+    movie.guid.clear();
+    let mut ports = [true, true];
+
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+
+        if let Some(input) = line.strip_prefix('|') {
+            movie.frames.push(parse_input(input, ports)?);
+            continue;
+        }
+
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "version" if value != "3" => return Err("Unsupported FM2 version"),
+            "palFlag" if value != "0" => return Err("PAL FM2 movies are not supported"),
+            "fourscore" if value != "0" => return Err("Four Score FM2 movies are not supported"),
+            "FDS" if value != "0" => return Err("FDS FM2 movies are not supported"),
+            "binary" if value != "0" => return Err("Binary FM2 input logs are not supported"),
+            "port0" => ports[0] = parse_port(value)?,
+            "port1" => ports[1] = parse_port(value)?,
+            "port2" if value != "0" => return Err("Expansion port FM2 input is not supported"),
+            "rerecordCount" => {
+                movie.rerecord_count = value.parse().map_err(|_| "Invalid FM2 rerecord count")?
+            }
+            "romFilename" => movie.rom_filename = value.to_owned(),
+            "romChecksum" => movie.rom_checksum = value.to_owned(),
+            "guid" => movie.guid = value.to_owned(),
+            "comment" => movie.comments.push(value.to_owned()),
+            "savestate" => {
+                // Only our own states can be restored, FCEUX's savestate blobs are not compatible
+                let state = value
+                    .strip_prefix("base64:")
+                    .and_then(decode_base64)
+                    .filter(|state| state.starts_with(b"RNST"))
+                    .ok_or("FM2 movies starting from an FCEUX save state are not supported")?;
+                movie.start_state = Some(state);
+            }
+            _ => (),
+        }
+    }
+
+    Ok(movie)
+}
+
+// Whether a gamepad is plugged in, other devices change the input line layout
+fn parse_port(value: &str) -> Result<bool, &'static str> {
+    match value {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        "2" => Err("Zapper FM2 movies are not supported"),
+        _ => Err("Unsupported FM2 input device"),
+    }
+}
+
+fn parse_input(input: &str, ports: [bool; 2]) -> Result<MovieFrame, &'static str> {
+    let mut fields = input.split('|');
+    let commands = fields
+        .next()
+        .and_then(|commands| commands.trim().parse().ok())
+        .ok_or("Invalid FM2 input line")?;
+    let mut frame = MovieFrame {
+        buttons: [0; 2],
+        commands,
+    };
+
+    // Disabled ports still have their (empty) field
+    for (port, enabled) in ports.iter().enumerate() {
+        let field = fields.next().ok_or("Invalid FM2 input line")?;
+        if !enabled {
+            continue;
+        }
+        for (bit, character) in BUTTON_ORDER.iter().zip(field.bytes()) {
+            if character != b'.' && character != b' ' {
+                frame.buttons[port] |= bit;
+            }
+        }
+    }
+
+    Ok(frame)
+}
+
+pub(super) fn export(movie: &Movie) -> String {
+    let mut text = String::new();
+
+    // Writing to a String cannot fail
+    let _ = writeln!(text, "version 3");
+    let _ = writeln!(text, "emuVersion 22020");
+    let _ = writeln!(text, "rerecordCount {}", movie.rerecord_count);
+    let _ = writeln!(text, "palFlag 0");
+    let _ = writeln!(text, "romFilename {}", movie.rom_filename);
+    if !movie.rom_checksum.is_empty() {
+        let _ = writeln!(text, "romChecksum {}", movie.rom_checksum);
+    }
+    let _ = writeln!(text, "guid {}", movie.guid);
+    let _ = writeln!(text, "fourscore 0");
+    let _ = writeln!(text, "microphone 0");
+    let _ = writeln!(text, "port0 1");
+    let _ = writeln!(text, "port1 1");
+    let _ = writeln!(text, "port2 0");
+    let _ = writeln!(text, "FDS 0");
+    let _ = writeln!(text, "NewPPU 0");
+    for comment in &movie.comments {
+        let _ = writeln!(text, "comment {}", comment);
+    }
+    // Our own state format, FCEUX can't load movies that start from a save state
+    if let Some(state) = &movie.start_state {
+        let _ = writeln!(text, "savestate base64:{}", encode_base64(state));
+    }
+
+    for frame in &movie.frames {
+        let _ = write!(text, "|{}|", frame.commands);
+        for buttons in frame.buttons {
+            for (bit, character) in BUTTON_ORDER.iter().zip(BUTTON_CHARACTERS) {
+                text.push(if buttons & bit != 0 {
+                    *character as char
+                } else {
+                    '.'
+                });
+            }
+            text.push('|');
+        }
+        text.push_str("|\n");
+    }
+
+    text
+}
+
+// Random version 4 style GUID, FCEUX only needs it to be unique
+pub(super) fn generate_guid() -> String {
+    let high = RandomState::new().build_hasher().finish();
+    let low = RandomState::new().build_hasher().finish();
+    format!(
+        "{:08X}-{:04X}-{:04X}-{:04X}-{:012X}",
+        high >> 32,
+        (high >> 16) & 0xFFFF,
+        (high & 0x0FFF) | 0x4000,
+        ((low >> 48) & 0x3FFF) | 0x8000,
+        low & 0xFFFF_FFFF_FFFF
+    )
+}
+
+// As FCEUX writes the romChecksum header
+pub(super) fn rom_checksum(md5: &[u8; 16]) -> String {
+    format!("base64:{}", encode_base64(md5))
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for group in data.chunks(3) {
+        let bytes = [
+            group[0],
+            *group.get(1).unwrap_or(&0),
+            *group.get(2).unwrap_or(&0),
+        ];
+        let value = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for index in 0..4 {
+            if index <= group.len() {
+                let sextet = (value >> (18 - index * 6)) & 0x3F;
+                text.push(BASE64_ALPHABET[sextet as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let mut value = 0u32;
+    let mut bits = 0;
+
+    for character in text.trim().bytes().filter(|&c| c != b'=') {
+        let sextet = BASE64_ALPHABET.iter().position(|&c| c == character)? as u32;
+        value = (value << 6) | sextet;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((value >> bits) as u8);
+        }
+    }
+
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_port_keeps_its_field() {
+        let frame = parse_input("0||RLDUTSBA||", [false, true]).unwrap();
+        assert_eq!(frame.buttons, [0x00, 0xFF]);
+
+        let frame = parse_input("1|..D...B.|||", [true, false]).unwrap();
+        assert_eq!(frame.buttons, [0x22, 0x00]);
+        assert_eq!(frame.commands, MovieFrame::RESET);
+    }
+}
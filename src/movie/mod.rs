@@ -0,0 +1,212 @@
+mod fm2;
+
+#[derive(Copy, Clone, PartialEq, Default)]
+pub struct MovieFrame {
+    // Button bits for controller ports 1 and 2, see controller::Button
+    pub buttons: [u8; 2],
+    // Commands applied before the frame runs, FM2 command bits
+    pub commands: u8,
+}
+
+impl MovieFrame {
+    pub const RESET: u8 = 0x01;
+    pub const POWER: u8 = 0x02;
+}
+
+pub struct Movie {
+    frames: Vec<MovieFrame>,
+    // Save state the movie starts from, None starts from power on
+    start_state: Option<Vec<u8>>,
+    rerecord_count: u32,
+    // FM2 header fields, kept so imported movies export unchanged
+    rom_filename: String,
+    rom_checksum: String,
+    guid: String,
+    comments: Vec<String>,
+}
+
+impl Movie {
+    pub(crate) fn new(start_state: Option<Vec<u8>>) -> Self {
+        Self {
+            frames: Vec::new(),
+            start_state,
+            rerecord_count: 0,
+            rom_filename: String::new(),
+            rom_checksum: String::new(),
+            guid: fm2::generate_guid(),
+            comments: Vec::new(),
+        }
+    }
+
+    pub fn from_fm2(text: &str) -> Result<Self, &'static str> {
+        fm2::import(text)
+    }
+
+    // Movies from power on play in FCEUX, ones starting from a save state only play here
+    pub fn to_fm2(&self) -> String {
+        fm2::export(self)
+    }
+
+    pub fn frames(&self) -> &[MovieFrame] {
+        &self.frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn starts_from_save_state(&self) -> bool {
+        self.start_state.is_some()
+    }
+
+    pub(crate) fn start_state(&self) -> Option<&[u8]> {
+        self.start_state.as_deref()
+    }
+
+    pub fn rerecord_count(&self) -> u32 {
+        self.rerecord_count
+    }
+
+    pub fn rom_filename(&self) -> &str {
+        &self.rom_filename
+    }
+
+    pub fn set_rom_filename(&mut self, rom_filename: &str) {
+        self.rom_filename = rom_filename.to_owned();
+    }
+
+    pub(crate) fn set_rom_md5(&mut self, md5: &[u8; 16]) {
+        self.rom_checksum = fm2::rom_checksum(md5);
+    }
+
+    // Movies that don't say which ROM they were recorded with match any
+    pub(crate) fn matches_rom(&self, md5: &[u8; 16]) -> bool {
+        self.rom_checksum.is_empty() || self.rom_checksum == fm2::rom_checksum(md5)
+    }
+
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    pub fn add_comment(&mut self, comment: &str) {
+        self.comments.push(comment.to_owned());
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+    // Playback ran out of frames, input is back to the user
+    Finished,
+}
+
+pub(crate) struct MovieSession {
+    movie: Movie,
+    mode: MovieMode,
+    // Next frame to record or play
+    frame_index: usize,
+    pending_commands: u8,
+}
+
+impl MovieSession {
+    pub(crate) fn new(movie: Movie, mode: MovieMode) -> Self {
+        Self {
+            movie,
+            mode,
+            frame_index: 0,
+            pending_commands: 0,
+        }
+    }
+
+    pub(crate) fn mode(&self) -> MovieMode {
+        self.mode
+    }
+
+    pub(crate) fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    pub(crate) fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub(crate) fn into_movie(self) -> Movie {
+        self.movie
+    }
+
+    // Reset and power presses are stored with the next recorded frame
+    pub(crate) fn record_command(&mut self, command: u8) {
+        if self.mode == MovieMode::Recording {
+            self.pending_commands |= command;
+        }
+    }
+
+    // Input for the frame about to run, while playing
+    pub(crate) fn current_frame(&mut self) -> Option<MovieFrame> {
+        if self.mode != MovieMode::Playing {
+            return None;
+        }
+
+        let frame = self.movie.frames.get(self.frame_index).copied();
+        if frame.is_none() {
+            self.mode = MovieMode::Finished;
+        }
+        frame
+    }
+
+    // Called at the end of every emulated frame. Records the frame that just ran, or returns
+    // the input for the next one during playback.
+    pub(crate) fn complete_frame(&mut self, buttons: [u8; 2]) -> Option<MovieFrame> {
+        match self.mode {
+            MovieMode::Recording => {
+                let frame = MovieFrame {
+                    buttons,
+                    commands: self.pending_commands,
+                };
+                self.pending_commands = 0;
+
+                self.movie.frames.truncate(self.frame_index);
+                self.movie.frames.push(frame);
+                self.frame_index += 1;
+                None
+            }
+            MovieMode::Playing => {
+                self.frame_index += 1;
+                self.current_frame()
+            }
+            MovieMode::Finished => None,
+        }
+    }
+
+    // Switch from playback to recording at the current frame, dropping the rest of the movie
+    pub(crate) fn branch(&mut self) {
+        self.movie.frames.truncate(self.frame_index);
+        self.movie.rerecord_count += 1;
+        self.pending_commands = 0;
+        self.mode = MovieMode::Recording;
+    }
+
+    // Loading a state jumps to the frame it was saved at. While recording that is a rerecord.
+    pub(crate) fn seek(&mut self, frame_index: usize) {
+        match self.mode {
+            MovieMode::Recording => {
+                self.movie.frames.truncate(frame_index);
+                self.movie.rerecord_count += 1;
+                self.pending_commands = 0;
+            }
+            MovieMode::Playing | MovieMode::Finished => {
+                self.mode = if frame_index < self.movie.frames.len() {
+                    MovieMode::Playing
+                } else {
+                    MovieMode::Finished
+                };
+            }
+        }
+        self.frame_index = frame_index.min(self.movie.frames.len());
+    }
+}
@@ -1,9 +1,30 @@
+use std::path::Path;
+use std::time::Duration;
+
 use crate::bus::Bus;
-use crate::cpu::Cpu;
+use crate::cheats::{Cheat, CheatEngine};
+use crate::code_data_log::CodeDataLog;
+use crate::cpu::{CallKind, ControlFlow, Cpu, CpuRegisters};
+use crate::debugger::{
+    BreakHit, Breakpoint, BreakpointKind, CallFrame, CallStack, Debugger, EventKind, FrameEvent,
+};
+use crate::disassembler::{SymbolAddress, SymbolTable};
+use crate::filter::RgbaImage;
+use crate::movie::{Movie, MovieFrame, MovieMode, MovieSession};
+use crate::palette::ColorPalette;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::profiler::{ProfileEntry, Profiler};
+use crate::recorder::{GifCapture, Recorder, VideoFormat};
+use crate::region::Region;
+use crate::rewind::RewindBuffer;
+use crate::run_ahead::{RunAhead, RunAheadMode};
+use crate::screenshot::Screenshot;
+use crate::state::{StateReader, StateWriter};
 
-// const MASTER_CLOCK: u32 = 21_477_272; // NTSC
-const TICKS_PER_CPU_TICK: u8 = 3;
-const TICKS_PER_PPU_TICK: u8 = 1;
+// Version 2 counts the CPU clock phase in master clocks and stores the region
+const STATE_VERSION: u16 = 2;
+// The movie position, versioned on its own as it changes apart from the console's chunk
+const MOVIE_STATE_VERSION: u16 = 1;
 
 #[derive(PartialEq)]
 enum PowerState {
@@ -11,6 +32,16 @@ enum PowerState {
     On,
 }
 
+// Where a reverse debugging operation lands
+enum ReverseTarget {
+    // The history entry itself
+    Entry,
+    // The CPU about to tick at this cycle
+    Cycle(u64),
+    // The breakpoint hit at this position
+    Break(u64, bool),
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum RunMode {
     Running,
@@ -20,15 +51,68 @@ pub enum RunMode {
     StepCycle,
 }
 
+// Emulation speed relative to the region's frame rate. The core runs every frame at any
+// speed, frontends pace frames by it and may skip drawing some.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Speed {
+    Percent(u32),
+    Uncapped,
+}
+
+impl Speed {
+    pub const NORMAL: Speed = Speed::Percent(100);
+
+    pub fn name(self) -> String {
+        match self {
+            Speed::Percent(percent) => format!("{}%", percent),
+            Speed::Uncapped => "Uncapped".to_owned(),
+        }
+    }
+
+    // Host time per frame, None when frames should run as fast as they can
+    pub fn frame_duration(self, region: Region) -> Option<Duration> {
+        match self {
+            Speed::Percent(percent) => Some(Duration::from_secs_f64(
+                100.0 / (region.frame_rate() * percent.max(1) as f64),
+            )),
+            Speed::Uncapped => None,
+        }
+    }
+}
+
 pub struct Nes {
     bus: Box<Bus>,
     cpu: Cpu,
+    // Master clocks since the last CPU cycle, each tick is one PPU dot
     cpu_tick_counter: u8,
-    ppu_tick_counter: u8,
+    // None follows the cartridge header
+    region_setting: Option<Region>,
+    palette: ColorPalette,
     run_mode: RunMode,
+    speed: Speed,
     power_state: PowerState,
+    debugger: Debugger,
+    call_stack: CallStack,
+    cheats: CheatEngine,
+    profiler: Profiler,
+    rewind: Option<RewindBuffer>,
+    movie: Option<MovieSession>,
+    recorder: Option<Recorder>,
+    gif_capture: Option<GifCapture>,
+    run_ahead: RunAhead,
+    // The inserted ROM, to start a second instance for run-ahead from
+    rom: Vec<u8>,
+    // Reused by load_state for its way back
+    state_backup: Vec<u8>,
+    // Reused for the states going into and out of the rewind buffer
+    rewind_state: Vec<u8>,
 }
 
+// Safety: The CPU's bus pointer targets the Box kept here, whose heap allocation stays put when
+// the Nes moves. The CPU can't be taken out on its own, so the pair only ever crosses threads
+// together.
+unsafe impl Send for Nes {}
+
 impl Nes {
     pub fn new() -> Self {
         let mut bus = Box::new(Bus::new());
@@ -39,21 +123,117 @@ impl Nes {
             bus: bus,
             cpu: cpu,
             cpu_tick_counter: 0,
-            ppu_tick_counter: 0,
+            region_setting: None,
+            palette: ColorPalette::ntsc(),
             run_mode: RunMode::Paused,
+            speed: Speed::NORMAL,
             power_state: PowerState::Off,
+            debugger: Debugger::new(),
+            call_stack: CallStack::new(),
+            cheats: CheatEngine::new(),
+            profiler: Profiler::new(),
+            rewind: None,
+            movie: None,
+            recorder: None,
+            gif_capture: None,
+            run_ahead: RunAhead::default(),
+            rom: Vec::new(),
+            state_backup: Vec::new(),
+            rewind_state: Vec::new(),
         }
     }
 
     pub fn insert_cartridge(&mut self, data: &[u8]) -> Result<(), &'static str> {
         self.bus.load_cartridge(data)?;
+        self.apply_region();
+        self.rom = data.to_vec();
+        self.run_ahead.instance = None;
+
+        // States from before the swap no longer fit the machine
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
 
         Ok(())
     }
 
+    // Fetch save_ram() first, anything not yet written out is lost with the cartridge
+    pub fn eject_cartridge(&mut self) {
+        self.power_off();
+        self.bus.unload_cartridge();
+        self.rom.clear();
+        self.run_ahead.instance = None;
+        // Codes are made for one game, the next cartridge brings its own
+        self.cheats.clear(&mut self.bus);
+
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+    }
+
+    // Region
+
+    // None picks the region from the ROM header, defaulting to NTSC
+    pub fn set_region(&mut self, region: Option<Region>) {
+        self.region_setting = region;
+        self.apply_region();
+    }
+
+    pub fn region_setting(&self) -> Option<Region> {
+        self.region_setting
+    }
+
+    // The timing in effect
+    pub fn region(&self) -> Region {
+        self.bus.ppu().region()
+    }
+
+    fn apply_region(&mut self) {
+        let region = self
+            .region_setting
+            .or_else(|| self.bus.cartridge_region())
+            .unwrap_or(Region::Ntsc);
+        if region != self.region() {
+            self.bus.set_region(region);
+            self.cpu_tick_counter = 0;
+        }
+    }
+
+    pub fn has_cartridge(&self) -> bool {
+        self.bus.has_cartridge()
+    }
+
+    // CRC32 of PRG and CHR ROM, without the header
+    pub fn rom_crc32(&self) -> Option<u32> {
+        self.bus.cartridge_crc32()
+    }
+
+    // Battery-backed save RAM, the contents of a .sav file
+
+    pub fn has_save_ram(&self) -> bool {
+        self.bus.save_ram().is_some()
+    }
+
+    pub fn save_ram(&self) -> Option<&[u8]> {
+        self.bus.save_ram()
+    }
+
+    // Must match the size reported by save_ram()
+    pub fn load_save_ram(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        self.bus.load_save_ram(data)
+    }
+
     pub fn power_on(&mut self) {
+        self.debugger.reset_hit_counts();
+        self.cpu_tick_counter = 0;
+        self.bus.power_on();
         self.cpu.power_on();
+        self.call_stack.clear();
         self.power_state = PowerState::On;
+
+        if let Some(movie) = self.movie.as_mut() {
+            movie.record_command(MovieFrame::POWER);
+        }
     }
 
     pub fn power_off(&mut self) {
@@ -66,6 +246,11 @@ impl Nes {
 
     pub fn reset(&mut self) {
         self.cpu.reset();
+        self.call_stack.clear();
+
+        if let Some(movie) = self.movie.as_mut() {
+            movie.record_command(MovieFrame::RESET);
+        }
     }
 
     pub fn set_run_mode(&mut self, run_mode: RunMode) {
@@ -76,21 +261,1014 @@ impl Nes {
         self.run_mode
     }
 
-    fn tick(&mut self) {
-        self.ppu_tick_counter += 1;
-        if self.ppu_tick_counter >= TICKS_PER_PPU_TICK {
-            self.ppu_tick_counter = 0;
-            self.bus.ppu_stub_tick();
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    // Runs one frame and pauses, works from any run mode
+    pub fn advance_frame(&mut self) {
+        self.run_frame();
+        self.run_mode = RunMode::Paused;
+    }
+
+    // Controllers
+
+    // Ignored while a movie is playing, the movie drives the controllers then
+    pub fn set_buttons(&mut self, port: usize, buttons: u8) {
+        if self.movie_mode() != Some(MovieMode::Playing) {
+            self.bus.set_controller_buttons(port, buttons);
         }
+    }
 
-        self.cpu_tick_counter += 1;
-        if self.cpu_tick_counter >= TICKS_PER_CPU_TICK {
-            self.cpu_tick_counter = 0;
+    pub fn buttons(&self, port: usize) -> u8 {
+        self.bus.controller_buttons(port)
+    }
+
+    pub fn cpu_registers(&self) -> &CpuRegisters {
+        self.cpu.registers()
+    }
+
+    // For debugger edits while paused between instructions
+    pub fn cpu_registers_mut(&mut self) -> &mut CpuRegisters {
+        self.cpu.registers_mut()
+    }
+
+    // Memory access for debugging tools, free of register side effects
+
+    // CPU address space, registers read back what a read would return without changing them
+    pub fn peek(&self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
+
+    // RAM, PRG-RAM and PRG-ROM through the current bank mapping, register writes are ignored
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.bus.poke(address, value);
+    }
+
+    // PRG-ROM offset the CPU address maps to with the current banking, for symbols and logs
+    pub fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.bus.prg_rom_offset(address)
+    }
+
+    // PPU address space: CHR, nametables after mirroring, palette
+    pub fn peek_ppu(&self, address: u16) -> u8 {
+        self.bus.ppu_peek(address)
+    }
+
+    pub fn poke_ppu(&mut self, address: u16, value: u8) {
+        self.bus.ppu_poke(address, value);
+    }
+
+    pub fn peek_oam(&self, address: u8) -> u8 {
+        self.bus.peek_oam(address)
+    }
+
+    pub fn poke_oam(&mut self, address: u8, value: u8) {
+        self.bus.poke_oam(address, value);
+    }
+
+    // Video
+
+    pub fn set_palette(&mut self, palette: ColorPalette) {
+        self.palette = palette;
+    }
+
+    pub fn palette(&self) -> &ColorPalette {
+        &self.palette
+    }
+
+    // Last frame as the PPU output it: palette index in bits 0-5, emphasis in bits 6-8.
+    // With run-ahead this is the frame run ahead to.
+    pub fn frame_buffer(&self) -> &[u16] {
+        match self.run_ahead.frame(self.frame_count()) {
+            Some((frame_buffer, _)) => frame_buffer,
+            None => self.bus.ppu().frame_buffer(),
+        }
+    }
+
+    // Color subcarrier phase of the first pixel of the last frame, in twelfths of a cycle,
+    // for the NTSC filter
+    pub fn frame_phase(&self) -> u8 {
+        match self.run_ahead.frame(self.frame_count()) {
+            Some((_, frame_phase)) => frame_phase,
+            None => self.bus.ppu().frame_phase(),
+        }
+    }
+
+    // Last frame through the palette, SCREEN_WIDTH x SCREEN_HEIGHT RGBA pixels
+    pub fn frame_rgba(&self) -> Vec<u8> {
+        self.palette.to_rgba(self.frame_buffer())
+    }
+
+    // The same, ready for the filter chain
+    pub fn frame_image(&self) -> RgbaImage {
+        RgbaImage::from_rgba(SCREEN_WIDTH, SCREEN_HEIGHT, &self.frame_rgba())
+    }
+
+    // PPU frames since power on
+    pub fn frame_count(&self) -> u64 {
+        self.bus.ppu().frame()
+    }
+
+    // The last frame unfiltered, swap in a filtered image before saving to keep the look
+    pub fn screenshot(&self) -> Screenshot {
+        Screenshot::new(self.frame_image(), self.rom_crc32(), self.frame_count())
+    }
+
+    // Save states
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.save_state_into(&mut buffer);
+        buffer
+    }
+
+    // Replaces the buffer's contents, reusing its capacity so frequent saves don't allocate
+    pub fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        let mut writer = StateWriter::new(buffer);
+
+        writer.chunk(b"NES ", STATE_VERSION, |writer| {
+            writer.write_bool(self.power_state == PowerState::On);
+            writer.write_u8(self.cpu_tick_counter);
+            writer.write_u8(self.region().to_u8());
+        });
+        self.cpu.save_state(&mut writer);
+        self.bus.save_state(&mut writer);
+        if let Some(movie) = self.movie.as_ref() {
+            writer.chunk(b"MOVI", MOVIE_STATE_VERSION, |writer| {
+                writer.write_u32(movie.frame_index() as u32);
+            });
+        }
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        let state = StateReader::new(data)?;
+        self.bus.check_state(&state)?;
+
+        // A chunk can still turn out truncated halfway through, so keep a way back
+        let mut backup = std::mem::take(&mut self.state_backup);
+        self.save_state_into(&mut backup);
+        let result = self.apply_state(&state);
+        let restored = match result {
+            Ok(()) => Ok(()),
+            Err(_) => StateReader::new(&backup).and_then(|backup| self.apply_state(&backup)),
+        };
+        self.state_backup = backup;
+        restored?;
+        result?;
+
+        // Calls made before the state was saved are unknown
+        self.call_stack.clear();
+        self.run_ahead.invalidate();
+
+        if let (Some(movie), Some(mut chunk)) = (self.movie.as_mut(), state.chunk(b"MOVI")) {
+            chunk.expect_version(MOVIE_STATE_VERSION)?;
+            movie.seek(chunk.read_u32()? as usize);
+        }
+
+        Ok(())
+    }
+
+    fn apply_state(&mut self, state: &StateReader) -> Result<(), &'static str> {
+        if let Some(mut chunk) = state.chunk(b"NES ") {
+            chunk.expect_version(STATE_VERSION)?;
+            self.power_state = if chunk.read_bool()? {
+                PowerState::On
+            } else {
+                PowerState::Off
+            };
+            self.cpu_tick_counter = chunk.read_u8()?;
+            let region = chunk.read_u8()?;
+            if chunk.version() < 2 {
+                // NTSC only, counted in PPU dots
+                self.cpu_tick_counter *= Region::Ntsc.ppu_divider();
+                self.bus.set_region(Region::Ntsc);
+            } else {
+                let region = Region::from_u8(region).ok_or("Unknown region in save state")?;
+                self.bus.set_region(region);
+            }
+        }
+        self.cpu.load_state(state)?;
+        self.bus.load_state(state)
+    }
+
+    // Rewind
+
+    pub fn enable_rewind(&mut self, memory_budget: usize) {
+        self.rewind = Some(RewindBuffer::new(memory_budget));
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    pub fn is_rewind_enabled(&self) -> bool {
+        self.rewind.is_some()
+    }
+
+    pub fn rewind_frames_available(&self) -> usize {
+        self.rewind
+            .as_ref()
+            .map_or(0, |rewind| rewind.frames_available())
+    }
+
+    pub fn rewind_memory_used(&self) -> usize {
+        self.rewind
+            .as_ref()
+            .map_or(0, |rewind| rewind.memory_used())
+    }
+
+    // Steps back up to `frames` frames, returns how many were actually rewound
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let Some(rewind) = self.rewind.as_mut() else {
+            return 0;
+        };
+
+        let mut state = std::mem::take(&mut self.rewind_state);
+        let rewound = rewind.rewind(frames, &mut state);
+        let loaded = rewound > 0 && self.load_state(&state).is_ok();
+        self.rewind_state = state;
+        if loaded { rewound } else { 0 }
+    }
+
+    fn capture_rewind_state(&mut self) {
+        if self.rewind.is_none() {
+            return;
+        }
+
+        let mut state = std::mem::take(&mut self.rewind_state);
+        self.save_state_into(&mut state);
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.push(&state, self.cpu.total_cycles());
+        }
+        self.rewind_state = state;
+    }
+
+    // Reverse debugging
+    //
+    // The rewind states double as keyframes: going back restores the newest state saved
+    // before the target and replays forward from it, with the controller input each frame
+    // had, until the CPU reaches the target cycle. Needs rewind to be enabled.
+
+    // Goes back to the start of the previous instruction
+    pub fn step_back_instruction(&mut self) -> Result<(), &'static str> {
+        let current = self.cpu.total_cycles();
+        self.reverse(|nes, index| {
+            let mut boundary = None;
+            nes.replay(index, current, |nes, _| {
+                let cycles = nes.cpu.total_cycles();
+                if nes.is_cpu_tick_due() && nes.cpu.is_fetch_pending() && cycles < current {
+                    boundary = Some(cycles);
+                }
+            })?;
+            Ok(boundary.map(ReverseTarget::Cycle))
+        })
+    }
+
+    // Goes back to the start of the current frame, or of the previous one when already there
+    pub fn step_back_frame(&mut self) -> Result<(), &'static str> {
+        self.reverse(|_, _| Ok(Some(ReverseTarget::Entry)))
+    }
+
+    // Goes back to the last breakpoint hit before the current position, which take_break_hit
+    // then reports as if execution had just stopped there
+    pub fn run_back_to_breakpoint(&mut self) -> Result<(), &'static str> {
+        let current = self.position();
+        self.reverse(|nes, index| {
+            let mut last_hit = None;
+            nes.replay(index, current.0, |nes, hit| {
+                if hit && nes.position() < current {
+                    last_hit = Some(nes.position());
+                }
+            })?;
+            Ok(last_hit.map(|(cycles, due)| ReverseTarget::Break(cycles, due)))
+        })
+    }
+
+    fn is_cpu_tick_due(&self) -> bool {
+        let region = self.region();
+        self.cpu_tick_counter + region.ppu_divider() >= region.cpu_divider()
+    }
+
+    // Orders points within a CPU cycle: right after the CPU ticked, where watchpoints stop, comes
+    // before the next CPU tick is due, where execute breakpoints stop
+    fn position(&self) -> (u64, bool) {
+        (self.cpu.total_cycles(), self.is_cpu_tick_due())
+    }
+
+    // Tries history entries from the newest before the current cycle backwards until `search`
+    // finds a target in one, then goes there
+    fn reverse(
+        &mut self,
+        mut search: impl FnMut(&mut Self, usize) -> Result<Option<ReverseTarget>, &'static str>,
+    ) -> Result<(), &'static str> {
+        let Some(rewind) = self.rewind.as_ref() else {
+            return Err("Rewind must be enabled to step backwards");
+        };
+        let current = self.cpu.total_cycles();
+        let mut index = rewind.find_before(current);
+
+        let hit_counts = self.debugger.hit_counts();
+        let profiling = self.profiler.is_enabled();
+        self.profiler.stop(current);
+        let restore = self.save_state();
+
+        let mut result = Err("No earlier state to step back to");
+        while let Some(entry) = index {
+            match search(self, entry) {
+                Ok(Some(target)) => {
+                    result = self.seek_target(entry, target, &hit_counts);
+                    break;
+                }
+                Ok(None) => index = entry.checked_sub(1),
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+
+        if result.is_err() {
+            self.load_state(&restore)?;
+            self.debugger.take_break_hit();
+            self.debugger.restore_hit_counts(&hit_counts);
+        }
+        if profiling {
+            self.profiler.start(self.cpu.total_cycles());
+        }
+        self.run_mode = RunMode::Paused;
+        result
+    }
+
+    fn seek_target(
+        &mut self,
+        index: usize,
+        target: ReverseTarget,
+        hit_counts: &[u32],
+    ) -> Result<(), &'static str> {
+        match target {
+            ReverseTarget::Entry => self.restore_entry(index)?,
+            ReverseTarget::Cycle(cycles) => self.replay(index, cycles, |_, _| {})?,
+            // A watchpoint hit is made by the CPU tick of the cycle before
+            ReverseTarget::Break(cycles, due) => {
+                self.replay(index, cycles - u64::from(!due), |_, _| {})?
+            }
+        }
+        self.debugger.take_break_hit();
+        self.debugger.restore_hit_counts(hit_counts);
+
+        // Break again for real, so the hit is reported and resuming steps over it
+        if let ReverseTarget::Break(cycles, due) = target {
+            while self.position() <= (cycles, due) {
+                if self.tick() && self.position() == (cycles, due) {
+                    return Ok(());
+                }
+            }
+            return Err("Breakpoint did not hit again when replayed");
+        }
+        Ok(())
+    }
+
+    // Restores history entry `index` and runs until the CPU tick at `end_cycles` is due.
+    // `visit` is called after every tick on the way, with whether it hit a breakpoint.
+    fn replay(
+        &mut self,
+        index: usize,
+        end_cycles: u64,
+        mut visit: impl FnMut(&mut Self, bool),
+    ) -> Result<(), &'static str> {
+        self.restore_entry(index)?;
+
+        let mut next = index + 1;
+        while !(self.is_cpu_tick_due() && self.cpu.total_cycles() >= end_cycles) {
+            let frame = self.bus.ppu().frame();
+            let hit = self.tick();
+            visit(self, hit);
+
+            if self.bus.ppu().frame() != frame {
+                self.end_frame();
+                self.cheats.apply_freezes(&mut self.bus);
+                self.apply_replay_input(next);
+                next += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn restore_entry(&mut self, index: usize) -> Result<(), &'static str> {
+        let Some(rewind) = self.rewind.as_ref() else {
+            return Err("Rewind must be enabled to step backwards");
+        };
+        let mut state = std::mem::take(&mut self.rewind_state);
+        rewind.decode(index, &mut state);
+        let result = self.load_state(&state);
+        self.rewind_state = state;
+        result?;
+        self.bus.event_log_mut().restart_frame();
+        self.apply_replay_input(index);
+        Ok(())
+    }
+
+    fn apply_replay_input(&mut self, index: usize) {
+        let input = self.rewind.as_ref().and_then(|rewind| rewind.input(index));
+        if let Some(buttons) = input
+            && self.movie_mode() != Some(MovieMode::Playing)
+        {
+            self.bus.set_controller_buttons(0, buttons[0]);
+            self.bus.set_controller_buttons(1, buttons[1]);
+        }
+    }
+
+    // Recording
+
+    // Every frame from here on goes to the video file, and the audio to a WAV when given
+    pub fn start_recording(
+        &mut self,
+        video_path: &Path,
+        format: VideoFormat,
+        audio_path: Option<&Path>,
+    ) -> Result<(), &'static str> {
+        self.stop_recording()?;
+        self.recorder = Some(Recorder::create(
+            video_path,
+            format,
+            audio_path,
+            self.region(),
+        )?);
+        Ok(())
+    }
+
+    // Returns the number of frames written, or the first error the recording ran into
+    pub fn stop_recording(&mut self) -> Result<u64, &'static str> {
+        self.recorder.take().map_or(Ok(0), Recorder::finish)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    // None records a clip until stopped, a number of seconds keeps only the latest ones to
+    // save after something interesting happened
+    pub fn start_gif_capture(&mut self, max_seconds: Option<u32>) {
+        self.gif_capture = Some(GifCapture::new(
+            self.region().frame_rate_ratio(),
+            max_seconds,
+        ));
+    }
+
+    pub fn stop_gif_capture(&mut self) {
+        self.gif_capture = None;
+    }
+
+    pub fn is_capturing_gif(&self) -> bool {
+        self.gif_capture.is_some()
+    }
+
+    // The frames captured so far as a GIF file, the capture goes on
+    pub fn gif(&self) -> Result<Vec<u8>, &'static str> {
+        match self.gif_capture.as_ref() {
+            Some(capture) => capture.encode(&self.palette),
+            None => Err("No GIF capture running"),
+        }
+    }
+
+    // Frames replayed while stepping backwards were already recorded the first time
+    fn record_frame(&mut self) {
+        if self.recorder.is_some() {
+            // The emulated frame, never one run ahead to
+            let rgba = self.palette.to_rgba(self.bus.ppu().frame_buffer());
+            let image = RgbaImage::from_rgba(SCREEN_WIDTH, SCREEN_HEIGHT, &rgba);
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.record_frame(&image);
+            }
+        }
+        if let Some(capture) = self.gif_capture.as_mut() {
+            capture.capture_frame(self.bus.ppu().frame_buffer());
+        }
+    }
+
+    // Run-ahead
+
+    // Frames to run ahead after each frame, 0 turns run-ahead off. One or two usually match a
+    // game's internal lag, more than that makes it react before the input.
+    pub fn set_run_ahead(&mut self, frames: u32, mode: RunAheadMode) {
+        self.run_ahead.frames = frames;
+        self.run_ahead.mode = mode;
+        self.run_ahead.invalidate();
+        if frames == 0 || mode != RunAheadMode::SecondInstance {
+            self.run_ahead.instance = None;
+        }
+    }
+
+    pub fn run_ahead_frames(&self) -> u32 {
+        self.run_ahead.frames
+    }
+
+    pub fn run_ahead_mode(&self) -> RunAheadMode {
+        self.run_ahead.mode
+    }
+
+    fn run_ahead(&mut self) {
+        if self.run_ahead.frames == 0 {
+            return;
+        }
+        let mut run_ahead = std::mem::take(&mut self.run_ahead);
+        self.save_state_into(&mut run_ahead.state);
+        let frame = self.frame_count();
+
+        match run_ahead.mode {
+            RunAheadMode::SingleInstance => {
+                let logging = self.bus.is_code_data_logging();
+                self.bus.set_code_data_logging(false);
+                for _ in 0..run_ahead.frames {
+                    self.run_frame_unobserved();
+                }
+                let ppu = self.bus.ppu();
+                run_ahead.present(ppu.frame_buffer(), ppu.frame_phase(), frame);
+
+                // Just written, so it can't fail to load
+                if let Ok(state) = StateReader::new(&run_ahead.state) {
+                    let _ = self.apply_state(&state);
+                }
+                self.bus.event_log_mut().restart_frame();
+                self.bus.set_code_data_logging(logging);
+            }
+            RunAheadMode::SecondInstance => {
+                if run_ahead.instance.is_none() {
+                    let mut instance = Box::new(Nes::new());
+                    if instance.insert_cartridge(&self.rom).is_ok() {
+                        run_ahead.instance = Some(instance);
+                    }
+                }
+                if let Some(mut instance) = run_ahead.instance.take() {
+                    self.sync_instance(&mut instance, &run_ahead.state);
+                    for _ in 0..run_ahead.frames {
+                        instance.run_frame_unobserved();
+                    }
+                    let ppu = instance.bus.ppu();
+                    run_ahead.present(ppu.frame_buffer(), ppu.frame_phase(), frame);
+                    run_ahead.instance = Some(instance);
+                }
+            }
+        }
+
+        self.run_ahead = run_ahead;
+    }
+
+    // Brings the second instance to this one's state, cheats included
+    fn sync_instance(&self, instance: &mut Nes, state: &[u8]) {
+        let cheats = self.cheats.cheats();
+        let other = instance.cheats.cheats();
+        let same_cheats = cheats.len() == other.len()
+            && cheats
+                .iter()
+                .zip(other)
+                .all(|(a, b)| a.code() == b.code() && a.is_enabled() == b.is_enabled());
+        if !same_cheats {
+            let _ = instance.import_cheats(&self.export_cheats());
+        }
+
+        if let Ok(state) = StateReader::new(state) {
+            let _ = instance.apply_state(&state);
+        }
+    }
+
+    // Movies
+
+    // From power on, or from the current machine state as an embedded save state
+    pub fn record_movie(&mut self, from_save_state: bool) {
+        self.movie = None;
+
+        let start_state = if from_save_state {
+            Some(self.save_state())
+        } else {
+            self.power_on();
+            None
+        };
+        let mut movie = Movie::new(start_state);
+        if let Some(md5) = self.bus.cartridge_md5() {
+            movie.set_rom_md5(&md5);
+        }
+        self.movie = Some(MovieSession::new(movie, MovieMode::Recording));
+    }
+
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), &'static str> {
+        if let Some(md5) = self.bus.cartridge_md5()
+            && !movie.matches_rom(&md5)
+        {
+            return Err("Movie was recorded with a different ROM");
+        }
+        self.movie = None;
+
+        match movie.start_state() {
+            Some(state) => self.load_state(state)?,
+            None => self.power_on(),
+        }
+
+        let mut session = MovieSession::new(movie, MovieMode::Playing);
+        if let Some(frame) = session.current_frame() {
+            self.apply_movie_frame(frame);
+        }
+        self.movie = Some(session);
+
+        Ok(())
+    }
+
+    // Take over a playing movie and record from the current frame on
+    pub fn branch_movie(&mut self) {
+        if let Some(movie) = self.movie.as_mut() {
+            movie.branch();
+        }
+    }
+
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(MovieSession::into_movie)
+    }
+
+    pub fn movie(&self) -> Option<&Movie> {
+        self.movie.as_ref().map(MovieSession::movie)
+    }
+
+    pub fn movie_mode(&self) -> Option<MovieMode> {
+        self.movie.as_ref().map(MovieSession::mode)
+    }
+
+    pub fn movie_frame(&self) -> usize {
+        self.movie.as_ref().map_or(0, MovieSession::frame_index)
+    }
+
+    fn apply_movie_frame(&mut self, frame: MovieFrame) {
+        if frame.commands & MovieFrame::POWER != 0 {
+            self.power_on();
+        } else if frame.commands & MovieFrame::RESET != 0 {
+            self.reset();
+        }
+        self.bus.set_controller_buttons(0, frame.buttons[0]);
+        self.bus.set_controller_buttons(1, frame.buttons[1]);
+    }
+
+    fn complete_movie_frame(&mut self) {
+        let buttons = [
+            self.bus.controller_buttons(0),
+            self.bus.controller_buttons(1),
+        ];
+        let next_frame = self
+            .movie
+            .as_mut()
+            .and_then(|movie| movie.complete_frame(buttons));
+
+        if let Some(frame) = next_frame {
+            self.apply_movie_frame(frame);
+        }
+    }
+
+    // Debugger
+
+    pub fn add_breakpoint(
+        &mut self,
+        kind: BreakpointKind,
+        start_address: u16,
+        end_address: u16,
+        condition: Option<&str>,
+    ) -> Result<u32, &'static str> {
+        self.debugger
+            .add_breakpoint(kind, start_address, end_address, condition, &mut self.bus)
+    }
+
+    pub fn remove_breakpoint(&mut self, id: u32) -> bool {
+        self.debugger.remove_breakpoint(id, &mut self.bus)
+    }
+
+    pub fn set_breakpoint_enabled(&mut self, id: u32, enabled: bool) {
+        self.debugger
+            .set_breakpoint_enabled(id, enabled, &mut self.bus);
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        self.debugger.breakpoints()
+    }
+
+    pub fn take_break_hit(&mut self) -> Option<BreakHit> {
+        self.debugger.take_break_hit()
+    }
+
+    // Resuming runs the instruction at PC even when an execute breakpoint sits on it
+    pub(crate) fn resume_from_program_counter(&mut self) {
+        let program_counter = self.cpu.registers().program_counter();
+        self.debugger.resume_at(program_counter);
+    }
+
+    // Shadow call stack, outermost frame first
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.call_stack.frames()
+    }
+
+    // Runs until the frame at index returns, then pauses. False if there is no such frame.
+    pub fn run_to_return(&mut self, index: usize) -> bool {
+        if !self.call_stack.run_to_return(index) {
+            return false;
+        }
+        self.run_mode = RunMode::Running;
+        true
+    }
+
+    // Event viewer
+
+    // Recording starts empty, the first complete frame is available after the next run_frame
+    pub fn set_event_logging(&mut self, enabled: bool) {
+        self.bus.event_log_mut().set_enabled(enabled);
+    }
+
+    pub fn is_event_logging(&self) -> bool {
+        self.bus.event_log().is_enabled()
+    }
+
+    // Events of the last complete frame, in the order they happened
+    pub fn frame_events(&self) -> &[FrameEvent] {
+        self.bus.event_log().completed()
+    }
+
+    // Cheats
+
+    pub fn add_cheat(&mut self, code: &str, name: &str) -> Result<u32, &'static str> {
+        self.cheats.add(code, name, &mut self.bus)
+    }
+
+    pub fn remove_cheat(&mut self, id: u32) -> bool {
+        self.cheats.remove(id, &mut self.bus)
+    }
+
+    pub fn set_cheat_enabled(&mut self, id: u32, enabled: bool) {
+        self.cheats.set_enabled(id, enabled, &mut self.bus);
+    }
+
+    pub fn rename_cheat(&mut self, id: u32, name: &str) {
+        self.cheats.rename(id, name);
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear(&mut self.bus);
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        self.cheats.cheats()
+    }
+
+    // Text form for saving a cheat list per ROM
+    pub fn export_cheats(&self) -> String {
+        self.cheats.export()
+    }
+
+    // Replaces the current cheats, nothing changes if the text has an invalid code
+    pub fn import_cheats(&mut self, text: &str) -> Result<(), &'static str> {
+        self.cheats.import(text, &mut self.bus)
+    }
+
+    // Code/data logging
+
+    // Logging resumes into the existing log, a new one is created for the cartridge if needed
+    pub fn start_code_data_log(&mut self) -> Result<(), &'static str> {
+        self.bus.code_data_log_mut()?;
+        self.bus.set_code_data_logging(true);
+        Ok(())
+    }
+
+    pub fn pause_code_data_log(&mut self) {
+        self.bus.set_code_data_logging(false);
+    }
+
+    pub fn is_code_data_logging(&self) -> bool {
+        self.bus.is_code_data_logging()
+    }
+
+    pub fn reset_code_data_log(&mut self) {
+        if let Ok(log) = self.bus.code_data_log_mut() {
+            log.clear();
+        }
+    }
+
+    pub fn code_data_log(&self) -> Option<&CodeDataLog> {
+        self.bus.code_data_log()
+    }
+
+    // Merges a .cdl file into the log, it must have been made for this cartridge
+    pub fn import_code_data_log(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        self.bus.code_data_log_mut()?.import(data)
+    }
+
+    // Profiler
+
+    pub fn start_profiler(&mut self) {
+        self.profiler.start(self.cpu.total_cycles());
+    }
+
+    pub fn stop_profiler(&mut self) {
+        self.profiler.stop(self.cpu.total_cycles());
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiler.is_enabled()
+    }
+
+    pub fn reset_profiler(&mut self) {
+        self.profiler.reset();
+    }
+
+    // The first entry is always the top level, cycles spent outside any call
+    pub fn profile(&self) -> &[ProfileEntry] {
+        self.profiler.entries()
+    }
+
+    // Sorted by exclusive cycles, routines named from the symbols where they have a label
+    pub fn export_profile_text(&self, symbols: Option<&SymbolTable>) -> String {
+        self.profiler.export_text(symbols)
+    }
+
+    pub fn export_profile_csv(&self, symbols: Option<&SymbolTable>) -> String {
+        self.profiler.export_csv(symbols)
+    }
+
+    // Returns true when a run-to-return target was reached
+    fn record_control_flow(&mut self) -> bool {
+        let Some(flow) = self.cpu.take_control_flow() else {
+            return false;
+        };
+
+        let registers = self.cpu.registers();
+        let stack_pointer = registers.stack_pointer();
+        let total_cycles = self.cpu.total_cycles();
+        match flow {
+            ControlFlow::Call {
+                kind,
+                target,
+                return_address,
+            } => {
+                self.call_stack
+                    .call(kind, target, return_address, stack_pointer);
+                let event = match kind {
+                    CallKind::Irq => Some(EventKind::Irq),
+                    CallKind::Nmi => Some(EventKind::Nmi),
+                    _ => None,
+                };
+                if let Some(event) = event {
+                    let (scanline, dot) = (self.bus.ppu().scanline(), self.bus.ppu().dot());
+                    self.bus.event_log_mut().record_interrupt(
+                        event,
+                        scanline,
+                        dot,
+                        target,
+                        return_address,
+                    );
+                }
+                if self.profiler.is_enabled() {
+                    let location = match self.bus.prg_rom_offset(target) {
+                        Some(offset) => SymbolAddress::PrgRom(offset),
+                        None => SymbolAddress::Cpu(target),
+                    };
+                    self.profiler
+                        .record_call(kind, target, location, stack_pointer, total_cycles);
+                }
+            }
+            ControlFlow::Return { from_interrupt } => {
+                self.call_stack
+                    .ret(from_interrupt, registers.program_counter(), stack_pointer);
+                if self.profiler.is_enabled() {
+                    self.profiler.record_return(stack_pointer, total_cycles);
+                }
+            }
+        }
+
+        self.call_stack.take_return_reached()
+    }
+
+    // Returns true when a breakpoint was hit or a run-to-return finished
+    fn tick(&mut self) -> bool {
+        let cpu_tick_due = self.is_cpu_tick_due();
+
+        // Execute breakpoints stop before anything advances, so resuming continues seamlessly
+        if cpu_tick_due
+            && self.cpu.is_fetch_pending()
+            && self.debugger.has_execute_breakpoints()
+            && self
+                .debugger
+                .check_execute(self.cpu.registers(), &self.bus, self.cpu.total_cycles())
+        {
+            return true;
+        }
+
+        if self.tick_hardware() {
+            if self.bus.event_log().is_enabled() {
+                self.bus
+                    .event_log_mut()
+                    .attribute(self.cpu.instruction_address());
+            }
+            let returned = self.record_control_flow();
+
+            // Watchpoints break after the instruction that made the access
+            let watchpoint_hit = self.debugger.check_accesses(
+                self.cpu.registers(),
+                &mut self.bus,
+                self.cpu.total_cycles(),
+            );
+            return returned || watchpoint_hit;
+        }
+
+        false
+    }
+
+    // One PPU dot and the CPU cycle when one is due, returns whether the CPU ticked
+    fn tick_hardware(&mut self) -> bool {
+        self.bus.ppu_tick();
+
+        // PAL's 3.2 dots per CPU cycle leave a remainder, so the phase carries over
+        let region = self.region();
+        self.cpu_tick_counter += region.ppu_divider();
+        if self.cpu_tick_counter >= region.cpu_divider() {
+            self.cpu_tick_counter -= region.cpu_divider();
             self.cpu.tick();
+            self.bus.cartridge_tick();
+            return true;
+        }
+        false
+    }
+
+    // A frame nothing observes: no breakpoints, call stack, profiling or recording. Run-ahead
+    // throws these frames away again.
+    fn run_frame_unobserved(&mut self) {
+        let frame = self.bus.ppu().frame();
+        self.cheats.apply_freezes(&mut self.bus);
+        while self.bus.ppu().frame() == frame {
+            if self.tick_hardware() {
+                self.cpu.take_control_flow();
+                // Accesses from frames that are thrown away must not trip watchpoints later
+                self.bus.access_log_mut().clear();
+            }
         }
     }
 
     pub fn run_frame(&mut self) {
-        self.tick();
+        let frame = self.bus.ppu().frame();
+        self.cheats.apply_freezes(&mut self.bus);
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.record_input([
+                self.bus.controller_buttons(0),
+                self.bus.controller_buttons(1),
+            ]);
+        }
+
+        while self.bus.ppu().frame() == frame {
+            if self.tick() {
+                self.run_mode = RunMode::Paused;
+                return;
+            }
+        }
+
+        self.end_frame();
+        self.record_frame();
+        self.capture_rewind_state();
+        self.run_ahead();
+    }
+
+    // Runs until the CPU is about to fetch its next instruction, or to the end of the frame
+    // when it is halted. Returns true when a breakpoint stopped it first.
+    pub fn step_instruction(&mut self) -> bool {
+        let start = self.cpu.total_cycles();
+        self.run_ahead.invalidate();
+
+        loop {
+            let frame = self.bus.ppu().frame();
+            if self.tick() {
+                self.run_mode = RunMode::Paused;
+                return true;
+            }
+
+            if self.bus.ppu().frame() != frame {
+                self.end_frame();
+                self.record_frame();
+                self.capture_rewind_state();
+                self.cheats.apply_freezes(&mut self.bus);
+                if self.cpu.is_halted() {
+                    return false;
+                }
+            }
+            if self.cpu.total_cycles() > start
+                && self.is_cpu_tick_due()
+                && self.cpu.is_fetch_pending()
+            {
+                return false;
+            }
+        }
+    }
+
+    fn end_frame(&mut self) {
+        if self.profiler.is_enabled() {
+            self.profiler.end_frame(self.cpu.total_cycles());
+        }
+        self.bus.event_log_mut().end_frame();
+        self.complete_movie_frame();
     }
 }
@@ -0,0 +1,103 @@
+// Palette index to RGB conversion
+//
+// The PPU outputs a 6-bit palette index with the three PPUMASK emphasis bits above it, in
+// NTSC order (bit 6 red, bit 7 green, bit 8 blue). A palette holds a color for each of the
+// 512 combinations.
+
+// How much an emphasis bit darkens the two channels it does not emphasize
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+// 2C02 colors without emphasis
+#[rustfmt::skip]
+const NTSC_COLORS: [[u8; 3]; 64] = [
+    [84, 84, 84], [0, 30, 116], [8, 16, 144], [48, 0, 136],
+    [68, 0, 100], [92, 0, 48], [84, 4, 0], [60, 24, 0],
+    [32, 42, 0], [8, 58, 0], [0, 64, 0], [0, 60, 0],
+    [0, 50, 60], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [152, 150, 152], [8, 76, 196], [48, 50, 236], [92, 30, 228],
+    [136, 20, 176], [160, 20, 100], [152, 34, 32], [120, 60, 0],
+    [84, 90, 0], [40, 114, 0], [8, 124, 0], [0, 118, 40],
+    [0, 102, 120], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [76, 154, 236], [120, 124, 236], [176, 98, 236],
+    [228, 84, 236], [236, 88, 180], [236, 106, 100], [212, 136, 32],
+    [160, 170, 0], [116, 196, 0], [76, 208, 32], [56, 204, 108],
+    [56, 180, 204], [60, 60, 60], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236],
+    [236, 174, 236], [236, 174, 212], [236, 180, 176], [228, 196, 144],
+    [204, 210, 120], [180, 222, 120], [168, 226, 144], [152, 226, 180],
+    [160, 214, 228], [160, 162, 160], [0, 0, 0], [0, 0, 0],
+];
+
+#[derive(Clone)]
+pub struct ColorPalette {
+    colors: Box<[[u8; 3]; 512]>,
+}
+
+impl ColorPalette {
+    pub fn ntsc() -> Self {
+        Self::with_emphasis(&NTSC_COLORS)
+    }
+
+    // Accepts the usual .pal layouts: 64 colors, with emphasis derived from them, or all
+    // 512 combinations with the emphasis bits selecting one of eight 64 color blocks
+    pub fn from_pal(data: &[u8]) -> Result<Self, &'static str> {
+        match data.len() {
+            192 => {
+                let mut colors = [[0; 3]; 64];
+                for (color, rgb) in colors.iter_mut().zip(data.chunks_exact(3)) {
+                    color.copy_from_slice(rgb);
+                }
+                Ok(Self::with_emphasis(&colors))
+            }
+            1536 => {
+                let mut colors = Box::new([[0; 3]; 512]);
+                for (color, rgb) in colors.iter_mut().zip(data.chunks_exact(3)) {
+                    color.copy_from_slice(rgb);
+                }
+                Ok(Self { colors })
+            }
+            _ => Err("Palette file must be 192 or 1536 bytes"),
+        }
+    }
+
+    fn with_emphasis(base: &[[u8; 3]; 64]) -> Self {
+        let mut colors = Box::new([[0; 3]; 512]);
+        for (pixel, color) in colors.iter_mut().enumerate() {
+            let index = pixel & 0x3F;
+            let emphasis = pixel >> 6;
+            *color = base[index];
+
+            // Columns $E and $F are blacker than black and not affected
+            if index & 0x0E == 0x0E {
+                continue;
+            }
+            // Each emphasized channel darkens the other two, so attenuations stack
+            for (channel, value) in color.iter_mut().enumerate() {
+                let darkening = (emphasis & !(1 << channel)).count_ones() as i32;
+                *value = (*value as f32 * EMPHASIS_ATTENUATION.powi(darkening)).round() as u8;
+            }
+        }
+        Self { colors }
+    }
+
+    // Color for a pixel as the PPU outputs it, index with emphasis bits
+    pub fn rgb(&self, pixel: u16) -> [u8; 3] {
+        self.colors[(pixel & 0x1FF) as usize]
+    }
+
+    // Converts PPU output to RGBA, four bytes per pixel
+    pub fn to_rgba(&self, pixels: &[u16]) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(pixels.len() * 4);
+        for &pixel in pixels {
+            let [red, green, blue] = self.rgb(pixel);
+            rgba.extend_from_slice(&[red, green, blue, 0xFF]);
+        }
+        rgba
+    }
+}
+
+impl Default for ColorPalette {
+    fn default() -> Self {
+        Self::ntsc()
+    }
+}
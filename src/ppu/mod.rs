@@ -2,25 +2,253 @@ mod registers;
 
 use self::registers::PpuRegisters;
 use crate::memory::{Oam, Palette, Vram};
+use crate::region::Region;
+use crate::state::{StateReader, StateWriter};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+// Scanline count and vblank placement come from the region
+const DOTS_PER_SCANLINE: u16 = 341;
+
+const STATUS_VBLANK: u8 = 0x80;
+const STATUS_SPRITE_0_HIT: u8 = 0x40;
+const STATUS_SPRITE_OVERFLOW: u8 = 0x20;
+const CTRL_NMI_ENABLE: u8 = 0x80;
+const MASK_GREYSCALE: u8 = 0x01;
+const MASK_RENDERING: u8 = 0x18;
+const MASK_EMPHASIS: u8 = 0xE0;
+
+const STATE_VERSION: u16 = 3;
 
 pub(crate) struct Ppu {
     registers: PpuRegisters,
     pub(super) oam: Oam,
     palette: Palette,
     vram: Vram,
+    region: Region,
+    // Palette index with the emphasis bits above it, for each visible pixel
+    frame_buffer: Box<[u16]>,
+    // Color subcarrier phase in twelfths of a cycle, a dot is 8 of them. frame_phase is the
+    // phase of the first pixel of the frame in the buffer.
+    phase: u8,
+    frame_phase: u8,
+
+    // Timing counters
+    dot: u16,
+    scanline: u16,
+    frame: u64,
 }
 
 impl Ppu {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(region: Region) -> Self {
         Self {
             registers: PpuRegisters::new(),
             oam: Oam::new(),
             palette: Palette::new(),
             vram: Vram::new(),
+            region,
+            frame_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+            phase: 0,
+            frame_phase: 0,
+            dot: 0,
+            scanline: 0,
+            frame: 0,
         }
     }
 
     pub(super) fn get_oam_address(&self) -> u8 {
         self.registers.oam_address
     }
+
+    pub(crate) fn tick(&mut self) {
+        // TODO: Rendering, only the timing counters and vblank flag advance for now
+        let pre_render_scanline = self.region.scanlines_per_frame() - 1;
+
+        self.dot += 1;
+        // Odd frames jump from the second to last pre-render dot straight to the next frame
+        if self.dot == DOTS_PER_SCANLINE - 1
+            && self.scanline == pre_render_scanline
+            && self.frame & 1 == 1
+            && self.region.skips_odd_frame_dot()
+            && self.registers.ppumask & MASK_RENDERING != 0
+        {
+            self.dot += 1;
+        }
+
+        if self.dot >= DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > pre_render_scanline {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+
+        self.phase = (self.phase + 8) % 12;
+        if self.dot == 1 && self.scanline == 0 {
+            self.frame_phase = self.phase;
+        }
+
+        if (self.scanline as usize) < SCREEN_HEIGHT && (1..=SCREEN_WIDTH as u16).contains(&self.dot)
+        {
+            self.output_pixel();
+        }
+
+        if self.dot == 1 {
+            if self.scanline == self.region.vblank_scanline() {
+                self.registers.ppustatus |= STATUS_VBLANK;
+            } else if self.scanline == pre_render_scanline {
+                self.registers.ppustatus &=
+                    !(STATUS_VBLANK | STATUS_SPRITE_0_HIT | STATUS_SPRITE_OVERFLOW);
+            }
+        }
+    }
+
+    fn output_pixel(&mut self) {
+        let vram_address = self.registers.current_vram_address & 0x3FFF;
+        // With rendering off the backdrop comes from the palette entry the VRAM address points
+        // at, if it points into the palette
+        let address = if self.registers.ppumask & MASK_RENDERING == 0 && vram_address >= 0x3F00 {
+            vram_address
+        } else {
+            0x3F00
+        };
+
+        let mut index = self.palette.read(address) & 0x3F;
+        if self.registers.ppumask & MASK_GREYSCALE != 0 {
+            index &= 0x30;
+        }
+
+        // PAL and Dendy PPUs have the red and green emphasis bits swapped
+        let mut emphasis = (self.registers.ppumask & MASK_EMPHASIS) >> 5;
+        if self.region != Region::Ntsc {
+            emphasis = (emphasis & 0x04) | ((emphasis & 0x01) << 1) | ((emphasis & 0x02) >> 1);
+        }
+
+        let pixel = self.scanline as usize * SCREEN_WIDTH + self.dot as usize - 1;
+        self.frame_buffer[pixel] = index as u16 | (emphasis as u16) << 6;
+    }
+
+    pub(crate) fn frame_buffer(&self) -> &[u16] {
+        &self.frame_buffer
+    }
+
+    pub(crate) fn frame_phase(&self) -> u8 {
+        self.frame_phase
+    }
+
+    // Level of the /NMI output, the CPU side latches the edge
+    pub(crate) fn nmi_output(&self) -> bool {
+        self.registers.ppustatus & STATUS_VBLANK != 0
+            && self.registers.ppuctrl & CTRL_NMI_ENABLE != 0
+    }
+
+    pub(crate) fn region(&self) -> Region {
+        self.region
+    }
+
+    // Takes effect from the current position, which is kept within the new frame length
+    pub(crate) fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.scanline = self.scanline.min(region.scanlines_per_frame() - 1);
+    }
+
+    // What a CPU read of $2000-$3FFF would return, without clearing vblank, resetting the
+    // write latch or advancing the VRAM address
+    pub(crate) fn peek_register(&self, address: u16, open_bus: u8) -> u8 {
+        match address & 0x0007 {
+            2 => (self.registers.ppustatus & 0xE0) | (open_bus & 0x1F),
+            4 => self.oam.read(self.registers.oam_address),
+            7 => {
+                // Palette reads bypass the buffer
+                let vram_address = self.registers.current_vram_address & 0x3FFF;
+                if vram_address >= 0x3F00 {
+                    (self.palette.read(vram_address) & 0x3F) | (open_bus & 0xC0)
+                } else {
+                    self.registers.read_buffer
+                }
+            }
+            _ => open_bus,
+        }
+    }
+
+    // Index into the 2K of console VRAM, after nametable mirroring
+    pub(crate) fn peek_vram(&self, index: u16) -> u8 {
+        self.vram.read(index)
+    }
+
+    pub(crate) fn poke_vram(&mut self, index: u16, value: u8) {
+        self.vram.write(index, value);
+    }
+
+    pub(crate) fn peek_palette(&self, address: u16) -> u8 {
+        self.palette.read(address)
+    }
+
+    pub(crate) fn poke_palette(&mut self, address: u16, value: u8) {
+        self.palette.write(address, value);
+    }
+
+    pub(crate) fn peek_oam(&self, address: u8) -> u8 {
+        self.oam.read(address)
+    }
+
+    pub(crate) fn poke_oam(&mut self, address: u8, value: u8) {
+        self.oam.write(address, value);
+    }
+
+    pub(crate) fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    pub(crate) fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub(crate) fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.chunk(b"PPU ", STATE_VERSION, |writer| {
+            self.registers.save_state(writer);
+            writer.write_u16(self.dot);
+            writer.write_u16(self.scanline);
+            writer.write_u64(self.frame);
+            self.vram.save_state(writer);
+            self.palette.save_state(writer);
+            self.oam.save_state(writer);
+            writer.write_u8(self.phase);
+            writer.write_u8(self.frame_phase);
+            // The picture goes along, after a load or rewind it matches the restored frame
+            writer.write_u16_slice(&self.frame_buffer);
+        });
+    }
+
+    pub(crate) fn load_state(&mut self, state: &StateReader) -> Result<(), &'static str> {
+        let Some(mut chunk) = state.chunk(b"PPU ") else {
+            return Ok(());
+        };
+        chunk.expect_version(STATE_VERSION)?;
+
+        self.registers.load_state(&mut chunk)?;
+        self.dot = chunk.read_u16()?;
+        self.scanline = chunk.read_u16()?;
+        self.frame = chunk.read_u64()?;
+        self.vram.load_state(&mut chunk)?;
+        self.palette.load_state(&mut chunk)?;
+        self.oam.load_state(&mut chunk)?;
+        if chunk.version() >= 2 {
+            self.phase = chunk.read_u8()? % 12;
+            self.frame_phase = chunk.read_u8()? % 12;
+        }
+        if chunk.version() >= 3 {
+            chunk.read_u16_slice(&mut self.frame_buffer)?;
+            for pixel in self.frame_buffer.iter_mut() {
+                *pixel &= 0x1FF;
+            }
+        }
+        Ok(())
+    }
 }
@@ -1,18 +1,20 @@
+use crate::state::{ChunkReader, StateWriter};
+
 pub(super) struct PpuRegisters {
     // Loopy registers (internal scroll/address state)
-    current_vram_address: u16,
+    pub(super) current_vram_address: u16,
     temp_vram_address: u16,
     fine_x: u8,
     write_latch: bool,
 
     // Memory-mapped registers ($2000-$2003)
-    ppuctrl: u8,                // $2000 - Control flags
-    ppumask: u8,                // $2001 - Rendering flags
-    ppustatus: u8,              // $2002 - Status flags (mostly read-only)
+    pub(super) ppuctrl: u8,     // $2000 - Control flags
+    pub(super) ppumask: u8,     // $2001 - Rendering flags
+    pub(super) ppustatus: u8,   // $2002 - Status flags (mostly read-only)
     pub(super) oam_address: u8, // $2003 - OAM read/write address
 
     // Internal state
-    read_buffer: u8, // Buffered read for $2007
+    pub(super) read_buffer: u8, // Buffered read for $2007
 }
 
 impl PpuRegisters {
@@ -29,4 +31,29 @@ impl PpuRegisters {
             read_buffer: 0,
         }
     }
+
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.current_vram_address);
+        writer.write_u16(self.temp_vram_address);
+        writer.write_u8(self.fine_x);
+        writer.write_bool(self.write_latch);
+        writer.write_u8(self.ppuctrl);
+        writer.write_u8(self.ppumask);
+        writer.write_u8(self.ppustatus);
+        writer.write_u8(self.oam_address);
+        writer.write_u8(self.read_buffer);
+    }
+
+    pub(super) fn load_state(&mut self, chunk: &mut ChunkReader) -> Result<(), &'static str> {
+        self.current_vram_address = chunk.read_u16()?;
+        self.temp_vram_address = chunk.read_u16()?;
+        self.fine_x = chunk.read_u8()?;
+        self.write_latch = chunk.read_bool()?;
+        self.ppuctrl = chunk.read_u8()?;
+        self.ppumask = chunk.read_u8()?;
+        self.ppustatus = chunk.read_u8()?;
+        self.oam_address = chunk.read_u8()?;
+        self.read_buffer = chunk.read_u8()?;
+        Ok(())
+    }
 }
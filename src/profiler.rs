@@ -0,0 +1,291 @@
+// Per-routine CPU cycle accounting from JSR/RTS and interrupt entry/exit
+//
+// Cycles between two control flow events go to the routine on top of the call stack
+// (exclusive) and to every routine on the stack (inclusive). Calls are unwound by stack
+// pointer, so routines left through stack tricks instead of RTS are still closed.
+
+use std::collections::BTreeMap;
+
+use crate::cpu::CallKind;
+use crate::disassembler::{SymbolAddress, SymbolTable};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum RoutineKind {
+    // Cycles outside of any tracked call
+    TopLevel,
+    Subroutine,
+    Nmi,
+    Irq,
+    Break,
+}
+
+pub struct ProfileEntry {
+    location: Option<SymbolAddress>,
+    address: u16,
+    kind: RoutineKind,
+    calls: u64,
+    inclusive_cycles: u64,
+    exclusive_cycles: u64,
+    max_cycles_per_frame: u64,
+    frame_cycles: u64,
+}
+
+impl ProfileEntry {
+    // PRG-ROM offset for banked code, so the same CPU address in two banks stays apart.
+    // None for the top level entry.
+    pub fn location(&self) -> Option<SymbolAddress> {
+        self.location
+    }
+
+    // CPU address the routine was last entered at
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    pub fn kind(&self) -> RoutineKind {
+        self.kind
+    }
+
+    pub fn calls(&self) -> u64 {
+        self.calls
+    }
+
+    pub fn inclusive_cycles(&self) -> u64 {
+        self.inclusive_cycles
+    }
+
+    pub fn exclusive_cycles(&self) -> u64 {
+        self.exclusive_cycles
+    }
+
+    // Most inclusive cycles spent in the routine during a single frame
+    pub fn max_cycles_per_frame(&self) -> u64 {
+        self.max_cycles_per_frame
+    }
+
+    pub fn name(&self, symbols: Option<&SymbolTable>) -> String {
+        let Some(location) = self.location else {
+            return "(top level)".to_owned();
+        };
+        if let Some(symbol) = symbols
+            .and_then(|symbols| symbols.get(location))
+            .filter(|symbol| !symbol.label.is_empty())
+        {
+            return symbol.label.clone();
+        }
+
+        let prefix = match self.kind {
+            RoutineKind::Nmi => "NMI ",
+            RoutineKind::Irq => "IRQ ",
+            RoutineKind::Break => "BRK ",
+            _ => "",
+        };
+        match location {
+            SymbolAddress::PrgRom(offset) => {
+                format!("{}${:04X} (PRG {:05X})", prefix, self.address, offset)
+            }
+            SymbolAddress::Cpu(address) => format!("{}${:04X}", prefix, address),
+        }
+    }
+}
+
+struct ActiveCall {
+    entry: usize,
+    // Stack pointer right after the call pushed its return address
+    stack_pointer: u8,
+}
+
+pub(crate) struct Profiler {
+    enabled: bool,
+    entries: Vec<ProfileEntry>,
+    entry_indices: BTreeMap<Option<SymbolAddress>, usize>,
+    stack: Vec<ActiveCall>,
+    last_cycle: u64,
+}
+
+impl Profiler {
+    pub(crate) fn new() -> Self {
+        let mut profiler = Self {
+            enabled: false,
+            entries: Vec::new(),
+            entry_indices: BTreeMap::new(),
+            stack: Vec::new(),
+            last_cycle: 0,
+        };
+        profiler.reset();
+        profiler
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Calls made while stopped were never seen, so tracking restarts at the top level
+    pub(crate) fn start(&mut self, total_cycles: u64) {
+        self.enabled = true;
+        self.stack.clear();
+        self.last_cycle = total_cycles;
+    }
+
+    pub(crate) fn stop(&mut self, total_cycles: u64) {
+        if self.enabled {
+            self.advance(total_cycles);
+            self.enabled = false;
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.entries.clear();
+        self.entry_indices.clear();
+        self.stack.clear();
+        self.entry(None, 0, RoutineKind::TopLevel);
+    }
+
+    pub(crate) fn entries(&self) -> &[ProfileEntry] {
+        &self.entries
+    }
+
+    fn entry(&mut self, location: Option<SymbolAddress>, address: u16, kind: RoutineKind) -> usize {
+        if let Some(&index) = self.entry_indices.get(&location) {
+            self.entries[index].address = address;
+            return index;
+        }
+
+        self.entries.push(ProfileEntry {
+            location,
+            address,
+            kind,
+            calls: 0,
+            inclusive_cycles: 0,
+            exclusive_cycles: 0,
+            max_cycles_per_frame: 0,
+            frame_cycles: 0,
+        });
+        self.entry_indices.insert(location, self.entries.len() - 1);
+        self.entries.len() - 1
+    }
+
+    fn advance(&mut self, total_cycles: u64) {
+        // Loading a state or powering on moves the clock backwards, open calls are stale
+        if total_cycles < self.last_cycle {
+            self.stack.clear();
+            self.last_cycle = total_cycles;
+            return;
+        }
+        let elapsed = total_cycles - self.last_cycle;
+        self.last_cycle = total_cycles;
+
+        let top = self.stack.last().map_or(0, |call| call.entry);
+        self.entries[top].exclusive_cycles += elapsed;
+
+        // The top level entry is implicitly on the stack
+        for entry in std::iter::once(0).chain(self.stack.iter().map(|call| call.entry)) {
+            self.entries[entry].inclusive_cycles += elapsed;
+            self.entries[entry].frame_cycles += elapsed;
+        }
+    }
+
+    pub(crate) fn record_call(
+        &mut self,
+        kind: CallKind,
+        target: u16,
+        location: SymbolAddress,
+        stack_pointer: u8,
+        total_cycles: u64,
+    ) {
+        self.advance(total_cycles);
+
+        let kind = match kind {
+            CallKind::Subroutine => RoutineKind::Subroutine,
+            CallKind::Nmi => RoutineKind::Nmi,
+            CallKind::Irq => RoutineKind::Irq,
+            CallKind::Break => RoutineKind::Break,
+        };
+        let entry = self.entry(Some(location), target, kind);
+        self.entries[entry].calls += 1;
+        self.stack.push(ActiveCall {
+            entry,
+            stack_pointer,
+        });
+    }
+
+    // RTS and RTI close every call whose return address is now above the stack pointer
+    pub(crate) fn record_return(&mut self, stack_pointer: u8, total_cycles: u64) {
+        self.advance(total_cycles);
+
+        while self
+            .stack
+            .last()
+            .is_some_and(|call| call.stack_pointer < stack_pointer)
+        {
+            self.stack.pop();
+        }
+    }
+
+    pub(crate) fn end_frame(&mut self, total_cycles: u64) {
+        self.advance(total_cycles);
+        for entry in &mut self.entries {
+            entry.max_cycles_per_frame = entry.max_cycles_per_frame.max(entry.frame_cycles);
+            entry.frame_cycles = 0;
+        }
+    }
+
+    fn sorted_entries(&self) -> Vec<&ProfileEntry> {
+        let mut entries: Vec<&ProfileEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.exclusive_cycles));
+        entries
+    }
+
+    pub(crate) fn export_text(&self, symbols: Option<&SymbolTable>) -> String {
+        let total = self.entries[0].inclusive_cycles.max(1);
+        let mut text = format!(
+            "{:<32} {:>10} {:>14} {:>14} {:>7} {:>12}\n",
+            "Routine", "Calls", "Inclusive", "Exclusive", "Excl %", "Max/frame"
+        );
+
+        for entry in self.sorted_entries() {
+            text.push_str(&format!(
+                "{:<32} {:>10} {:>14} {:>14} {:>6.2}% {:>12}\n",
+                entry.name(symbols),
+                entry.calls,
+                entry.inclusive_cycles,
+                entry.exclusive_cycles,
+                entry.exclusive_cycles as f64 * 100.0 / total as f64,
+                entry.max_cycles_per_frame
+            ));
+        }
+        text
+    }
+
+    pub(crate) fn export_csv(&self, symbols: Option<&SymbolTable>) -> String {
+        let mut text = String::from(
+            "routine,address,prg_offset,kind,calls,inclusive_cycles,exclusive_cycles,max_cycles_per_frame\n",
+        );
+
+        for entry in self.sorted_entries() {
+            let prg_offset = match entry.location {
+                Some(SymbolAddress::PrgRom(offset)) => format!("{:05X}", offset),
+                _ => String::new(),
+            };
+            let kind = match entry.kind {
+                RoutineKind::TopLevel => "top",
+                RoutineKind::Subroutine => "jsr",
+                RoutineKind::Nmi => "nmi",
+                RoutineKind::Irq => "irq",
+                RoutineKind::Break => "brk",
+            };
+            text.push_str(&format!(
+                "\"{}\",{:04X},{},{},{},{},{},{}\n",
+                entry.name(symbols).replace('"', "\"\""),
+                entry.address,
+                prg_offset,
+                kind,
+                entry.calls,
+                entry.inclusive_cycles,
+                entry.exclusive_cycles,
+                entry.max_cycles_per_frame
+            ));
+        }
+        text
+    }
+}
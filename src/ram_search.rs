@@ -0,0 +1,184 @@
+use crate::nes::Nes;
+
+// Searchable memory: internal RAM, optionally followed by cartridge PRG-RAM
+const RAM_RANGE: (u16, u16) = (0x0000, 0x0800);
+const PRG_RAM_RANGE: (u16, u16) = (0x6000, 0x8000);
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ValueSize {
+    Byte,
+    // Little endian, the low byte at the address
+    Word,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum CompareTo {
+    // The value when the last search ran, NotEqual is "changed since last"
+    Previous,
+    Value(i32),
+}
+
+// Words need their high byte inside the same range
+fn overhangs(address: u16, size: ValueSize) -> bool {
+    size == ValueSize::Word && (address == RAM_RANGE.1 - 1 || address == PRG_RAM_RANGE.1 - 1)
+}
+
+fn read_value(nes: &Nes, address: u16, size: ValueSize, signed: bool) -> i32 {
+    match (size, signed) {
+        (ValueSize::Byte, false) => nes.peek(address) as i32,
+        (ValueSize::Byte, true) => nes.peek(address) as i8 as i32,
+        (ValueSize::Word, _) => {
+            let word = u16::from_le_bytes([nes.peek(address), nes.peek(address.wrapping_add(1))]);
+            if signed {
+                word as i16 as i32
+            } else {
+                word as i32
+            }
+        }
+    }
+}
+
+// Narrows a set of candidate addresses by comparing memory against the previous search.
+// Run it again on later frames to keep narrowing.
+pub struct RamSearch {
+    size: ValueSize,
+    signed: bool,
+    include_prg_ram: bool,
+    candidates: Vec<u16>,
+    // Value of each candidate at the last search, same order as candidates
+    previous: Vec<i32>,
+}
+
+impl RamSearch {
+    pub fn new(size: ValueSize, signed: bool, include_prg_ram: bool) -> Self {
+        Self {
+            size,
+            signed,
+            include_prg_ram,
+            candidates: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> ValueSize {
+        self.size
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    pub fn includes_prg_ram(&self) -> bool {
+        self.include_prg_ram
+    }
+
+    // Every address becomes a candidate again and the current memory is the new snapshot
+    pub fn reset(&mut self, nes: &Nes) {
+        self.candidates.clear();
+
+        let mut ranges = vec![RAM_RANGE];
+        if self.include_prg_ram {
+            ranges.push(PRG_RAM_RANGE);
+        }
+        for (start, end) in ranges {
+            let size = self.size;
+            self.candidates
+                .extend((start..end).filter(|&address| !overhangs(address, size)));
+        }
+
+        self.take_snapshot(nes);
+    }
+
+    // Reinterpreting values keeps the candidates that still fit, the snapshot is taken again
+    pub fn set_format(&mut self, nes: &Nes, size: ValueSize, signed: bool) {
+        self.size = size;
+        self.signed = signed;
+        self.candidates.retain(|&address| !overhangs(address, size));
+        self.take_snapshot(nes);
+    }
+
+    pub fn set_include_prg_ram(&mut self, nes: &Nes, include_prg_ram: bool) {
+        self.include_prg_ram = include_prg_ram;
+        self.reset(nes);
+    }
+
+    pub fn take_snapshot(&mut self, nes: &Nes) {
+        self.previous.clear();
+        self.previous.extend(
+            self.candidates
+                .iter()
+                .map(|&address| read_value(nes, address, self.size, self.signed)),
+        );
+    }
+
+    // Keeps the candidates whose current value compares true, then snapshots the survivors.
+    // Returns how many are left.
+    pub fn filter(&mut self, nes: &Nes, comparison: Comparison, compare_to: CompareTo) -> usize {
+        let mut kept = 0;
+
+        for index in 0..self.candidates.len() {
+            let address = self.candidates[index];
+            let current = read_value(nes, address, self.size, self.signed);
+            let target = match compare_to {
+                CompareTo::Previous => self.previous[index],
+                CompareTo::Value(value) => value,
+            };
+
+            let matches = match comparison {
+                Comparison::Equal => current == target,
+                Comparison::NotEqual => current != target,
+                Comparison::Less => current < target,
+                Comparison::Greater => current > target,
+                Comparison::LessOrEqual => current <= target,
+                Comparison::GreaterOrEqual => current >= target,
+            };
+
+            if matches {
+                self.candidates[kept] = address;
+                self.previous[kept] = current;
+                kept += 1;
+            }
+        }
+
+        self.candidates.truncate(kept);
+        self.previous.truncate(kept);
+        kept
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    // Snapshot value of the candidate at index
+    pub fn previous_value(&self, index: usize) -> Option<i32> {
+        self.previous.get(index).copied()
+    }
+
+    pub fn current_value(&self, nes: &Nes, address: u16) -> i32 {
+        read_value(nes, address, self.size, self.signed)
+    }
+}
+
+// A pinned address, read live every time it is shown
+pub struct Watch {
+    pub address: u16,
+    pub size: ValueSize,
+    pub signed: bool,
+    pub label: String,
+}
+
+impl Watch {
+    pub fn value(&self, nes: &Nes) -> i32 {
+        read_value(nes, self.address, self.size, self.signed)
+    }
+}
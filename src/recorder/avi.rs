@@ -0,0 +1,287 @@
+// AVI with ZMBV video, the lossless codec DOSBox captures with
+//
+// ZMBV keeps one zlib stream going between keyframes. Keyframes carry the whole picture,
+// other frames only the blocks that changed, XORed with the previous frame. The encoder
+// never searches for motion, every block is compared in place.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use flate2::{Compress, Compression, FlushCompress};
+
+use crate::filter::RgbaImage;
+
+const BLOCK_SIZE: usize = 16;
+const KEYFRAME_INTERVAL: u64 = 300;
+const ZMBV_FORMAT_32BPP: u8 = 8;
+const AVIIF_KEYFRAME: u32 = 0x10;
+const AVIF_HASINDEX: u32 = 0x10;
+// RIFF sizes are 32 bits and some readers treat them as signed
+const MAX_FILE_SIZE: u64 = 0x7FFF_0000;
+
+struct IndexEntry {
+    keyframe: bool,
+    // From the start of the movi list's type
+    offset: u32,
+    size: u32,
+}
+
+pub(super) struct AviWriter<W: Write + Seek> {
+    output: W,
+    frame_rate: (u32, u32),
+    width: usize,
+    height: usize,
+    // Where the movi list's type code sits, chunk offsets count from here
+    movi_start: u64,
+    position: u64,
+    index: Vec<IndexEntry>,
+    zlib: Compress,
+    // Previous frame as 0x00RRGGBB, empty before the first
+    previous: Vec<u32>,
+    current: Vec<u32>,
+    work: Vec<u8>,
+    chunk: Vec<u8>,
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    pub(super) fn new(output: W, frame_rate: (u32, u32)) -> Self {
+        Self {
+            output,
+            frame_rate,
+            width: 0,
+            height: 0,
+            movi_start: 0,
+            position: 0,
+            index: Vec::new(),
+            zlib: Compress::new(Compression::default(), true),
+            previous: Vec::new(),
+            current: Vec::new(),
+            work: Vec::new(),
+            chunk: Vec::new(),
+        }
+    }
+
+    pub(super) fn write_frame(&mut self, image: &RgbaImage) -> std::io::Result<()> {
+        if self.index.is_empty() {
+            self.width = image.width();
+            self.height = image.height();
+            self.write_headers()?;
+        } else if image.width() != self.width || image.height() != self.height {
+            return Err(std::io::ErrorKind::InvalidInput.into());
+        }
+
+        self.current.clear();
+        self.current.extend(
+            image
+                .to_rgba()
+                .chunks_exact(4)
+                .map(|pixel| (pixel[0] as u32) << 16 | (pixel[1] as u32) << 8 | pixel[2] as u32),
+        );
+
+        let keyframe = (self.index.len() as u64).is_multiple_of(KEYFRAME_INTERVAL);
+        self.encode_frame(keyframe)?;
+        std::mem::swap(&mut self.previous, &mut self.current);
+
+        if self.position + 8 + self.chunk.len() as u64 + 16 * (self.index.len() as u64 + 1)
+            > MAX_FILE_SIZE
+        {
+            return Err(std::io::ErrorKind::FileTooLarge.into());
+        }
+        self.index.push(IndexEntry {
+            keyframe,
+            offset: (self.position - self.movi_start) as u32,
+            size: self.chunk.len() as u32,
+        });
+        let chunk = std::mem::take(&mut self.chunk);
+        self.write_chunk(b"00dc", &chunk)?;
+        self.chunk = chunk;
+        Ok(())
+    }
+
+    fn encode_frame(&mut self, keyframe: bool) -> std::io::Result<()> {
+        self.chunk.clear();
+        self.work.clear();
+
+        if keyframe {
+            self.chunk.push(0x01);
+            // Version 0.1, zlib, pixel format, block size
+            self.chunk.extend_from_slice(&[
+                0,
+                1,
+                1,
+                ZMBV_FORMAT_32BPP,
+                BLOCK_SIZE as u8,
+                BLOCK_SIZE as u8,
+            ]);
+            self.zlib.reset();
+            for pixel in &self.current {
+                self.work.extend_from_slice(&pixel.to_le_bytes());
+            }
+        } else {
+            self.chunk.push(0x00);
+            let blocks_x = self.width.div_ceil(BLOCK_SIZE);
+            let blocks_y = self.height.div_ceil(BLOCK_SIZE);
+            // Two bytes per block: motion vectors, always zero, with bit 0 of the first set
+            // when XOR data follows. Padded to four bytes.
+            let vectors_size = (blocks_x * blocks_y * 2 + 3) & !3;
+            self.work.resize(vectors_size, 0);
+
+            for block_y in 0..blocks_y {
+                for block_x in 0..blocks_x {
+                    let left = block_x * BLOCK_SIZE;
+                    let top = block_y * BLOCK_SIZE;
+                    let right = (left + BLOCK_SIZE).min(self.width);
+                    let bottom = (top + BLOCK_SIZE).min(self.height);
+                    let rows = (top..bottom).map(|y| y * self.width + left..y * self.width + right);
+
+                    let changed = rows
+                        .clone()
+                        .any(|row| self.current[row.clone()] != self.previous[row]);
+                    if !changed {
+                        continue;
+                    }
+                    self.work[(block_y * blocks_x + block_x) * 2] = 1;
+                    for row in rows {
+                        for (current, previous) in
+                            self.current[row.clone()].iter().zip(&self.previous[row])
+                        {
+                            self.work
+                                .extend_from_slice(&(current ^ previous).to_le_bytes());
+                        }
+                    }
+                }
+            }
+        }
+
+        self.compress()
+    }
+
+    // Appends the work buffer to the chunk through the running zlib stream
+    fn compress(&mut self) -> std::io::Result<()> {
+        let start = self.zlib.total_in();
+        loop {
+            self.chunk.reserve(self.work.len() / 4 + 1024);
+            let consumed = (self.zlib.total_in() - start) as usize;
+            self.zlib
+                .compress_vec(&self.work[consumed..], &mut self.chunk, FlushCompress::Sync)
+                .map_err(|_| std::io::ErrorKind::InvalidData)?;
+            let done = (self.zlib.total_in() - start) as usize == self.work.len();
+            // A full output buffer may still hold back part of the flush
+            if done && self.chunk.len() < self.chunk.capacity() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn write_chunk(&mut self, id: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+        self.output.write_all(id)?;
+        self.output.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.output.write_all(data)?;
+        self.position += 8 + data.len() as u64;
+        if data.len() % 2 == 1 {
+            self.output.write_all(&[0])?;
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    // Frame counts and sizes are left zero here and filled in by finish
+    fn write_headers(&mut self) -> std::io::Result<()> {
+        let (rate, scale) = self.frame_rate;
+        let (width, height) = (self.width as u32, self.height as u32);
+        let frame_size = width * height * 4;
+
+        let mut avih = Vec::new();
+        for value in [
+            (1_000_000u64 * scale as u64 / rate as u64) as u32, // Microseconds per frame
+            0,
+            0,
+            AVIF_HASINDEX,
+            0, // Total frames
+            0,
+            1, // Streams
+            frame_size,
+            width,
+            height,
+            0,
+            0,
+            0,
+            0,
+        ] {
+            avih.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut strh = Vec::new();
+        strh.extend_from_slice(b"vidsZMBV");
+        for value in [0, 0, 0, scale, rate, 0, 0, frame_size, u32::MAX, 0] {
+            // Flags, priority and language, initial frames, scale, rate, start, length,
+            // buffer size, quality, sample size
+            strh.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [0, 0, width as u16, height as u16] {
+            strh.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut strf = Vec::new();
+        strf.extend_from_slice(&40u32.to_le_bytes());
+        strf.extend_from_slice(&width.to_le_bytes());
+        strf.extend_from_slice(&height.to_le_bytes());
+        strf.extend_from_slice(&1u16.to_le_bytes()); // Planes
+        strf.extend_from_slice(&24u16.to_le_bytes()); // Bits per pixel
+        strf.extend_from_slice(b"ZMBV");
+        strf.extend_from_slice(&frame_size.to_le_bytes());
+        strf.extend_from_slice(&[0; 16]);
+
+        let strl_size = 4 + (8 + strh.len()) + (8 + strf.len());
+        let hdrl_size = 4 + (8 + avih.len()) + (8 + strl_size);
+
+        self.output.write_all(b"RIFF\0\0\0\0AVI ")?;
+        self.output.write_all(b"LIST")?;
+        self.output.write_all(&(hdrl_size as u32).to_le_bytes())?;
+        self.output.write_all(b"hdrl")?;
+        self.position = 12 + 12;
+        self.write_chunk(b"avih", &avih)?;
+        self.output.write_all(b"LIST")?;
+        self.output.write_all(&(strl_size as u32).to_le_bytes())?;
+        self.output.write_all(b"strl")?;
+        self.position += 12;
+        self.write_chunk(b"strh", &strh)?;
+        self.write_chunk(b"strf", &strf)?;
+
+        self.output.write_all(b"LIST\0\0\0\0movi")?;
+        self.position += 12;
+        self.movi_start = self.position - 4;
+        Ok(())
+    }
+
+    pub(super) fn finish(mut self) -> std::io::Result<()> {
+        if self.index.is_empty() {
+            return self.output.flush();
+        }
+
+        let movi_size = (self.position - self.movi_start) as u32;
+        let mut idx1 = Vec::with_capacity(self.index.len() * 16);
+        for entry in &self.index {
+            idx1.extend_from_slice(b"00dc");
+            let flags = if entry.keyframe { AVIIF_KEYFRAME } else { 0 };
+            idx1.extend_from_slice(&flags.to_le_bytes());
+            idx1.extend_from_slice(&entry.offset.to_le_bytes());
+            idx1.extend_from_slice(&entry.size.to_le_bytes());
+        }
+        self.write_chunk(b"idx1", &idx1)?;
+
+        let frames = self.index.len() as u32;
+        let patches = [
+            (4, (self.position - 8) as u32),
+            // avih total frames
+            (12 + 12 + 8 + 16, frames),
+            // strh length, after avih and the strl list header
+            (12 + 12 + 8 + 56 + 12 + 8 + 32, frames),
+            (self.movi_start - 4, movi_size),
+        ];
+        for (offset, value) in patches {
+            self.output.seek(SeekFrom::Start(offset))?;
+            self.output.write_all(&value.to_le_bytes())?;
+        }
+        self.output.flush()
+    }
+}
@@ -0,0 +1,401 @@
+// Animated GIF clips from the palette-indexed frame buffer
+//
+// A frame rarely uses more than a few dozen of the 512 palette and emphasis combinations,
+// so every frame gets its own color table and stays lossless without dithering. Frames
+// after the first only store the rectangle that changed.
+
+use std::collections::VecDeque;
+
+use crate::palette::ColorPalette;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Browsers slow down anything shorter than two centiseconds, faster frames are dropped
+const MIN_DELAY: u64 = 2;
+const MAX_CODE_SIZE: u8 = 12;
+
+struct GifFrame {
+    pixels: Box<[u16]>,
+    // When the frame is shown, in centiseconds from the start of the capture
+    time: u64,
+}
+
+pub(crate) struct GifCapture {
+    frame_rate: (u32, u32),
+    // Emulated frames since the capture started
+    frames_seen: u64,
+    frames: VecDeque<GifFrame>,
+    // None keeps every frame until stopped, otherwise only the latest this many centiseconds
+    max_duration: Option<u64>,
+}
+
+impl GifCapture {
+    pub(crate) fn new(frame_rate: (u32, u32), max_seconds: Option<u32>) -> Self {
+        Self {
+            frame_rate,
+            frames_seen: 0,
+            frames: VecDeque::new(),
+            max_duration: max_seconds.map(|seconds| seconds.max(1) as u64 * 100),
+        }
+    }
+
+    fn time(&self, frame: u64) -> u64 {
+        let (numerator, denominator) = self.frame_rate;
+        frame * 100 * denominator as u64 / numerator as u64
+    }
+
+    pub(crate) fn capture_frame(&mut self, pixels: &[u16]) {
+        let time = self.time(self.frames_seen);
+        self.frames_seen += 1;
+
+        // A repeated frame stays up longer, the next one decides how long
+        if let Some(last) = self.frames.back()
+            && (time < last.time + MIN_DELAY || *last.pixels == *pixels)
+        {
+            return;
+        }
+
+        if let Some(max_duration) = self.max_duration {
+            while self
+                .frames
+                .front()
+                .is_some_and(|first| time - first.time >= max_duration)
+            {
+                self.frames.pop_front();
+            }
+        }
+        self.frames.push_back(GifFrame {
+            pixels: pixels.into(),
+            time,
+        });
+    }
+
+    pub(crate) fn encode(&self, palette: &ColorPalette) -> Result<Vec<u8>, &'static str> {
+        if self.frames.is_empty() {
+            return Err("No frames captured");
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"GIF89a");
+        data.extend_from_slice(&(SCREEN_WIDTH as u16).to_le_bytes());
+        data.extend_from_slice(&(SCREEN_HEIGHT as u16).to_le_bytes());
+        // No global color table, background 0, no aspect ratio
+        data.extend_from_slice(&[0, 0, 0]);
+        // Loop forever
+        data.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+        let end_time = self.time(self.frames_seen);
+        // Frames are only dropped when a new one comes in, a still screen can outlast the limit
+        let start_time = self
+            .max_duration
+            .map_or(0, |max_duration| end_time.saturating_sub(max_duration));
+        // Approximated colors are still on screen after a lossy frame, redraw all of the next
+        let mut previous: Option<&[u16]> = None;
+        let mut lossy = false;
+        for (index, frame) in self.frames.iter().enumerate() {
+            let next_time = self
+                .frames
+                .get(index + 1)
+                .map_or(end_time, |next| next.time);
+            if next_time <= start_time {
+                continue;
+            }
+            let shown = next_time - frame.time.max(start_time);
+            let delay = shown.clamp(MIN_DELAY, u16::MAX as u64) as u16;
+
+            let (left, top, width, height) = match previous {
+                Some(previous) if !lossy => changed_area(previous, &frame.pixels),
+                _ => (0, 0, SCREEN_WIDTH, SCREEN_HEIGHT),
+            };
+            previous = Some(&frame.pixels);
+
+            // Graphic control: leave the frame in place for the next one to draw over
+            data.extend_from_slice(&[0x21, 0xF9, 0x04, 0x04]);
+            data.extend_from_slice(&delay.to_le_bytes());
+            data.extend_from_slice(&[0, 0]);
+
+            let rows = (top..top + height)
+                .map(|y| &frame.pixels[y * SCREEN_WIDTH + left..y * SCREEN_WIDTH + left + width]);
+            let (colors, indices, approximated) = palettize(rows, palette);
+            lossy = approximated;
+            let table_bits = (usize::BITS - (colors.len() - 1).leading_zeros()).max(1) as u8;
+
+            data.push(0x2C);
+            for value in [left, top, width, height] {
+                data.extend_from_slice(&(value as u16).to_le_bytes());
+            }
+            data.push(0x80 | (table_bits - 1));
+            for index in 0..1 << table_bits {
+                data.extend_from_slice(&colors.get(index).copied().unwrap_or([0; 3]));
+            }
+
+            let min_code_size = table_bits.max(2);
+            data.push(min_code_size);
+            let compressed = lzw_compress(&indices, min_code_size);
+            for block in compressed.chunks(255) {
+                data.push(block.len() as u8);
+                data.extend_from_slice(block);
+            }
+            data.push(0);
+        }
+
+        data.push(0x3B);
+        Ok(data)
+    }
+}
+
+// Smallest rectangle holding every changed pixel, one pixel when nothing changed since
+// GIF images can't be empty
+fn changed_area(previous: &[u16], current: &[u16]) -> (usize, usize, usize, usize) {
+    let (mut left, mut top, mut right, mut bottom) = (SCREEN_WIDTH, SCREEN_HEIGHT, 0, 0);
+    for y in 0..SCREEN_HEIGHT {
+        let row = y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH;
+        let changed = previous[row.clone()]
+            .iter()
+            .zip(&current[row])
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(x, _)| x);
+        for x in changed {
+            left = left.min(x);
+            right = right.max(x + 1);
+            top = top.min(y);
+            bottom = y + 1;
+        }
+    }
+
+    if right == 0 {
+        return (0, 0, 1, 1);
+    }
+    (left, top, right - left, bottom - top)
+}
+
+// Color table and indices for the pixels. Past 256 colors, only possible with emphasis
+// changing mid-frame, the extra colors fall back to the closest one in the table and the
+// frame is reported lossy.
+fn palettize<'a>(
+    rows: impl Iterator<Item = &'a [u16]>,
+    palette: &ColorPalette,
+) -> (Vec<[u8; 3]>, Vec<u8>, bool) {
+    let mut table_index = [None::<u8>; 512];
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+    let mut lossy = false;
+
+    for pixel in rows.flatten() {
+        let entry = &mut table_index[*pixel as usize & 0x1FF];
+        let index = match *entry {
+            Some(index) => index,
+            None => {
+                // Several entries share a color, the blacks especially
+                let rgb = palette.rgb(*pixel);
+                let index = if let Some(index) = colors.iter().position(|&color| color == rgb) {
+                    index as u8
+                } else if colors.len() < 256 {
+                    colors.push(rgb);
+                    (colors.len() - 1) as u8
+                } else {
+                    lossy = true;
+                    closest_color(&colors, rgb)
+                };
+                *entry = Some(index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+    (colors, indices, lossy)
+}
+
+fn closest_color(colors: &[[u8; 3]], rgb: [u8; 3]) -> u8 {
+    let distance = |color: &[u8; 3]| -> i32 {
+        (0..3)
+            .map(|i| (color[i] as i32 - rgb[i] as i32).pow(2))
+            .sum()
+    };
+    (0..colors.len())
+        .min_by_key(|&index| distance(&colors[index]))
+        .unwrap_or(0) as u8
+}
+
+// Variable length LZW as GIF wants it, codes packed least significant bit first
+fn lzw_compress(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear_code = 1u16 << min_code_size;
+    let end_code = clear_code + 1;
+    let alphabet = clear_code as usize;
+
+    let mut output = Vec::new();
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    let mut write_code = |code: u16, code_size: u8| {
+        bits |= (code as u32) << bit_count;
+        bit_count += code_size;
+        while bit_count >= 8 {
+            output.push(bits as u8);
+            bits >>= 8;
+            bit_count -= 8;
+        }
+    };
+
+    // table[code * alphabet + index] is the code for that string plus one more index, 0 for
+    // none since no string ever gets code 0
+    let mut table = vec![0u16; 4096 * alphabet];
+    let mut next_code = end_code + 1;
+    let mut code_size = min_code_size + 1;
+    write_code(clear_code, code_size);
+
+    let Some((&first, rest)) = indices.split_first() else {
+        write_code(end_code, code_size);
+        if bit_count > 0 {
+            output.push(bits as u8);
+        }
+        return output;
+    };
+
+    let mut prefix = first as u16;
+    for &index in rest {
+        let slot = prefix as usize * alphabet + index as usize;
+        if table[slot] != 0 {
+            prefix = table[slot];
+            continue;
+        }
+
+        write_code(prefix, code_size);
+        table[slot] = next_code;
+        next_code += 1;
+        if next_code > 1 << code_size && code_size < MAX_CODE_SIZE {
+            code_size += 1;
+        }
+        if next_code == 1 << MAX_CODE_SIZE {
+            write_code(clear_code, code_size);
+            table.fill(0);
+            next_code = end_code + 1;
+            code_size = min_code_size + 1;
+        }
+        prefix = index as u16;
+    }
+
+    write_code(prefix, code_size);
+    // The decoder adds an entry for the last code too and may widen before the end code
+    if next_code == 1 << code_size && code_size < MAX_CODE_SIZE {
+        code_size += 1;
+    }
+    write_code(end_code, code_size);
+    if bit_count > 0 {
+        output.push(bits as u8);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Straightforward GIF LZW decoder, widening the way common decoders do
+    fn lzw_decompress(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear_code = 1usize << min_code_size;
+        let end_code = clear_code + 1;
+        // The clear and end codes hold empty strings so codes index the table directly
+        let initial: Vec<Vec<u8>> = (0..=end_code)
+            .map(|code| {
+                if code < clear_code {
+                    vec![code as u8]
+                } else {
+                    Vec::new()
+                }
+            })
+            .collect();
+
+        let mut table = initial.clone();
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<usize> = None;
+        let mut output = Vec::new();
+        let mut bit_position = 0;
+
+        loop {
+            let mut code = 0;
+            for bit in bit_position..bit_position + code_size as usize {
+                code |= ((data[bit / 8] >> (bit % 8)) as usize & 1) << (bit - bit_position);
+            }
+            bit_position += code_size as usize;
+
+            if code == clear_code {
+                table = initial.clone();
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end_code {
+                return output;
+            }
+
+            let entry = match previous {
+                _ if code < table.len() => table[code].clone(),
+                Some(previous) if code == table.len() => {
+                    let mut entry = table[previous].clone();
+                    entry.push(entry[0]);
+                    entry
+                }
+                _ => panic!("code {code} out of range"),
+            };
+            output.extend_from_slice(&entry);
+
+            if let Some(previous) = previous
+                && table.len() < 1 << MAX_CODE_SIZE
+            {
+                let mut added = table[previous].clone();
+                added.push(entry[0]);
+                table.push(added);
+                if table.len() == 1 << code_size && code_size < MAX_CODE_SIZE {
+                    code_size += 1;
+                }
+            }
+            previous = Some(code);
+        }
+    }
+
+    fn round_trip(indices: &[u8], min_code_size: u8) {
+        let compressed = lzw_compress(indices, min_code_size);
+        assert!(lzw_decompress(&compressed, min_code_size) == indices);
+    }
+
+    // Fixed xorshift so the input is the same every run
+    fn noise(length: usize, mask: u8) -> Vec<u8> {
+        let mut state = 0x2545_F491u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8 & mask
+            })
+            .collect()
+    }
+
+    #[test]
+    fn short_inputs() {
+        round_trip(&[], 2);
+        round_trip(&[3], 2);
+        round_trip(&[0, 1, 0, 1, 0, 1, 2, 3, 2, 3], 2);
+    }
+
+    #[test]
+    fn widens_up_to_the_largest_code_size() {
+        // Noise fills the table quickly, crossing every width and several clear codes
+        for min_code_size in [2, 4, 8] {
+            let mask = ((1u16 << min_code_size) - 1) as u8;
+            for length in [1000, 5000, 60_000] {
+                round_trip(&noise(length, mask), min_code_size);
+            }
+        }
+    }
+
+    #[test]
+    fn long_runs() {
+        // Strings grow by one index per code, so the table fills slowly
+        round_trip(&vec![5; 200_000], 4);
+        let mut stripes = vec![0; 50_000];
+        stripes.extend(noise(20_000, 0x0F));
+        stripes.extend(vec![7; 50_000]);
+        round_trip(&stripes, 4);
+    }
+}
@@ -0,0 +1,138 @@
+mod avi;
+mod gif;
+mod wav;
+mod y4m;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::filter::RgbaImage;
+use crate::region::Region;
+use avi::AviWriter;
+pub(crate) use gif::GifCapture;
+use wav::WavWriter;
+use y4m::Y4mWriter;
+
+const SAMPLE_RATE: u32 = 44100;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VideoFormat {
+    Y4m,
+    // ZMBV compressed, plays in ffmpeg based players
+    Avi,
+}
+
+impl VideoFormat {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "y4m" => Some(Self::Y4m),
+            "avi" => Some(Self::Avi),
+            _ => None,
+        }
+    }
+}
+
+enum VideoWriter {
+    Y4m(Y4mWriter<BufWriter<File>>),
+    Avi(AviWriter<BufWriter<File>>),
+}
+
+// Writes every emulated frame, timed by the region's frame rate rather than the host clock,
+// so a recording made at any emulation speed plays back in real time
+pub(crate) struct Recorder {
+    video: VideoWriter,
+    audio: Option<WavWriter<BufWriter<File>>>,
+    frame_rate: (u32, u32),
+    frames: u64,
+    // Audio samples owed to the frames so far, in frame rate numerator units
+    sample_remainder: u64,
+    samples: Vec<i16>,
+    // The first write error, recording stops there and finish reports it
+    error: Option<&'static str>,
+}
+
+impl Recorder {
+    pub(crate) fn create(
+        video_path: &Path,
+        format: VideoFormat,
+        audio_path: Option<&Path>,
+        region: Region,
+    ) -> Result<Self, &'static str> {
+        let frame_rate = region.frame_rate_ratio();
+        let video_file = File::create(video_path).map_err(|_| "Could not create video file")?;
+        let video_file = BufWriter::new(video_file);
+        let video = match format {
+            VideoFormat::Y4m => VideoWriter::Y4m(Y4mWriter::new(video_file, frame_rate)),
+            VideoFormat::Avi => VideoWriter::Avi(AviWriter::new(video_file, frame_rate)),
+        };
+
+        let audio = match audio_path {
+            Some(path) => {
+                let file = File::create(path).map_err(|_| "Could not create audio file")?;
+                let writer = WavWriter::new(BufWriter::new(file), SAMPLE_RATE)
+                    .map_err(|_| "Could not write audio file")?;
+                Some(writer)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            video,
+            audio,
+            frame_rate,
+            frames: 0,
+            sample_remainder: 0,
+            samples: Vec::new(),
+            error: None,
+        })
+    }
+
+    pub(crate) fn record_frame(&mut self, image: &RgbaImage) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let result = match &mut self.video {
+            VideoWriter::Y4m(writer) => writer.write_frame(image),
+            VideoWriter::Avi(writer) => writer.write_frame(image),
+        };
+        if result.is_err() {
+            self.error = Some("Could not write video file");
+            return;
+        }
+        self.frames += 1;
+
+        if let Some(audio) = self.audio.as_mut() {
+            // Whole samples for this frame, the fraction carries over so the audio stays
+            // locked to the video over any length
+            let (numerator, denominator) = self.frame_rate;
+            self.sample_remainder += SAMPLE_RATE as u64 * denominator as u64;
+            let count = self.sample_remainder / numerator as u64;
+            self.sample_remainder %= numerator as u64;
+
+            // There is no APU yet, the track is silence of the right length
+            self.samples.clear();
+            self.samples.resize(count as usize, 0);
+            if audio.write_samples(&self.samples).is_err() {
+                self.error = Some("Could not write audio file");
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> Result<u64, &'static str> {
+        let video = match self.video {
+            VideoWriter::Y4m(writer) => writer.finish(),
+            VideoWriter::Avi(writer) => writer.finish(),
+        };
+        let audio = self.audio.map_or(Ok(()), WavWriter::finish);
+
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        video.map_err(|_| "Could not write video file")?;
+        audio.map_err(|_| "Could not write audio file")?;
+        Ok(self.frames)
+    }
+}
@@ -0,0 +1,58 @@
+// 16-bit PCM WAV, sizes patched in when the recording ends
+
+use std::io::{Seek, SeekFrom, Write};
+
+const HEADER_SIZE: u32 = 44;
+
+pub(super) struct WavWriter<W: Write + Seek> {
+    output: W,
+    sample_rate: u32,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub(super) fn new(mut output: W, sample_rate: u32) -> std::io::Result<Self> {
+        output.write_all(&[0; HEADER_SIZE as usize])?;
+        Ok(Self {
+            output,
+            sample_rate,
+            data_size: 0,
+        })
+    }
+
+    pub(super) fn write_samples(&mut self, samples: &[i16]) -> std::io::Result<()> {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        // The RIFF size field has to hold the header too
+        self.data_size = self
+            .data_size
+            .checked_add(bytes.len() as u32)
+            .filter(|&size| size <= u32::MAX - HEADER_SIZE)
+            .ok_or(std::io::ErrorKind::FileTooLarge)?;
+        self.output.write_all(&bytes)
+    }
+
+    pub(super) fn finish(mut self) -> std::io::Result<()> {
+        let channels: u16 = 1;
+        let block_align = channels * 2;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_size.to_le_bytes());
+
+        self.output.seek(SeekFrom::Start(0))?;
+        self.output.write_all(&header)?;
+        self.output.flush()
+    }
+}
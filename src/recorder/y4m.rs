@@ -0,0 +1,60 @@
+// YUV4MPEG2, uncompressed 4:4:4 so no chroma is lost to subsampling
+
+use std::io::Write;
+
+use crate::filter::RgbaImage;
+
+pub(super) struct Y4mWriter<W: Write> {
+    output: W,
+    header_written: bool,
+    frame_rate: (u32, u32),
+    // Y, U and V planes of the frame being written
+    planes: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub(super) fn new(output: W, frame_rate: (u32, u32)) -> Self {
+        Self {
+            output,
+            header_written: false,
+            frame_rate,
+            planes: Vec::new(),
+        }
+    }
+
+    pub(super) fn write_frame(&mut self, image: &RgbaImage) -> std::io::Result<()> {
+        if !self.header_written {
+            // NES pixels are 8:7 on an NTSC TV
+            writeln!(
+                self.output,
+                "YUV4MPEG2 W{} H{} F{}:{} Ip A8:7 C444",
+                image.width(),
+                image.height(),
+                self.frame_rate.0,
+                self.frame_rate.1
+            )?;
+            self.header_written = true;
+        }
+
+        // BT.601, studio range
+        let rgba = image.to_rgba();
+        let size = image.width() * image.height();
+        self.planes.resize(size * 3, 0);
+        for (index, pixel) in rgba.chunks_exact(4).enumerate() {
+            let (red, green, blue) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+            let y = 16.0 + (65.481 * red + 128.553 * green + 24.966 * blue) / 255.0;
+            let u = 128.0 + (-37.797 * red - 74.203 * green + 112.0 * blue) / 255.0;
+            let v = 128.0 + (112.0 * red - 93.786 * green - 18.214 * blue) / 255.0;
+            self.planes[index] = y.round() as u8;
+            self.planes[size + index] = u.round() as u8;
+            self.planes[2 * size + index] = v.round() as u8;
+        }
+
+        self.output.write_all(b"FRAME\n")?;
+        self.output.write_all(&self.planes)
+    }
+
+    pub(super) fn finish(mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
@@ -0,0 +1,143 @@
+// Console timing variants. Everything that differs between them hangs off Region, so the CPU,
+// PPU and APU only ever ask it instead of assuming NTSC.
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    // Famiclone timing: PAL clocks and frame length with an NTSC-like vblank
+    Dendy,
+}
+
+impl Region {
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Ntsc => "NTSC",
+            Region::Pal => "PAL",
+            Region::Dendy => "Dendy",
+        }
+    }
+
+    // Master clock in Hz
+    pub fn master_clock(self) -> u32 {
+        match self {
+            Region::Ntsc => 21_477_272,
+            Region::Pal | Region::Dendy => 26_601_712,
+        }
+    }
+
+    // Master clocks per CPU cycle
+    pub(crate) fn cpu_divider(self) -> u8 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    // Master clocks per PPU dot
+    pub(crate) fn ppu_divider(self) -> u8 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    pub fn scanlines_per_frame(self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    // Dendy pads the extra lines after rendering, so its vblank is as short as NTSC's
+    pub fn vblank_scanline(self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    // Only the NTSC PPU drops the last pre-render dot on odd frames while rendering
+    pub(crate) fn skips_odd_frame_dot(self) -> bool {
+        self == Region::Ntsc
+    }
+
+    // Frames per second, counting the skipped dot on half of the NTSC frames
+    pub fn frame_rate(self) -> f64 {
+        let mut dots = 341.0 * self.scanlines_per_frame() as f64;
+        if self.skips_odd_frame_dot() {
+            dots -= 0.5;
+        }
+        self.master_clock() as f64 / self.ppu_divider() as f64 / dots
+    }
+
+    // The same as an exact fraction, numerator and denominator, for video containers
+    pub(crate) fn frame_rate_ratio(self) -> (u32, u32) {
+        // Counted in half dots so the NTSC skipped dot stays whole
+        let mut half_dots = 2 * 341 * self.scanlines_per_frame() as u64;
+        if self.skips_odd_frame_dot() {
+            half_dots -= 1;
+        }
+        let numerator = 2 * self.master_clock() as u64;
+        let denominator = self.ppu_divider() as u64 * half_dots;
+
+        let (mut a, mut b) = (numerator, denominator);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        ((numerator / a) as u32, (denominator / a) as u32)
+    }
+
+    // APU timing, in CPU cycles. Dendy keeps the NTSC APU tables.
+
+    pub fn noise_periods(self) -> &'static [u16; 16] {
+        match self {
+            Region::Pal => &[
+                4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+            ],
+            _ => &[
+                4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+            ],
+        }
+    }
+
+    pub fn dmc_rates(self) -> &'static [u16; 16] {
+        match self {
+            Region::Pal => &[
+                398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+            ],
+            _ => &[
+                428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+            ],
+        }
+    }
+
+    // Frame counter steps, for the 4-step and 5-step sequences. The 4-step sequence raises
+    // its IRQ over the last three.
+    pub fn frame_counter_steps(self, five_step: bool) -> &'static [u32; 6] {
+        match (self, five_step) {
+            (Region::Pal, false) => &[8313, 16627, 24939, 33252, 33253, 33254],
+            (Region::Pal, true) => &[8313, 16627, 24939, 33253, 41565, 41566],
+            (_, false) => &[7457, 14913, 22371, 29828, 29829, 29830],
+            (_, true) => &[7457, 14913, 22371, 29829, 37281, 37282],
+        }
+    }
+
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+            Region::Dendy => 2,
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Region::Ntsc),
+            1 => Some(Region::Pal),
+            2 => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+}
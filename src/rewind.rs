@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+
+// A full state is kept every this many frames, the rest are deltas against the latest one
+const KEYFRAME_INTERVAL: u32 = 60;
+
+struct RewindEntry {
+    // Compressed full state for keyframes, compressed XOR against the keyframe otherwise
+    data: Vec<u8>,
+    is_keyframe: bool,
+    // CPU cycle count of the state, the timebase reverse debugging seeks by
+    total_cycles: u64,
+    // Controller buttons during the frame that followed, for replaying it
+    input: Option<[u8; 2]>,
+}
+
+// Ring buffer of per-frame save states, trimmed from the oldest end to stay within budget
+pub(crate) struct RewindBuffer {
+    entries: VecDeque<RewindEntry>,
+    memory_budget: usize,
+    memory_used: usize,
+    // Uncompressed state of the newest keyframe
+    keyframe: Vec<u8>,
+    frames_since_keyframe: u32,
+    scratch: Vec<u8>,
+}
+
+impl RewindBuffer {
+    pub(crate) fn new(memory_budget: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            memory_budget,
+            memory_used: 0,
+            keyframe: Vec::new(),
+            frames_since_keyframe: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.memory_used = 0;
+        self.keyframe.clear();
+        self.frames_since_keyframe = 0;
+    }
+
+    pub(crate) fn frames_available(&self) -> usize {
+        // The newest entry is the current frame
+        self.entries.len().saturating_sub(1)
+    }
+
+    pub(crate) fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    // States from a timeline that was left, by loading an earlier state or powering on, are
+    // dropped first
+    pub(crate) fn push(&mut self, state: &[u8], total_cycles: u64) {
+        let stale = self
+            .entries
+            .iter()
+            .rev()
+            .take_while(|e| e.total_cycles >= total_cycles)
+            .count();
+        if stale > 0 {
+            self.drop_newest(stale);
+        }
+
+        let is_keyframe = self.entries.is_empty()
+            || self.frames_since_keyframe + 1 >= KEYFRAME_INTERVAL
+            || state.len() != self.keyframe.len();
+
+        let mut data = Vec::new();
+        if is_keyframe {
+            self.keyframe.clear();
+            self.keyframe.extend_from_slice(state);
+            self.frames_since_keyframe = 0;
+            compress(state, &mut data);
+        } else {
+            self.scratch.clear();
+            self.scratch
+                .extend(state.iter().zip(&self.keyframe).map(|(a, b)| a ^ b));
+            self.frames_since_keyframe += 1;
+            compress(&self.scratch, &mut data);
+        }
+        data.shrink_to_fit();
+
+        self.memory_used += data.len();
+        self.entries.push_back(RewindEntry {
+            data,
+            is_keyframe,
+            total_cycles,
+            input: None,
+        });
+        self.enforce_budget();
+    }
+
+    // The oldest entry is always a keyframe, so it goes together with the deltas that need it.
+    // The newest keyframe group is kept even when it alone exceeds the budget.
+    fn enforce_budget(&mut self) {
+        while self.memory_used > self.memory_budget {
+            let Some(next_keyframe) = self.entries.iter().skip(1).position(|e| e.is_keyframe)
+            else {
+                break;
+            };
+
+            for _ in 0..=next_keyframe {
+                if let Some(entry) = self.entries.pop_front() {
+                    self.memory_used -= entry.data.len();
+                }
+            }
+        }
+    }
+
+    // Drops the newest `frames` entries and decodes the state that is then newest into `output`.
+    // Returns how many frames were actually stepped back.
+    pub(crate) fn rewind(&mut self, frames: usize, output: &mut Vec<u8>) -> usize {
+        let frames = frames.min(self.frames_available());
+        if frames == 0 {
+            return 0;
+        }
+
+        self.drop_newest(frames);
+        self.decode(self.entries.len() - 1, output);
+        frames
+    }
+
+    // Keeps the newest keyframe and its delta count in step with what is left
+    fn drop_newest(&mut self, count: usize) {
+        for _ in 0..count {
+            if let Some(entry) = self.entries.pop_back() {
+                self.memory_used -= entry.data.len();
+            }
+        }
+
+        let Some(keyframe_index) = self.entries.iter().rposition(|e| e.is_keyframe) else {
+            self.clear();
+            return;
+        };
+        decompress(&self.entries[keyframe_index].data, &mut self.keyframe);
+        self.frames_since_keyframe = (self.entries.len() - 1 - keyframe_index) as u32;
+    }
+
+    // Newest entry saved before the given cycle
+    pub(crate) fn find_before(&self, total_cycles: u64) -> Option<usize> {
+        self.entries
+            .iter()
+            .rposition(|e| e.total_cycles < total_cycles)
+    }
+
+    // Recorded at the start of each frame, against the state the frame starts from
+    pub(crate) fn record_input(&mut self, buttons: [u8; 2]) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.input = Some(buttons);
+        }
+    }
+
+    pub(crate) fn input(&self, index: usize) -> Option<[u8; 2]> {
+        self.entries.get(index).and_then(|e| e.input)
+    }
+
+    // Decodes any entry, leaving the buffer untouched
+    pub(crate) fn decode(&self, index: usize, output: &mut Vec<u8>) {
+        let keyframe_index = self.entries.range(..=index).rposition(|e| e.is_keyframe);
+        let keyframe_index = keyframe_index.unwrap_or(0);
+
+        decompress(&self.entries[keyframe_index].data, output);
+        if keyframe_index != index {
+            let mut delta = Vec::new();
+            decompress(&self.entries[index].data, &mut delta);
+            for (byte, key) in output.iter_mut().zip(delta) {
+                *byte ^= key;
+            }
+        }
+    }
+}
+
+// Zero run length encoding, XOR deltas between neighbouring frames are almost all zeros.
+// Stream of: zero run length (LEB128), literal length (LEB128), literal bytes.
+fn compress(input: &[u8], output: &mut Vec<u8>) {
+    output.clear();
+    let mut position = 0;
+
+    while position < input.len() {
+        let zero_start = position;
+        while position < input.len() && input[position] == 0 {
+            position += 1;
+        }
+        let zero_run = position - zero_start;
+
+        // Literals run until the next pair of zeros, single zeros are cheaper inline
+        let literal_start = position;
+        while position < input.len()
+            && !(input[position] == 0 && input.get(position + 1).is_none_or(|&b| b == 0))
+        {
+            position += 1;
+        }
+
+        write_length(output, zero_run);
+        write_length(output, position - literal_start);
+        output.extend_from_slice(&input[literal_start..position]);
+    }
+}
+
+fn decompress(input: &[u8], output: &mut Vec<u8>) {
+    output.clear();
+    let mut position = 0;
+
+    while position < input.len() {
+        let zero_run = read_length(input, &mut position);
+        let literal_length = read_length(input, &mut position);
+        output.resize(output.len() + zero_run, 0);
+
+        let literal_end = (position + literal_length).min(input.len());
+        output.extend_from_slice(&input[position..literal_end]);
+        position = literal_end;
+    }
+}
+
+fn write_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        output.push((length as u8) | 0x80);
+        length >>= 7;
+    }
+    output.push(length as u8);
+}
+
+fn read_length(input: &[u8], position: &mut usize) -> usize {
+    let mut length = 0;
+    let mut shift = 0;
+    while let Some(&byte) = input.get(*position) {
+        *position += 1;
+        length |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    length
+}
@@ -0,0 +1,51 @@
+// Run-ahead hides a game's own input lag: after each frame the machine is saved, run a few
+// frames further with the same input, and the last of those is shown before going back.
+// Games that react a frame or two late to input then appear to react right away.
+
+use crate::nes::Nes;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum RunAheadMode {
+    // Saves, runs ahead and restores the emulator itself
+    #[default]
+    SingleInstance,
+    // Runs ahead on a second emulator brought to the same state each frame. The first never
+    // goes back, so its audio stays continuous.
+    SecondInstance,
+}
+
+#[derive(Default)]
+pub(crate) struct RunAhead {
+    pub(crate) frames: u32,
+    pub(crate) mode: RunAheadMode,
+    // Reused every frame so saving doesn't allocate
+    pub(crate) state: Vec<u8>,
+    pub(crate) instance: Option<Box<Nes>>,
+    // The frame shown in place of the emulated one, while the PPU frame count matches
+    frame_buffer: Box<[u16]>,
+    frame_phase: u8,
+    shown_at: Option<u64>,
+}
+
+impl RunAhead {
+    pub(crate) fn present(&mut self, frame_buffer: &[u16], frame_phase: u8, frame: u64) {
+        if self.frame_buffer.len() != SCREEN_WIDTH * SCREEN_HEIGHT {
+            self.frame_buffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice();
+        }
+        self.frame_buffer.copy_from_slice(frame_buffer);
+        self.frame_phase = frame_phase;
+        self.shown_at = Some(frame);
+    }
+
+    // Anything but a plain frame advance, like stepping or loading a state, shows the
+    // emulated frame again
+    pub(crate) fn invalidate(&mut self) {
+        self.shown_at = None;
+    }
+
+    pub(crate) fn frame(&self, frame: u64) -> Option<(&[u16], u8)> {
+        (self.frames > 0 && self.shown_at == Some(frame))
+            .then_some((&*self.frame_buffer, self.frame_phase))
+    }
+}
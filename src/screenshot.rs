@@ -0,0 +1,86 @@
+use crate::filter::RgbaImage;
+
+// A frame with what is needed to tell later where it came from, written out as PNG with the
+// details in tEXt chunks
+pub struct Screenshot {
+    image: RgbaImage,
+    rom_name: String,
+    rom_crc32: Option<u32>,
+    frame: u64,
+}
+
+impl Screenshot {
+    pub(crate) fn new(image: RgbaImage, rom_crc32: Option<u32>, frame: u64) -> Self {
+        Self {
+            image,
+            rom_name: String::new(),
+            rom_crc32,
+            frame,
+        }
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    // For saving the frame as shown, after filtering
+    pub fn set_image(&mut self, image: RgbaImage) {
+        self.image = image;
+    }
+
+    // The emulator doesn't know file names, frontends fill this in
+    pub fn rom_name(&self) -> &str {
+        &self.rom_name
+    }
+
+    pub fn set_rom_name(&mut self, name: &str) {
+        self.rom_name = name.to_owned();
+    }
+
+    pub fn rom_crc32(&self) -> Option<u32> {
+        self.rom_crc32
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>, &'static str> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(
+            &mut data,
+            self.image.width() as u32,
+            self.image.height() as u32,
+        );
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut text = vec![
+            ("Software", "rustendulator".to_owned()),
+            ("Frame", self.frame.to_string()),
+        ];
+        if !self.rom_name.is_empty() {
+            text.push(("ROM", self.rom_name.clone()));
+        }
+        if let Some(crc32) = self.rom_crc32 {
+            text.push(("ROM CRC32", format!("{:08X}", crc32)));
+        }
+        for (keyword, value) in text {
+            // tEXt is Latin-1, anything else in a ROM name is replaced
+            let value = value
+                .chars()
+                .map(|c| if (c as u32) < 0x100 { c } else { '?' })
+                .collect();
+            encoder
+                .add_text_chunk(keyword.to_owned(), value)
+                .map_err(|_| "Could not encode PNG metadata")?;
+        }
+
+        let mut writer = encoder.write_header().map_err(|_| "Could not encode PNG")?;
+        writer
+            .write_image_data(&self.image.to_rgba())
+            .map_err(|_| "Could not encode PNG")?;
+        writer.finish().map_err(|_| "Could not encode PNG")?;
+        Ok(data)
+    }
+}
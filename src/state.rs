@@ -0,0 +1,195 @@
+// Save state container format
+//
+// Header:  "RNST" magic, format version (u16)
+// Chunks:  tag ([u8; 4]), chunk version (u16), payload length (u32), payload
+//
+// All values are little endian. Each component owns its chunks and versions them on its own,
+// unknown chunks are skipped and missing chunks leave the component untouched, so states keep
+// loading as components grow.
+
+const MAGIC: &[u8; 4] = b"RNST";
+const FORMAT_VERSION: u16 = 1;
+const HEADER_SIZE: usize = 6;
+const CHUNK_HEADER_SIZE: usize = 10;
+
+pub(crate) struct StateWriter<'a> {
+    buffer: &'a mut Vec<u8>,
+}
+
+impl<'a> StateWriter<'a> {
+    // Clears the buffer and writes the header, capacity is kept for reuse
+    pub(crate) fn new(buffer: &'a mut Vec<u8>) -> Self {
+        buffer.clear();
+        buffer.extend_from_slice(MAGIC);
+        buffer.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        Self { buffer }
+    }
+
+    pub(crate) fn chunk(&mut self, tag: &[u8; 4], version: u16, write: impl FnOnce(&mut Self)) {
+        self.buffer.extend_from_slice(tag);
+        self.buffer.extend_from_slice(&version.to_le_bytes());
+        let length_position = self.buffer.len();
+        self.buffer.extend_from_slice(&[0; 4]);
+
+        write(self);
+
+        let length = (self.buffer.len() - length_position - 4) as u32;
+        self.buffer[length_position..length_position + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    pub(crate) fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub(crate) fn write_bool(&mut self, value: bool) {
+        self.buffer.push(value as u8);
+    }
+
+    pub(crate) fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    // Large buffers in one go rather than a call per value
+    pub(crate) fn write_u16_slice(&mut self, values: &[u16]) {
+        self.buffer.reserve(values.len() * 2);
+        for value in values {
+            self.buffer.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+// Validates the chunk layout up front, then hands out chunks by tag without allocating
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Result<Self, &'static str> {
+        if data.len() < HEADER_SIZE || &data[0..4] != MAGIC {
+            return Err("Not a save state");
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version > FORMAT_VERSION {
+            return Err("Save state format is newer than this build");
+        }
+
+        let mut position = HEADER_SIZE;
+        while position < data.len() {
+            if data.len() - position < CHUNK_HEADER_SIZE {
+                return Err("Save state truncated");
+            }
+            let length = Self::payload_length(data, position);
+            if data.len() - position - CHUNK_HEADER_SIZE < length {
+                return Err("Save state truncated");
+            }
+            position += CHUNK_HEADER_SIZE + length;
+        }
+
+        Ok(Self { data })
+    }
+
+    fn payload_length(data: &[u8], position: usize) -> usize {
+        let bytes = &data[position + 6..position + 10];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+    }
+
+    pub(crate) fn chunk(&self, tag: &[u8; 4]) -> Option<ChunkReader<'a>> {
+        let mut position = HEADER_SIZE;
+        while position < self.data.len() {
+            let length = Self::payload_length(self.data, position);
+            let start = position + CHUNK_HEADER_SIZE;
+
+            if &self.data[position..position + 4] == tag {
+                let version =
+                    u16::from_le_bytes([self.data[position + 4], self.data[position + 5]]);
+                return Some(ChunkReader {
+                    version,
+                    data: &self.data[start..start + length],
+                    position: 0,
+                });
+            }
+            position = start + length;
+        }
+        None
+    }
+}
+
+pub(crate) struct ChunkReader<'a> {
+    version: u16,
+    data: &'a [u8],
+    position: usize,
+}
+
+impl ChunkReader<'_> {
+    // Rejects chunks written by a newer build, older versions are up to the component
+    pub(crate) fn expect_version(&self, supported: u16) -> Result<(), &'static str> {
+        if self.version > supported {
+            Err("Save state chunk version is newer than this build")
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn version(&self) -> u16 {
+        self.version
+    }
+
+    fn take(&mut self, length: usize) -> Result<&[u8], &'static str> {
+        if self.data.len() - self.position < length {
+            return Err("Save state chunk truncated");
+        }
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, &'static str> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn read_bool(&mut self) -> Result<bool, &'static str> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, &'static str> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, &'static str> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, &'static str> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn read_bytes(&mut self, destination: &mut [u8]) -> Result<(), &'static str> {
+        destination.copy_from_slice(self.take(destination.len())?);
+        Ok(())
+    }
+
+    pub(crate) fn read_u16_slice(&mut self, destination: &mut [u16]) -> Result<(), &'static str> {
+        let bytes = self.take(destination.len() * 2)?;
+        for (value, bytes) in destination.iter_mut().zip(bytes.chunks_exact(2)) {
+            *value = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+// NROM with the program at $8000 and every vector pointing at it
+pub fn nrom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut prg = vec![0xEA; 0x8000];
+    prg[..program.len()].copy_from_slice(program);
+    for vector in [0x7FFA, 0x7FFC, 0x7FFE] {
+        prg[vector] = 0x00;
+        prg[vector + 1] = 0x80;
+    }
+    rom.extend(prg);
+    rom.extend(vec![0; 0x2000]);
+    rom
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use rustendulator_core::{GdbStub, Nes, RunMode};
+
+const PROGRAM: [u8; 8] = [
+    0xA9, 0x05, // $8000 LDA #$05
+    0x8D, 0x00, 0x03, // $8002 STA $0300
+    0x4C, 0x05, 0x80, // $8005 JMP $8005
+];
+
+struct Client {
+    stream: TcpStream,
+    input: Vec<u8>,
+}
+
+impl Client {
+    fn send(&mut self, body: &str) {
+        let checksum = body.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${}#{:02x}", body, checksum);
+        self.stream.write_all(packet.as_bytes()).unwrap();
+    }
+
+    // Polls the stub until a whole reply packet came back, acks are skipped
+    fn reply(&mut self, stub: &mut GdbStub, nes: &mut Nes) -> String {
+        for _ in 0..500 {
+            stub.poll(nes);
+            let mut buffer = [0; 1024];
+            match self.stream.read(&mut buffer) {
+                Ok(0) => panic!("stub closed the connection"),
+                Ok(length) => self.input.extend_from_slice(&buffer[..length]),
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(error) => panic!("{}", error),
+            }
+
+            let start = self.input.iter().position(|&b| b == b'$');
+            let end = self.input.iter().position(|&b| b == b'#');
+            if let (Some(start), Some(end)) = (start, end)
+                && self.input.len() >= end + 3
+            {
+                let body = String::from_utf8_lossy(&self.input[start + 1..end]).into_owned();
+                self.input.drain(..end + 3);
+                return body;
+            }
+        }
+        panic!("no reply");
+    }
+
+    fn request(&mut self, body: &str, stub: &mut GdbStub, nes: &mut Nes) -> String {
+        self.send(body);
+        self.reply(stub, nes)
+    }
+}
+
+fn program_counter(registers: &str) -> &str {
+    &registers[10..14]
+}
+
+#[test]
+fn scripted_session_over_loopback() {
+    let mut nes = Nes::new();
+    nes.insert_cartridge(&common::nrom(&PROGRAM)).unwrap();
+    nes.power_on();
+    // Through the reset sequence to the first opcode fetch
+    nes.step_instruction();
+
+    let mut stub = GdbStub::listen("127.0.0.1:0").unwrap();
+    let address = stub.local_address().unwrap();
+    let stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_millis(2)))
+        .unwrap();
+    let mut client = Client {
+        stream,
+        input: Vec::new(),
+    };
+    stub.poll(&mut nes);
+    assert!(stub.is_connected());
+
+    let supported = client.request("qSupported:swbreak+;hwbreak+", &mut stub, &mut nes);
+    assert!(supported.contains("qXfer:features:read+"));
+    assert_eq!(client.request("?", &mut stub, &mut nes), "T05");
+    assert!(nes.get_run_mode() == RunMode::Paused);
+
+    let registers = client.request("g", &mut stub, &mut nes);
+    assert_eq!(registers.len(), 14);
+    assert_eq!(program_counter(&registers), "0080");
+    assert_eq!(client.request("m8000,5", &mut stub, &mut nes), "a9058d0003");
+
+    // A breakpoint on the current instruction doesn't stop a step from running it
+    assert_eq!(client.request("Z0,8000,1", &mut stub, &mut nes), "OK");
+    assert_eq!(client.request("s", &mut stub, &mut nes), "T05");
+    let registers = client.request("g", &mut stub, &mut nes);
+    assert_eq!(&registers[..2], "05");
+    assert_eq!(program_counter(&registers), "0280");
+
+    // Continue replies once the frontend's frames reach the breakpoint
+    assert_eq!(client.request("Z0,8005,1", &mut stub, &mut nes), "OK");
+    client.send("c");
+    stub.poll(&mut nes);
+    assert!(nes.get_run_mode() == RunMode::Running);
+    while nes.get_run_mode() == RunMode::Running {
+        nes.run_frame();
+    }
+    assert_eq!(client.reply(&mut stub, &mut nes), "T05swbreak:;");
+    let registers = client.request("g", &mut stub, &mut nes);
+    assert_eq!(program_counter(&registers), "0580");
+    assert_eq!(client.request("m0300,1", &mut stub, &mut nes), "05");
+
+    // Stepping off the breakpoint it stopped at runs the JMP back onto it
+    assert_eq!(client.request("s", &mut stub, &mut nes), "T05");
+    assert_eq!(nes.cpu_registers().program_counter(), 0x8005);
+
+    client.send("k");
+    for _ in 0..100 {
+        stub.poll(&mut nes);
+        if !stub.is_connected() {
+            break;
+        }
+    }
+    assert!(!stub.is_connected());
+    assert!(nes.breakpoints().is_empty());
+}
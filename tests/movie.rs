@@ -0,0 +1,21 @@
+mod common;
+
+use rustendulator_core::{Movie, Nes};
+
+#[test]
+fn movies_only_play_on_the_rom_they_were_recorded_with() {
+    let mut nes = Nes::new();
+    nes.insert_cartridge(&common::nrom(&[])).unwrap();
+    nes.record_movie(false);
+    nes.run_frame();
+    let text = nes.stop_movie().unwrap().to_fm2();
+    assert!(text.contains("romChecksum base64:"));
+
+    let movie = Movie::from_fm2(&text).unwrap();
+    assert!(nes.play_movie(movie).is_ok());
+
+    let mut other = Nes::new();
+    other.insert_cartridge(&common::nrom(&[0xE8])).unwrap();
+    let movie = Movie::from_fm2(&text).unwrap();
+    assert!(other.play_movie(movie).is_err());
+}
@@ -0,0 +1,31 @@
+use rustendulator_core::{NTSC_OUTPUT_WIDTH, NtscFilter, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Every color index across each row, with the emphasis bits stepping down the screen
+fn test_frame() -> Vec<u16> {
+    let mut pixels = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+    for y in 0..SCREEN_HEIGHT {
+        for x in 0..SCREEN_WIDTH {
+            pixels.push((x / 4) as u16 | ((y / 30) as u16) << 6);
+        }
+    }
+    pixels
+}
+
+// Golden values for the default settings, only update them for an intended change to the
+// signal model
+#[test]
+fn output_matches_golden_hashes() {
+    let pixels = test_frame();
+    let mut filter = NtscFilter::default();
+
+    let hashes: Vec<u32> = (0..3)
+        .map(|phase| {
+            let image = filter.apply(&pixels, phase * 4);
+            assert_eq!(image.width(), NTSC_OUTPUT_WIDTH);
+            assert_eq!(image.height(), SCREEN_HEIGHT);
+            crc32fast::hash(&image.to_rgba())
+        })
+        .collect();
+
+    assert_eq!(hashes, [0xA00F_A001, 0x8353_7D57, 0x8AD8_4937]);
+}
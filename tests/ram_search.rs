@@ -0,0 +1,24 @@
+use rustendulator_core::{Nes, RamSearch, ValueSize};
+
+#[test]
+fn words_never_overhang_their_range() {
+    let nes = Nes::new();
+
+    let mut search = RamSearch::new(ValueSize::Word, false, true);
+    search.reset(&nes);
+    assert!(!search.candidates().contains(&0x07FF));
+    assert!(!search.candidates().contains(&0x7FFF));
+
+    let mut search = RamSearch::new(ValueSize::Byte, false, true);
+    search.reset(&nes);
+    assert!(search.candidates().contains(&0x07FF));
+    search.set_format(&nes, ValueSize::Word, false);
+    assert!(!search.candidates().contains(&0x07FF));
+    assert!(!search.candidates().contains(&0x7FFF));
+    assert_eq!(search.candidates().len(), 0x0800 + 0x2000 - 2);
+    assert!(
+        search
+            .previous_value(search.candidates().len() - 1)
+            .is_some()
+    );
+}
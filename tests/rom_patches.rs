@@ -0,0 +1,21 @@
+mod common;
+
+use rustendulator_core::Nes;
+
+#[test]
+fn save_states_carry_prg_rom_patches() {
+    let mut nes = Nes::new();
+    nes.insert_cartridge(&common::nrom(&[])).unwrap();
+    nes.power_on();
+
+    let unpatched = nes.save_state();
+    nes.poke(0x8000, 0x12);
+    let patched = nes.save_state();
+    nes.poke(0x8000, 0x34);
+
+    nes.load_state(&patched).unwrap();
+    assert_eq!(nes.peek(0x8000), 0x12);
+
+    nes.load_state(&unpatched).unwrap();
+    assert_eq!(nes.peek(0x8000), 0xEA);
+}
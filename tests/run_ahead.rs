@@ -0,0 +1,51 @@
+mod common;
+
+use rustendulator_core::{BreakpointKind, Nes, RunAheadMode, RunMode};
+
+// Counts $00 through to $01 = $14, about 1.4 frames, then stores $01 to $0300
+const PROGRAM: [u8; 18] = [
+    0xE6, 0x00, // $8000 INC $00
+    0xD0, 0xFC, // $8002 BNE $8000
+    0xE6, 0x01, // $8004 INC $01
+    0xA5, 0x01, // $8006 LDA $01
+    0xC9, 0x14, // $8008 CMP #$14
+    0xD0, 0xF4, // $800A BNE $8000
+    0x8D, 0x00, 0x03, // $800C STA $0300
+    0x4C, 0x0F, 0x80, // $800F JMP $800F
+];
+
+fn run_to_watchpoint(frames: u32, mode: RunAheadMode) -> (u16, u8, u8) {
+    let mut nes = Nes::new();
+    nes.insert_cartridge(&common::nrom(&PROGRAM)).unwrap();
+    nes.set_run_ahead(frames, mode);
+    nes.add_breakpoint(BreakpointKind::Write, 0x0300, 0x0300, None)
+        .unwrap();
+    nes.power_on();
+    nes.set_run_mode(RunMode::Running);
+
+    for _ in 0..10 {
+        nes.run_frame();
+        if nes.get_run_mode() != RunMode::Running {
+            break;
+        }
+    }
+    assert!(nes.take_break_hit().is_some(), "watchpoint never hit");
+    (
+        nes.cpu_registers().program_counter(),
+        nes.peek(0x0001),
+        nes.peek(0x0300),
+    )
+}
+
+#[test]
+fn watchpoint_ignores_run_ahead_frames() {
+    assert_eq!(
+        run_to_watchpoint(0, RunAheadMode::SingleInstance),
+        (0x800F, 0x14, 0x14)
+    );
+    for frames in 1..=2 {
+        for mode in [RunAheadMode::SingleInstance, RunAheadMode::SecondInstance] {
+            assert_eq!(run_to_watchpoint(frames, mode), (0x800F, 0x14, 0x14));
+        }
+    }
+}
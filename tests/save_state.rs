@@ -0,0 +1,17 @@
+mod common;
+
+use rustendulator_core::Nes;
+
+#[test]
+fn states_for_another_rom_of_the_same_size_are_rejected() {
+    let mut nes = Nes::new();
+    nes.insert_cartridge(&common::nrom(&[])).unwrap();
+    nes.power_on();
+    let state = nes.save_state();
+
+    let mut other = Nes::new();
+    other.insert_cartridge(&common::nrom(&[0xE8])).unwrap();
+    other.power_on();
+    assert!(other.load_state(&state).is_err());
+    assert!(nes.load_state(&state).is_ok());
+}